@@ -24,7 +24,8 @@ impl MicroGLUT for Demo {
                 gl,
                 include_str!("minimal.vert"),
                 include_str!("minimal.frag"),
-            );
+            )
+            .unwrap();
             gl.use_program(Some(program));
 
             let vao = gl.create_vertex_array().unwrap();
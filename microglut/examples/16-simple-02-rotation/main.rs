@@ -32,7 +32,8 @@ impl MicroGLUT for Demo {
                 gl,
                 include_str!("rotation.vert"),
                 include_str!("rotation.frag"),
-            );
+            )
+            .unwrap();
             gl.use_program(Some(program));
 
             let vao = gl.create_vertex_array().unwrap();
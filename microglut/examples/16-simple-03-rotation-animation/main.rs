@@ -27,7 +27,8 @@ impl MicroGLUT for Demo {
                 gl,
                 include_str!("rotationanimation.vert"),
                 include_str!("rotationanimation.frag"),
-            );
+            )
+            .unwrap();
             gl.use_program(Some(program));
 
             let vao = gl.create_vertex_array().unwrap();
@@ -25,7 +25,8 @@ impl MicroGLUT for Demo {
                 gl,
                 include_str!("colored.vert"),
                 include_str!("colored.frag"),
-            );
+            )
+            .unwrap();
             gl.use_program(Some(program));
 
             let vao = gl.create_vertex_array().unwrap();
@@ -25,7 +25,8 @@ impl MicroGLUT for Demo {
         unsafe {
             gl.clear_color(0.2, 0.2, 0.5, 0.0);
             gl.enable(DEPTH_TEST);
-            let program = load_shaders(gl, include_str!("phong.vert"), include_str!("phong.frag"));
+            let program =
+                load_shaders(gl, include_str!("phong.vert"), include_str!("phong.frag")).unwrap();
             gl.use_program(Some(program));
 
             let vao = gl.create_vertex_array().unwrap();
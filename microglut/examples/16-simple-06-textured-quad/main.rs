@@ -35,7 +35,8 @@ impl MicroGLUT for Demo {
                 gl,
                 include_str!("textured.vert"),
                 include_str!("textured.frag"),
-            );
+            )
+            .unwrap();
             gl.use_program(Some(program));
 
             let vao = gl.create_vertex_array().unwrap();
@@ -57,7 +58,7 @@ impl MicroGLUT for Demo {
             gl.vertex_attrib_pointer_f32(texcoord_loc, 2, FLOAT, false, 0, 0);
             gl.enable_vertex_attrib_array(texcoord_loc);
 
-            let _maskros = Texture::load(gl, include_bytes!("maskros512.tga"), false);
+            let _maskros = Texture::load(gl, include_bytes!("maskros512.tga"), false).unwrap();
 
             Demo { vao }
         }
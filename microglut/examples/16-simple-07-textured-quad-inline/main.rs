@@ -42,7 +42,8 @@ impl MicroGLUT for Demo {
                 gl,
                 include_str!("inlinetexture.vert"),
                 include_str!("inlinetexture.frag"),
-            );
+            )
+            .unwrap();
             gl.use_program(Some(program));
 
             let vao = gl.create_vertex_array().unwrap();
@@ -83,7 +83,8 @@ impl MicroGLUT for Demo {
                 gl,
                 include_str!("colorcube.vert"),
                 include_str!("colorcube.frag"),
-            );
+            )
+            .unwrap();
             gl.use_program(Some(program));
 
             let vao = gl.create_vertex_array().unwrap();
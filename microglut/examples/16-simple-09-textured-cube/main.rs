@@ -116,7 +116,8 @@ impl MicroGLUT for Demo {
                 gl,
                 include_str!("texcube.vert"),
                 include_str!("texcube.frag"),
-            );
+            )
+            .unwrap();
             gl.use_program(Some(program));
 
             let vao = gl.create_vertex_array().unwrap();
@@ -157,7 +158,7 @@ impl MicroGLUT for Demo {
             );
 
             gl.uniform_1_i32(gl.get_uniform_location(program, "tex").as_ref(), 0);
-            let _maskros = Texture::load(gl, include_bytes!("maskros512.tga"), false);
+            let _maskros = Texture::load(gl, include_bytes!("maskros512.tga"), false).unwrap();
 
             Demo {
                 program,
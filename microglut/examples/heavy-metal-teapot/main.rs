@@ -31,11 +31,12 @@ impl MicroGLUT for Demo {
             let program = LoadShaders::new(include_str!("minimal.vs"), include_str!("minimal.fs"))
                 .geometry(include_str!("minimal.gs"))
                 .tesselation(include_str!("minimal.tcs"), include_str!("minimal.tes"))
-                .compile(gl);
+                .compile(gl)
+                .unwrap();
             gl.use_program(Some(program));
 
             gl.active_texture(TEXTURE0);
-            let _ = Texture::load(gl, include_bytes!("spots.tga"), false);
+            let _ = Texture::load(gl, include_bytes!("spots.tga"), false).unwrap();
             gl.uniform_1_i32(gl.get_uniform_location(program, "tex").as_ref(), 0);
             // gl.polygon_mode(FRONT_AND_BACK, LINE);
 
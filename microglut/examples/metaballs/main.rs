@@ -50,7 +50,8 @@ impl MicroGLUT for Metaballs {
                 gl,
                 include_str!("metaballs99.vert"),
                 include_str!("metaballs99.frag"),
-            );
+            )
+            .unwrap();
             gl.use_program(Some(program));
 
             let quad = Model::load_raw_data(
@@ -36,7 +36,8 @@ impl MicroGLUT for TransparentTeapot {
         ];
 
         unsafe {
-            let program = load_shaders(gl, include_str!("tex.vert"), include_str!("tex.frag"));
+            let program =
+                load_shaders(gl, include_str!("tex.vert"), include_str!("tex.frag")).unwrap();
             let teapot = Model::load_obj_data(gl, include_bytes!("teapotmini.obj"), None, None);
             gl.use_program(Some(program));
 
@@ -23,7 +23,8 @@ impl MicroGLUT for Demo {
 
         let program = LoadShaders::new(include_str!("minimal.vs"), include_str!("minimal.fs"))
             .tesselation(include_str!("minimal.tcs"), include_str!("minimal.tes"))
-            .compile(gl);
+            .compile(gl)
+            .unwrap();
 
         print_error(gl, "init (load shaders)").unwrap();
 
@@ -20,16 +20,20 @@ impl MicroGLUT for Demo {
         let programs = [
             LoadShaders::new(include_str!("minimal.vert"), include_str!("minimal.frag"))
                 .geometry(include_str!("passthrough.gs"))
-                .compile(gl),
+                .compile(gl)
+                .unwrap(),
             LoadShaders::new(include_str!("minimal.vert"), include_str!("minimal.frag"))
                 .geometry(include_str!("flatshading.gs"))
-                .compile(gl),
+                .compile(gl)
+                .unwrap(),
             LoadShaders::new(include_str!("minimal.vert"), include_str!("minimal.frag"))
                 .geometry(include_str!("cracking.gs"))
-                .compile(gl),
+                .compile(gl)
+                .unwrap(),
             LoadShaders::new(include_str!("minimal.vert"), include_str!("black.frag"))
                 .geometry(include_str!("wireframe.gs"))
-                .compile(gl),
+                .compile(gl)
+                .unwrap(),
         ];
         let model = Model::load_obj_data(gl, include_bytes!("bunnyplus.obj"), None, None);
         // TODO: CenterModel
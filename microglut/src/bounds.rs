@@ -0,0 +1,210 @@
+use glam::{Mat4, Vec3};
+
+/// Axis-aligned bounding box. The empty box has `min` above `max` so that extending or
+/// uniting it with anything gives the other thing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb {
+        min: Vec3::INFINITY,
+        max: Vec3::NEG_INFINITY,
+    };
+
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Aabb { min, max }
+    }
+
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        points
+            .into_iter()
+            .fold(Self::EMPTY, |aabb, point| aabb.extend(point))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn extend(self, point: Vec3) -> Self {
+        Aabb {
+            min: self.min.min(point),
+            max: self.max.max(point),
+        }
+    }
+
+    pub fn union(self, other: Aabb) -> Self {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// Grows every side by `margin`.
+    pub fn expand(self, margin: Vec3) -> Self {
+        Aabb {
+            min: self.min - margin,
+            max: self.max + margin,
+        }
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.cmple(other.max).all() && self.max.cmpge(other.min).all()
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            min,
+            Vec3::new(max.x, min.y, min.z),
+            Vec3::new(min.x, max.y, min.z),
+            Vec3::new(max.x, max.y, min.z),
+            Vec3::new(min.x, min.y, max.z),
+            Vec3::new(max.x, min.y, max.z),
+            Vec3::new(min.x, max.y, max.z),
+            max,
+        ]
+    }
+
+    /// The box around this box after an affine transform.
+    pub fn transformed(&self, transform: Mat4) -> Self {
+        if self.is_empty() {
+            return *self;
+        }
+        Self::from_points(
+            self.corners()
+                .map(|corner| transform.transform_point3(corner)),
+        )
+    }
+
+    /// The smallest sphere around the box, which is usually larger than one fitted to the
+    /// geometry inside it.
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        if self.is_empty() {
+            return BoundingSphere::EMPTY;
+        }
+        BoundingSphere {
+            center: self.center(),
+            radius: self.size().length() * 0.5,
+        }
+    }
+}
+
+impl FromIterator<Aabb> for Aabb {
+    fn from_iter<T: IntoIterator<Item = Aabb>>(iter: T) -> Self {
+        iter.into_iter().fold(Self::EMPTY, Aabb::union)
+    }
+}
+
+/// The empty sphere has a negative radius.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl Default for BoundingSphere {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+impl BoundingSphere {
+    pub const EMPTY: BoundingSphere = BoundingSphere {
+        center: Vec3::ZERO,
+        radius: -1.0,
+    };
+
+    pub fn new(center: Vec3, radius: f32) -> Self {
+        BoundingSphere { center, radius }
+    }
+
+    /// A sphere centred on the bounding box of the points, which is cheap and
+    /// deterministic but not the smallest possible.
+    pub fn from_points(points: &[Vec3]) -> Self {
+        let aabb = Aabb::from_points(points.iter().copied());
+        if aabb.is_empty() {
+            return Self::EMPTY;
+        }
+        let center = aabb.center();
+        let radius = points
+            .iter()
+            .map(|point| point.distance_squared(center))
+            .fold(0.0, f32::max)
+            .sqrt();
+        BoundingSphere { center, radius }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.radius < 0.0
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.distance_squared(self.center) <= self.radius * self.radius
+    }
+
+    /// The smallest sphere around both spheres.
+    pub fn union(self, other: BoundingSphere) -> Self {
+        if self.is_empty() {
+            return other;
+        }
+        if other.is_empty() {
+            return self;
+        }
+        let offset = other.center - self.center;
+        let distance = offset.length();
+        if distance + other.radius <= self.radius {
+            return self;
+        }
+        if distance + self.radius <= other.radius {
+            return other;
+        }
+        let radius = (distance + self.radius + other.radius) * 0.5;
+        BoundingSphere {
+            center: self.center + offset * ((radius - self.radius) / distance),
+            radius,
+        }
+    }
+
+    /// The sphere around this sphere after an affine transform. Non-uniform scaling makes
+    /// it larger than it needs to be.
+    pub fn transformed(&self, transform: Mat4) -> Self {
+        if self.is_empty() {
+            return *self;
+        }
+        let max_scale = [transform.x_axis, transform.y_axis, transform.z_axis]
+            .map(|axis| axis.truncate().length())
+            .into_iter()
+            .fold(0.0, f32::max);
+        BoundingSphere {
+            center: transform.transform_point3(self.center),
+            radius: self.radius * max_scale,
+        }
+    }
+}
+
+impl FromIterator<BoundingSphere> for BoundingSphere {
+    fn from_iter<T: IntoIterator<Item = BoundingSphere>>(iter: T) -> Self {
+        iter.into_iter().fold(Self::EMPTY, BoundingSphere::union)
+    }
+}
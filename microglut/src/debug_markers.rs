@@ -0,0 +1,80 @@
+use glow::{
+    Context, HasContext as _, NativeBuffer, NativeFramebuffer, NativeProgram, NativeQuery,
+    NativeRenderbuffer, NativeShader, NativeTexture, NativeVertexArray, BUFFER,
+    DEBUG_SOURCE_APPLICATION, FRAMEBUFFER, PROGRAM, QUERY, RENDERBUFFER, SHADER, TEXTURE,
+    VERTEX_ARRAY,
+};
+
+use crate::resources;
+
+/// A GL object that can be given a name with `glObjectLabel`.
+pub trait ObjectLabel: Copy {
+    /// The `identifier` argument of `glObjectLabel`, e.g. `GL_TEXTURE`.
+    const IDENTIFIER: u32;
+
+    fn name(self) -> u32;
+}
+
+macro_rules! impl_object_label {
+    ($($ty:ty => $identifier:expr),* $(,)?) => {
+        $(
+            impl ObjectLabel for $ty {
+                const IDENTIFIER: u32 = $identifier;
+
+                fn name(self) -> u32 {
+                    self.0.get()
+                }
+            }
+        )*
+    };
+}
+
+impl_object_label!(
+    NativeBuffer => BUFFER,
+    NativeFramebuffer => FRAMEBUFFER,
+    NativeProgram => PROGRAM,
+    NativeQuery => QUERY,
+    NativeRenderbuffer => RENDERBUFFER,
+    NativeShader => SHADER,
+    NativeTexture => TEXTURE,
+    NativeVertexArray => VERTEX_ARRAY,
+);
+
+/// Names `object` in debug messages and capture tools like RenderDoc. Does nothing on
+/// contexts without `KHR_debug`, like the other functions in this module.
+pub fn label_object<T: ObjectLabel>(gl: &Context, object: T, label: &str) {
+    resources::set_label(T::IDENTIFIER, object.name(), label);
+    if gl.supports_debug() {
+        unsafe { gl.object_label(T::IDENTIFIER, object.name(), Some(label)) };
+    }
+}
+
+/// Opens a named group of GL calls. Groups nest and must be closed in reverse order with
+/// [pop_debug_group].
+pub fn push_debug_group(gl: &Context, name: &str) {
+    if gl.supports_debug() {
+        unsafe { gl.push_debug_group(DEBUG_SOURCE_APPLICATION, 0, name) };
+    }
+}
+
+pub fn pop_debug_group(gl: &Context) {
+    if gl.supports_debug() {
+        unsafe { gl.pop_debug_group() };
+    }
+}
+
+/// Opens a named group of GL calls that is closed when the returned guard is dropped.
+pub fn debug_group<'a>(gl: &'a Context, name: &str) -> DebugGroup<'a> {
+    push_debug_group(gl, name);
+    DebugGroup { gl }
+}
+
+pub struct DebugGroup<'a> {
+    gl: &'a Context,
+}
+
+impl Drop for DebugGroup<'_> {
+    fn drop(&mut self) {
+        pop_debug_group(self.gl);
+    }
+}
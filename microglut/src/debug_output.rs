@@ -0,0 +1,295 @@
+use std::{collections::HashSet, fmt, sync::Mutex};
+
+use glow::{
+    Context, HasContext as _, DEBUG_OUTPUT, DEBUG_OUTPUT_SYNCHRONOUS, DEBUG_SEVERITY_HIGH,
+    DEBUG_SEVERITY_LOW, DEBUG_SEVERITY_MEDIUM, DEBUG_SEVERITY_NOTIFICATION, DEBUG_SOURCE_API,
+    DEBUG_SOURCE_APPLICATION, DEBUG_SOURCE_SHADER_COMPILER, DEBUG_SOURCE_THIRD_PARTY,
+    DEBUG_SOURCE_WINDOW_SYSTEM, DEBUG_TYPE_DEPRECATED_BEHAVIOR, DEBUG_TYPE_ERROR,
+    DEBUG_TYPE_MARKER, DEBUG_TYPE_PERFORMANCE, DEBUG_TYPE_POP_GROUP, DEBUG_TYPE_PORTABILITY,
+    DEBUG_TYPE_PUSH_GROUP, DEBUG_TYPE_UNDEFINED_BEHAVIOR, DONT_CARE,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DebugSource {
+    Api,
+    WindowSystem,
+    ShaderCompiler,
+    ThirdParty,
+    Application,
+    Other,
+}
+
+impl DebugSource {
+    pub fn from_gl(source: u32) -> Self {
+        match source {
+            DEBUG_SOURCE_API => DebugSource::Api,
+            DEBUG_SOURCE_WINDOW_SYSTEM => DebugSource::WindowSystem,
+            DEBUG_SOURCE_SHADER_COMPILER => DebugSource::ShaderCompiler,
+            DEBUG_SOURCE_THIRD_PARTY => DebugSource::ThirdParty,
+            DEBUG_SOURCE_APPLICATION => DebugSource::Application,
+            _ => DebugSource::Other,
+        }
+    }
+}
+
+impl fmt::Display for DebugSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DebugSource::Api => "API",
+            DebugSource::WindowSystem => "window system",
+            DebugSource::ShaderCompiler => "shader compiler",
+            DebugSource::ThirdParty => "third party",
+            DebugSource::Application => "application",
+            DebugSource::Other => "other",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DebugType {
+    Error,
+    DeprecatedBehavior,
+    UndefinedBehavior,
+    Portability,
+    Performance,
+    Marker,
+    PushGroup,
+    PopGroup,
+    Other,
+}
+
+impl DebugType {
+    pub fn from_gl(ty: u32) -> Self {
+        match ty {
+            DEBUG_TYPE_ERROR => DebugType::Error,
+            DEBUG_TYPE_DEPRECATED_BEHAVIOR => DebugType::DeprecatedBehavior,
+            DEBUG_TYPE_UNDEFINED_BEHAVIOR => DebugType::UndefinedBehavior,
+            DEBUG_TYPE_PORTABILITY => DebugType::Portability,
+            DEBUG_TYPE_PERFORMANCE => DebugType::Performance,
+            DEBUG_TYPE_MARKER => DebugType::Marker,
+            DEBUG_TYPE_PUSH_GROUP => DebugType::PushGroup,
+            DEBUG_TYPE_POP_GROUP => DebugType::PopGroup,
+            _ => DebugType::Other,
+        }
+    }
+}
+
+impl fmt::Display for DebugType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DebugType::Error => "error",
+            DebugType::DeprecatedBehavior => "deprecated behavior",
+            DebugType::UndefinedBehavior => "undefined behavior",
+            DebugType::Portability => "portability",
+            DebugType::Performance => "performance",
+            DebugType::Marker => "marker",
+            DebugType::PushGroup => "push group",
+            DebugType::PopGroup => "pop group",
+            DebugType::Other => "other",
+        })
+    }
+}
+
+/// Ordered from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DebugSeverity {
+    Notification,
+    Low,
+    Medium,
+    High,
+}
+
+impl DebugSeverity {
+    pub fn from_gl(severity: u32) -> Self {
+        match severity {
+            DEBUG_SEVERITY_HIGH => DebugSeverity::High,
+            DEBUG_SEVERITY_MEDIUM => DebugSeverity::Medium,
+            DEBUG_SEVERITY_LOW => DebugSeverity::Low,
+            _ => DebugSeverity::Notification,
+        }
+    }
+}
+
+impl fmt::Display for DebugSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DebugSeverity::Notification => "notification",
+            DebugSeverity::Low => "low",
+            DebugSeverity::Medium => "medium",
+            DebugSeverity::High => "high",
+        })
+    }
+}
+
+/// A message from the GL debug output, decoded from the `glDebugMessageCallback` arguments.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DebugMessage {
+    pub source: DebugSource,
+    pub ty: DebugType,
+    pub id: u32,
+    pub severity: DebugSeverity,
+    pub message: String,
+}
+
+impl DebugMessage {
+    pub fn from_gl(source: u32, ty: u32, id: u32, severity: u32, message: &str) -> Self {
+        DebugMessage {
+            source: DebugSource::from_gl(source),
+            ty: DebugType::from_gl(ty),
+            id,
+            severity: DebugSeverity::from_gl(severity),
+            message: message.trim_end().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for DebugMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "GL {} {} (id {}, {} severity): {}",
+            self.source, self.ty, self.id, self.severity, self.message
+        )
+    }
+}
+
+/// Reports a message through the `tracing` or `log` crate if one of those features is
+/// enabled, preferring `tracing`, and to stderr otherwise. High severity maps to the error
+/// level, medium to warn, low to info and notifications to debug.
+pub fn log_debug_message(message: &DebugMessage) {
+    #[cfg(feature = "tracing")]
+    match message.severity {
+        DebugSeverity::High => tracing::error!(target: "microglut::gl", "{message}"),
+        DebugSeverity::Medium => tracing::warn!(target: "microglut::gl", "{message}"),
+        DebugSeverity::Low => tracing::info!(target: "microglut::gl", "{message}"),
+        DebugSeverity::Notification => tracing::debug!(target: "microglut::gl", "{message}"),
+    }
+
+    #[cfg(all(feature = "log", not(feature = "tracing")))]
+    {
+        let level = match message.severity {
+            DebugSeverity::High => log::Level::Error,
+            DebugSeverity::Medium => log::Level::Warn,
+            DebugSeverity::Low => log::Level::Info,
+            DebugSeverity::Notification => log::Level::Debug,
+        };
+        log::log!(target: "microglut::gl", level, "{message}");
+    }
+
+    #[cfg(not(any(feature = "log", feature = "tracing")))]
+    eprintln!("{message}");
+}
+
+pub type DebugMessageHandler = dyn Fn(&DebugMessage) + Send + Sync;
+
+/// Configuration of the GL debug output, installed with
+/// [StartBuilder::debug_output](crate::StartBuilder::debug_output).
+///
+/// By default messages of low severity and above are passed to [log_debug_message], debug
+/// group markers are ignored and every distinct message is only reported once.
+pub struct DebugOutput {
+    min_severity: DebugSeverity,
+    ignored_ids: HashSet<u32>,
+    ignored_types: HashSet<DebugType>,
+    deduplicate: bool,
+    synchronous: bool,
+    handler: Box<DebugMessageHandler>,
+}
+
+impl Default for DebugOutput {
+    fn default() -> Self {
+        DebugOutput {
+            min_severity: DebugSeverity::Low,
+            ignored_ids: HashSet::new(),
+            ignored_types: HashSet::from([DebugType::PushGroup, DebugType::PopGroup]),
+            deduplicate: true,
+            synchronous: false,
+            handler: Box::new(log_debug_message),
+        }
+    }
+}
+
+impl DebugOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn min_severity(mut self, severity: DebugSeverity) -> Self {
+        self.min_severity = severity;
+        self
+    }
+
+    /// Ignores a message id, e.g. a driver's notice about buffer placement.
+    pub fn ignore_id(mut self, id: u32) -> Self {
+        self.ignored_ids.insert(id);
+        self
+    }
+
+    pub fn ignore_type(mut self, ty: DebugType) -> Self {
+        self.ignored_types.insert(ty);
+        self
+    }
+
+    /// Only reports the first occurrence of each distinct message.
+    pub fn deduplicate(mut self, deduplicate: bool) -> Self {
+        self.deduplicate = deduplicate;
+        self
+    }
+
+    /// Enables `DEBUG_OUTPUT_SYNCHRONOUS`, which makes the driver report messages from
+    /// inside the offending GL call. Slower, but lets a breakpoint in the handler show
+    /// where an error comes from.
+    pub fn synchronous(mut self, synchronous: bool) -> Self {
+        self.synchronous = synchronous;
+        self
+    }
+
+    /// Replaces [log_debug_message] as the receiver of messages that pass the filters.
+    pub fn handler(mut self, handler: impl Fn(&DebugMessage) + Send + Sync + 'static) -> Self {
+        self.handler = Box::new(handler);
+        self
+    }
+
+    fn accepts(&self, message: &DebugMessage) -> bool {
+        message.severity >= self.min_severity
+            && !self.ignored_ids.contains(&message.id)
+            && !self.ignored_types.contains(&message.ty)
+    }
+
+    /// Enables debug output on `gl` and starts reporting messages.
+    pub fn install(self, gl: &mut Context) {
+        if !gl.supports_debug() {
+            eprintln!("GL debug output is not supported by this context");
+            return;
+        }
+
+        unsafe {
+            gl.enable(DEBUG_OUTPUT);
+            if self.synchronous {
+                gl.enable(DEBUG_OUTPUT_SYNCHRONOUS);
+            }
+            // Let the driver skip notifications entirely when they would be filtered anyway
+            gl.debug_message_control(
+                DONT_CARE,
+                DONT_CARE,
+                DEBUG_SEVERITY_NOTIFICATION,
+                &[],
+                self.min_severity == DebugSeverity::Notification,
+            );
+        }
+
+        let seen = Mutex::new(HashSet::new());
+        unsafe {
+            gl.debug_message_callback(move |source, ty, id, severity, message| {
+                let message = DebugMessage::from_gl(source, ty, id, severity, message);
+                if !self.accepts(&message) {
+                    return;
+                }
+                if self.deduplicate && !seen.lock().unwrap().insert(message.clone()) {
+                    return;
+                }
+                (self.handler)(&message);
+            });
+        }
+    }
+}
@@ -1,10 +1,19 @@
+use std::{error::Error, fmt};
+
 use glow::{
-    Context, HasContext as _, NativeFramebuffer, NativeRenderbuffer, NativeTexture,
-    COLOR_ATTACHMENT0, DEPTH_ATTACHMENT, DEPTH_COMPONENT24, FRAMEBUFFER, LINEAR, NEAREST,
-    RENDERBUFFER, REPEAT, RGBA, RGBA32F, TEXTURE_2D, TEXTURE_MAG_FILTER, TEXTURE_MIN_FILTER,
-    TEXTURE_WRAP_S, TEXTURE_WRAP_T, UNSIGNED_BYTE,
+    Context, HasContext as _, NativeFramebuffer, NativeRenderbuffer, NativeTexture, CLAMP_TO_EDGE,
+    COLOR_ATTACHMENT0, DEPTH24_STENCIL8, DEPTH32F_STENCIL8, DEPTH_ATTACHMENT, DEPTH_COMPONENT24,
+    DEPTH_STENCIL_ATTACHMENT, FRAMEBUFFER, FRAMEBUFFER_COMPLETE, FRAMEBUFFER_INCOMPLETE_ATTACHMENT,
+    FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER, FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS,
+    FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT, FRAMEBUFFER_INCOMPLETE_MULTISAMPLE,
+    FRAMEBUFFER_INCOMPLETE_READ_BUFFER, FRAMEBUFFER_UNDEFINED, FRAMEBUFFER_UNSUPPORTED, LINEAR,
+    LINEAR_MIPMAP_LINEAR, MAX_COLOR_ATTACHMENTS, NEAREST, RENDERBUFFER, REPEAT, RGBA, RGBA32F,
+    STENCIL_INDEX8, TEXTURE_2D, TEXTURE_2D_ARRAY, TEXTURE_3D, TEXTURE_MAG_FILTER,
+    TEXTURE_MIN_FILTER, TEXTURE_WRAP_R, TEXTURE_WRAP_S, TEXTURE_WRAP_T, UNSIGNED_BYTE,
 };
 
+use crate::{debug_markers::label_object, delete_object, track_object};
+
 pub struct FBO {
     width: i32,
     height: i32,
@@ -17,9 +26,9 @@ pub struct FBO {
 impl FBO {
     pub fn init(gl: &Context, width: i32, height: i32, filter_nn: bool) -> Self {
         unsafe {
-            let fb = gl.create_framebuffer().unwrap();
+            let fb = track_object(gl.create_framebuffer().unwrap());
             gl.bind_framebuffer(FRAMEBUFFER, Some(fb));
-            let tex = gl.create_texture().unwrap();
+            let tex = track_object(gl.create_texture().unwrap());
             gl.bind_texture(TEXTURE_2D, Some(tex));
             gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_S, REPEAT as _);
             gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_T, REPEAT as _);
@@ -44,11 +53,11 @@ impl FBO {
             gl.framebuffer_texture_2d(FRAMEBUFFER, COLOR_ATTACHMENT0, TEXTURE_2D, Some(tex), 0);
 
             // renderbuffer
-            let rb = gl.create_renderbuffer().unwrap();
+            let rb = track_object(gl.create_renderbuffer().unwrap());
             gl.bind_renderbuffer(RENDERBUFFER, Some(rb));
             gl.renderbuffer_storage(RENDERBUFFER, DEPTH_COMPONENT24, width, height);
             gl.framebuffer_renderbuffer(FRAMEBUFFER, DEPTH_ATTACHMENT, RENDERBUFFER, Some(rb));
-            // TODO: check framebuffer status
+            check_framebuffer_status(gl, FRAMEBUFFER).unwrap();
 
             gl.bind_framebuffer(FRAMEBUFFER, Some(fb));
 
@@ -62,14 +71,17 @@ impl FBO {
         }
     }
 
-    pub fn delete(&mut self, gl: &Context) {
-        unsafe {
-            gl.delete_renderbuffer(self.rb);
-            gl.delete_texture(self.tex);
-            gl.delete_framebuffer(self.fb);
-            self.width = -1;
-            self.height = -1;
-        }
+    /// Labels the framebuffer and its attachments for debug output and capture tools.
+    pub fn label(&self, gl: &Context, label: &str) {
+        label_object(gl, self.fb, label);
+        label_object(gl, self.tex, &format!("{label} color"));
+        label_object(gl, self.rb, &format!("{label} depth"));
+    }
+
+    pub fn delete(self, gl: &Context) {
+        delete_object(gl, self.rb);
+        delete_object(gl, self.tex);
+        delete_object(gl, self.fb);
     }
 }
 
@@ -95,3 +107,474 @@ pub unsafe fn bind_texture_fbo(gl: &Context, fbo: &FBO, tex_unit: u32) {
         gl.bind_texture(TEXTURE_2D, Some(fbo.tex));
     }
 }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FramebufferError {
+    /// Width, height or layer count is zero or negative.
+    InvalidSize {
+        width: i32,
+        height: i32,
+        layers: i32,
+    },
+    /// More colour attachments were requested than the driver supports.
+    TooManyColorAttachments { requested: usize, max: usize },
+    /// A texture, renderbuffer or framebuffer object could not be created.
+    Create(String),
+    /// `glCheckFramebufferStatus` did not return `GL_FRAMEBUFFER_COMPLETE`.
+    Incomplete { status: u32 },
+}
+
+impl FramebufferError {
+    fn status_name(status: u32) -> &'static str {
+        match status {
+            FRAMEBUFFER_UNDEFINED => "GL_FRAMEBUFFER_UNDEFINED",
+            FRAMEBUFFER_INCOMPLETE_ATTACHMENT => "GL_FRAMEBUFFER_INCOMPLETE_ATTACHMENT",
+            FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => {
+                "GL_FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT"
+            }
+            FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => "GL_FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER",
+            FRAMEBUFFER_INCOMPLETE_READ_BUFFER => "GL_FRAMEBUFFER_INCOMPLETE_READ_BUFFER",
+            FRAMEBUFFER_UNSUPPORTED => "GL_FRAMEBUFFER_UNSUPPORTED",
+            FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => "GL_FRAMEBUFFER_INCOMPLETE_MULTISAMPLE",
+            FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => "GL_FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS",
+            _ => "unknown status",
+        }
+    }
+
+    fn status_reason(status: u32) -> &'static str {
+        match status {
+            FRAMEBUFFER_UNDEFINED => "the default framebuffer does not exist",
+            FRAMEBUFFER_INCOMPLETE_ATTACHMENT => {
+                "an attachment has a format that is not renderable or has a zero size"
+            }
+            FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => "no image is attached",
+            FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => "a draw buffer refers to a missing attachment",
+            FRAMEBUFFER_INCOMPLETE_READ_BUFFER => "the read buffer refers to a missing attachment",
+            FRAMEBUFFER_UNSUPPORTED => "the driver does not support this combination of formats",
+            FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => "attachments have different sample counts",
+            FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => {
+                "layered and non-layered attachments are mixed, or layered attachments have \
+                 different targets"
+            }
+            _ => "",
+        }
+    }
+}
+
+impl fmt::Display for FramebufferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FramebufferError::InvalidSize {
+                width,
+                height,
+                layers,
+            } => write!(f, "invalid framebuffer size {width}x{height}x{layers}"),
+            FramebufferError::TooManyColorAttachments { requested, max } => write!(
+                f,
+                "{requested} colour attachments requested but only {max} are supported"
+            ),
+            FramebufferError::Create(error) => {
+                write!(f, "failed to create framebuffer object: {error}")
+            }
+            FramebufferError::Incomplete { status } => write!(
+                f,
+                "framebuffer is incomplete: {} (0x{status:X}): {}",
+                Self::status_name(*status),
+                Self::status_reason(*status)
+            ),
+        }
+    }
+}
+
+impl Error for FramebufferError {}
+
+/// Checks the completeness of the framebuffer bound to `target`.
+pub fn check_framebuffer_status(gl: &Context, target: u32) -> Result<(), FramebufferError> {
+    let status = unsafe { gl.check_framebuffer_status(target) };
+    if status == FRAMEBUFFER_COMPLETE {
+        Ok(())
+    } else {
+        Err(FramebufferError::Incomplete { status })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextureShape {
+    Texture2D,
+    Array2D { layers: i32 },
+    Texture3D { depth: i32 },
+}
+
+/// Texture to create for a framebuffer attachment, see [FramebufferBuilder::color].
+///
+/// Defaults to a 2D texture without mip-maps, linear filtering and `CLAMP_TO_EDGE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttachmentTexture {
+    internal_format: u32,
+    shape: TextureShape,
+    /// `None` allocates the full mip chain.
+    mip_levels: Option<i32>,
+    min_filter: u32,
+    mag_filter: u32,
+    wrap: u32,
+    /// Attach a single layer of an array or 3D texture instead of all of them.
+    layer: Option<i32>,
+}
+
+impl AttachmentTexture {
+    /// `internal_format` has to be a sized format like `RGBA16F` or `DEPTH_COMPONENT32`.
+    pub fn new(internal_format: u32) -> Self {
+        Self {
+            internal_format,
+            shape: TextureShape::Texture2D,
+            mip_levels: Some(1),
+            min_filter: LINEAR,
+            mag_filter: LINEAR,
+            wrap: CLAMP_TO_EDGE,
+            layer: None,
+        }
+    }
+
+    /// Allocates the full mip chain. Level 0 is the one attached.
+    pub fn mipmapped(mut self) -> Self {
+        self.mip_levels = None;
+        if self.min_filter == LINEAR {
+            self.min_filter = LINEAR_MIPMAP_LINEAR;
+        }
+        self
+    }
+
+    pub fn mip_levels(mut self, levels: i32) -> Self {
+        self.mip_levels = Some(levels);
+        self
+    }
+
+    pub fn filter(mut self, min_filter: u32, mag_filter: u32) -> Self {
+        self.min_filter = min_filter;
+        self.mag_filter = mag_filter;
+        self
+    }
+
+    pub fn wrap(mut self, wrap: u32) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// Makes this a 2D array texture. All layers are attached, so a geometry shader can pick
+    /// one with `gl_Layer`, unless [Self::layer] is used.
+    pub fn array(mut self, layers: i32) -> Self {
+        self.shape = TextureShape::Array2D { layers };
+        self
+    }
+
+    /// Makes this a 3D texture. All slices are attached unless [Self::layer] is used.
+    pub fn volume(mut self, depth: i32) -> Self {
+        self.shape = TextureShape::Texture3D { depth };
+        self
+    }
+
+    /// Attaches a single layer of an array or 3D texture.
+    pub fn layer(mut self, layer: i32) -> Self {
+        self.layer = Some(layer);
+        self
+    }
+
+    fn target(&self) -> u32 {
+        match self.shape {
+            TextureShape::Texture2D => TEXTURE_2D,
+            TextureShape::Array2D { .. } => TEXTURE_2D_ARRAY,
+            TextureShape::Texture3D { .. } => TEXTURE_3D,
+        }
+    }
+
+    unsafe fn create(
+        &self,
+        gl: &Context,
+        width: i32,
+        height: i32,
+        attachment: u32,
+    ) -> Result<NativeTexture, FramebufferError> {
+        let target = self.target();
+        let depth = match self.shape {
+            TextureShape::Texture2D => 1,
+            TextureShape::Array2D { layers } => layers,
+            TextureShape::Texture3D { depth } => depth,
+        };
+        if depth <= 0 {
+            return Err(FramebufferError::InvalidSize {
+                width,
+                height,
+                layers: depth,
+            });
+        }
+        let largest_side = match self.shape {
+            TextureShape::Texture3D { depth } => width.max(height).max(depth),
+            _ => width.max(height),
+        };
+        let levels = self
+            .mip_levels
+            .unwrap_or_else(|| largest_side.ilog2() as i32 + 1);
+
+        unsafe {
+            let tex = track_object(gl.create_texture().map_err(FramebufferError::Create)?);
+            gl.bind_texture(target, Some(tex));
+            gl.tex_parameter_i32(target, TEXTURE_WRAP_S, self.wrap as _);
+            gl.tex_parameter_i32(target, TEXTURE_WRAP_T, self.wrap as _);
+            gl.tex_parameter_i32(target, TEXTURE_WRAP_R, self.wrap as _);
+            gl.tex_parameter_i32(target, TEXTURE_MAG_FILTER, self.mag_filter as _);
+            gl.tex_parameter_i32(target, TEXTURE_MIN_FILTER, self.min_filter as _);
+            match self.shape {
+                TextureShape::Texture2D => {
+                    gl.tex_storage_2d(target, levels, self.internal_format, width, height)
+                }
+                _ => gl.tex_storage_3d(target, levels, self.internal_format, width, height, depth),
+            }
+
+            match (self.shape, self.layer) {
+                (TextureShape::Texture2D, _) => {
+                    gl.framebuffer_texture_2d(FRAMEBUFFER, attachment, target, Some(tex), 0)
+                }
+                (_, Some(layer)) => {
+                    gl.framebuffer_texture_layer(FRAMEBUFFER, attachment, Some(tex), 0, layer)
+                }
+                (_, None) => gl.framebuffer_texture(FRAMEBUFFER, attachment, Some(tex), 0),
+            }
+            Ok(tex)
+        }
+    }
+}
+
+impl From<u32> for AttachmentTexture {
+    fn from(internal_format: u32) -> Self {
+        Self::new(internal_format)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DepthAttachment {
+    Texture(AttachmentTexture),
+    Renderbuffer(u32),
+}
+
+fn depth_attachment_point(internal_format: u32) -> u32 {
+    match internal_format {
+        DEPTH24_STENCIL8 | DEPTH32F_STENCIL8 => DEPTH_STENCIL_ATTACHMENT,
+        STENCIL_INDEX8 => glow::STENCIL_ATTACHMENT,
+        _ => DEPTH_ATTACHMENT,
+    }
+}
+
+/// Builds a [Framebuffer] with any number of colour attachments and an optional depth or
+/// depth-stencil attachment.
+///
+/// ```ignore
+/// let scene = FramebufferBuilder::new(width, height)
+///     .color(RGBA16F)
+///     .color(AttachmentTexture::new(RG32F).mipmapped())
+///     .depth_texture(DEPTH_COMPONENT32)
+///     .build(gl)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct FramebufferBuilder {
+    width: i32,
+    height: i32,
+    colors: Vec<AttachmentTexture>,
+    depth: Option<DepthAttachment>,
+}
+
+impl FramebufferBuilder {
+    pub fn new(width: i32, height: i32) -> Self {
+        Self {
+            width,
+            height,
+            colors: vec![],
+            depth: None,
+        }
+    }
+
+    /// Adds a colour attachment at `COLOR_ATTACHMENT0 + n`, where `n` is the number of colour
+    /// attachments added before it. Takes an internal format or an [AttachmentTexture].
+    pub fn color(mut self, texture: impl Into<AttachmentTexture>) -> Self {
+        self.colors.push(texture.into());
+        self
+    }
+
+    /// Attaches a depth texture. Depth-stencil formats like `DEPTH24_STENCIL8` are attached to
+    /// `DEPTH_STENCIL_ATTACHMENT`.
+    pub fn depth_texture(mut self, texture: impl Into<AttachmentTexture>) -> Self {
+        self.depth = Some(DepthAttachment::Texture(texture.into()));
+        self
+    }
+
+    /// Attaches a depth renderbuffer, for when depth is only needed for testing.
+    pub fn depth_renderbuffer(mut self, internal_format: u32) -> Self {
+        self.depth = Some(DepthAttachment::Renderbuffer(internal_format));
+        self
+    }
+
+    /// Creates the framebuffer and its attachments, leaving no framebuffer bound. Everything
+    /// created is deleted again if the framebuffer turns out to be incomplete.
+    pub fn build(&self, gl: &Context) -> Result<Framebuffer, FramebufferError> {
+        if self.width <= 0 || self.height <= 0 {
+            return Err(FramebufferError::InvalidSize {
+                width: self.width,
+                height: self.height,
+                layers: 1,
+            });
+        }
+        let max = unsafe { gl.get_parameter_i32(MAX_COLOR_ATTACHMENTS) } as usize;
+        if self.colors.len() > max {
+            return Err(FramebufferError::TooManyColorAttachments {
+                requested: self.colors.len(),
+                max,
+            });
+        }
+
+        unsafe {
+            let fb = track_object(gl.create_framebuffer().map_err(FramebufferError::Create)?);
+            gl.bind_framebuffer(FRAMEBUFFER, Some(fb));
+            let mut framebuffer = Framebuffer {
+                width: self.width,
+                height: self.height,
+                fb,
+                colors: vec![],
+                depth_texture: None,
+                depth_renderbuffer: None,
+            };
+
+            let result = self.attach(gl, &mut framebuffer);
+            gl.bind_framebuffer(FRAMEBUFFER, None);
+            gl.bind_renderbuffer(RENDERBUFFER, None);
+            match result {
+                Ok(()) => Ok(framebuffer),
+                Err(error) => {
+                    framebuffer.delete(gl);
+                    Err(error)
+                }
+            }
+        }
+    }
+
+    unsafe fn attach(
+        &self,
+        gl: &Context,
+        framebuffer: &mut Framebuffer,
+    ) -> Result<(), FramebufferError> {
+        unsafe {
+            for (i, color) in self.colors.iter().enumerate() {
+                let attachment = COLOR_ATTACHMENT0 + i as u32;
+                let tex = color.create(gl, self.width, self.height, attachment)?;
+                gl.bind_texture(color.target(), None);
+                framebuffer.colors.push(tex);
+            }
+
+            match self.depth {
+                Some(DepthAttachment::Texture(texture)) => {
+                    let attachment = depth_attachment_point(texture.internal_format);
+                    let tex = texture.create(gl, self.width, self.height, attachment)?;
+                    gl.bind_texture(texture.target(), None);
+                    framebuffer.depth_texture = Some(tex);
+                }
+                Some(DepthAttachment::Renderbuffer(internal_format)) => {
+                    let rb =
+                        track_object(gl.create_renderbuffer().map_err(FramebufferError::Create)?);
+                    framebuffer.depth_renderbuffer = Some(rb);
+                    gl.bind_renderbuffer(RENDERBUFFER, Some(rb));
+                    gl.renderbuffer_storage(RENDERBUFFER, internal_format, self.width, self.height);
+                    gl.framebuffer_renderbuffer(
+                        FRAMEBUFFER,
+                        depth_attachment_point(internal_format),
+                        RENDERBUFFER,
+                        Some(rb),
+                    );
+                }
+                None => {}
+            }
+
+            let draw_buffers: Vec<u32> = (0..self.colors.len() as u32)
+                .map(|i| COLOR_ATTACHMENT0 + i)
+                .collect();
+            gl.draw_buffers(&draw_buffers);
+            if draw_buffers.is_empty() {
+                gl.read_buffer(glow::NONE);
+            }
+
+            check_framebuffer_status(gl, FRAMEBUFFER)
+        }
+    }
+}
+
+/// Framebuffer created with a [FramebufferBuilder].
+#[derive(Debug)]
+pub struct Framebuffer {
+    width: i32,
+    height: i32,
+    fb: NativeFramebuffer,
+    colors: Vec<NativeTexture>,
+    depth_texture: Option<NativeTexture>,
+    depth_renderbuffer: Option<NativeRenderbuffer>,
+}
+
+impl Framebuffer {
+    pub fn fb(&self) -> NativeFramebuffer {
+        self.fb
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// Texture of colour attachment `index`, in the order they were added to the builder.
+    pub fn color(&self, index: usize) -> NativeTexture {
+        self.colors[index]
+    }
+
+    pub fn colors(&self) -> &[NativeTexture] {
+        &self.colors
+    }
+
+    /// Depth texture, if the depth attachment is not a renderbuffer.
+    pub fn depth_texture(&self) -> Option<NativeTexture> {
+        self.depth_texture
+    }
+
+    /// Labels the framebuffer and its attachments for debug output and capture tools. The
+    /// attachments are named `"{label} color {index}"` and `"{label} depth"`.
+    pub fn label(&self, gl: &Context, label: &str) {
+        label_object(gl, self.fb, label);
+        for (i, tex) in self.colors.iter().enumerate() {
+            label_object(gl, *tex, &format!("{label} color {i}"));
+        }
+        if let Some(tex) = self.depth_texture {
+            label_object(gl, tex, &format!("{label} depth"));
+        }
+        if let Some(rb) = self.depth_renderbuffer {
+            label_object(gl, rb, &format!("{label} depth"));
+        }
+    }
+
+    /// Binds the framebuffer and sets the viewport to cover it.
+    pub fn bind(&self, gl: &Context) {
+        unsafe {
+            gl.bind_framebuffer(FRAMEBUFFER, Some(self.fb));
+            gl.viewport(0, 0, self.width, self.height);
+        }
+    }
+
+    pub fn delete(self, gl: &Context) {
+        for tex in self.colors {
+            delete_object(gl, tex);
+        }
+        if let Some(tex) = self.depth_texture {
+            delete_object(gl, tex);
+        }
+        if let Some(rb) = self.depth_renderbuffer {
+            delete_object(gl, rb);
+        }
+        delete_object(gl, self.fb);
+    }
+}
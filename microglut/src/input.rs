@@ -0,0 +1,81 @@
+use std::{
+    collections::HashSet,
+    sync::{LazyLock, Mutex, MutexGuard},
+};
+
+use sdl2::{keyboard::Scancode, mouse::MouseButton};
+
+static INPUT: LazyLock<Mutex<InputState>> = LazyLock::new(Default::default);
+
+/// Keyboard and mouse state as of the last processed event, maintained by the event loop.
+#[derive(Default)]
+struct InputState {
+    keys: HashSet<Scancode>,
+    mouse_buttons: HashSet<MouseButton>,
+    mouse_position: (i32, i32),
+    wheel_delta: (f32, f32),
+}
+
+fn input() -> MutexGuard<'static, InputState> {
+    INPUT.lock().unwrap()
+}
+
+/// Called before the events of a frame are processed.
+pub(crate) fn begin_frame() {
+    input().wheel_delta = (0.0, 0.0);
+}
+
+pub(crate) fn set_key(scancode: Scancode, down: bool) {
+    let mut input = input();
+    if down {
+        input.keys.insert(scancode);
+    } else {
+        input.keys.remove(&scancode);
+    }
+}
+
+pub(crate) fn set_mouse_button(button: MouseButton, down: bool) {
+    let mut input = input();
+    if down {
+        input.mouse_buttons.insert(button);
+    } else {
+        input.mouse_buttons.remove(&button);
+    }
+}
+
+pub(crate) fn set_mouse_position(x: i32, y: i32) {
+    input().mouse_position = (x, y);
+}
+
+pub(crate) fn add_wheel_delta(x: f32, y: f32) {
+    let mut input = input();
+    input.wheel_delta.0 += x;
+    input.wheel_delta.1 += y;
+}
+
+/// Releases everything, since the matching up events go to whichever window has focus.
+pub(crate) fn focus_lost() {
+    let mut input = input();
+    input.keys.clear();
+    input.mouse_buttons.clear();
+}
+
+/// Whether the key at a physical position is held, independent of the keyboard layout.
+pub fn is_key_down(scancode: Scancode) -> bool {
+    input().keys.contains(&scancode)
+}
+
+pub fn is_mouse_button_down(button: MouseButton) -> bool {
+    input().mouse_buttons.contains(&button)
+}
+
+/// Mouse position in pixels, like the drawable size.
+pub fn mouse_position() -> (i32, i32) {
+    input().mouse_position
+}
+
+/// Sum of the mouse wheel scrolling during the current frame. Positive `y` is away from
+/// the user and positive `x` is to the right.
+pub fn wheel_delta() -> (f32, f32) {
+    input().wheel_delta
+}
@@ -13,19 +13,53 @@ pub use glow;
 pub use imgui;
 pub use sdl2::{self, video::Window};
 
+mod bounds;
+mod debug_markers;
+mod debug_output;
 pub mod fbo;
+mod input;
 mod load_shaders;
 mod model;
+mod profiler;
+mod program;
+mod readback;
+mod resources;
 mod texture;
 pub mod time;
 pub mod util;
 
-pub use fbo::FBO;
-pub use load_shaders::{load_compute_shader, load_shaders, LoadShaders};
-pub use model::{load_tangent_buf, MaterialBindings, Model};
-pub use texture::Texture;
-use time::set_delta_time;
-pub use time::{delta_time, elapsed_time};
+pub use bounds::{Aabb, BoundingSphere};
+pub use debug_markers::{
+    debug_group, label_object, pop_debug_group, push_debug_group, DebugGroup, ObjectLabel,
+};
+pub use debug_output::{
+    log_debug_message, DebugMessage, DebugMessageHandler, DebugOutput, DebugSeverity, DebugSource,
+    DebugType,
+};
+pub use fbo::{AttachmentTexture, Framebuffer, FramebufferBuilder, FramebufferError, FBO};
+pub use input::{is_key_down, is_mouse_button_down, mouse_position, wheel_delta};
+pub use load_shaders::{
+    load_compute_shader, load_shaders, LoadShaders, ProgramCache, ReloadableProgram, Severity,
+    ShaderError, ShaderMessage, ShaderStage,
+};
+pub use model::{
+    generate_tangents, load_tangent_buf, BatchedModel, LoadError, MaterialBinding,
+    MaterialBindings, Mesh, Model, ObjOptions, TangentMesh, Vertex,
+};
+pub use profiler::{GpuProfiler, GpuScope, PassTiming};
+pub use program::{Program, TextureUnit, Uniform};
+pub use readback::{ReadbackError, ReadbackImage};
+pub use resources::{
+    delete_object, live_objects, report_leaks, track_object, GlObject, LiveObject,
+};
+pub use texture::{
+    mip_level_count, ColorSpace, Texture, Texture3D, TextureArray2D, TextureCache,
+    TextureCacheStats, TextureCube, TextureError, TextureOptions,
+};
+pub use time::{
+    advance_time, delta_time, elapsed_time, is_paused, set_paused, set_simulation_time,
+    set_time_source, simulation_time, step, time_source, TimeSource,
+};
 
 pub fn print_error(gl: &Context, what: &str) -> Result<(), ()> {
     let mut result = Ok(());
@@ -40,17 +74,48 @@ pub fn print_error(gl: &Context, what: &str) -> Result<(), ()> {
     result
 }
 
+/// Ratio between the drawable size in pixels and the window size in screen coordinates,
+/// e.g. 2.0 on a Retina display. Positions and sizes from SDL are in screen coordinates
+/// unless stated otherwise.
+pub fn dpi_scale(window: &Window) -> f32 {
+    let (width, _) = window.size();
+    let (drawable_width, _) = window.drawable_size();
+    if width == 0 {
+        1.0
+    } else {
+        drawable_width as f32 / width as f32
+    }
+}
+
+/// Size of the window's drawable in pixels. This is what the viewport and screen-sized
+/// render targets should use, rather than [Window::size].
+pub fn drawable_size(window: &Window) -> (u32, u32) {
+    window.drawable_size()
+}
+
 #[allow(unused)]
 pub trait MicroGLUT: Sized {
     fn init(gl: &Context, window: &Window) -> Self;
+    /// Called once per frame after the events have been processed and before
+    /// [MicroGLUT::display], with the [delta_time] of the frame. Continuous input like
+    /// movement belongs here, using [is_key_down] and friends rather than key repeat.
+    fn update(&mut self, dt: f32) {}
     fn display(&mut self, gl: &Context, window: &Window);
     #[cfg(feature = "imgui")]
     fn ui(&mut self, gl: &Context, ui: &mut imgui::Ui) {}
 
+    /// Called when the window changes size. `width` and `height` are the size of the
+    /// drawable in pixels, which is what the viewport and render targets should use.
+    fn resize(&mut self, gl: &Context, width: u32, height: u32) {}
+
+    /// Mouse positions are in pixels, like the drawable size. Relative motion is left in
+    /// screen coordinates so that mouse sensitivity does not depend on the DPI scale.
     fn mouse_up(&mut self, button: MouseButton, x: i32, y: i32) {}
     fn mouse_down(&mut self, button: MouseButton, x: i32, y: i32) {}
     fn mouse_moved_to(&mut self, x: i32, y: i32) {}
     fn mouse_moved_rel(&mut self, xrel: i32, yrel: i32) {}
+    /// Scroll amounts in the direction of [wheel_delta].
+    fn mouse_wheel(&mut self, x: f32, y: f32) {}
 
     fn key_down(
         &mut self,
@@ -70,6 +135,16 @@ pub trait MicroGLUT: Sized {
     ) {
     }
 
+    /// Text typed by the user, with the keyboard layout and input method applied.
+    fn text_input(&mut self, text: &str) {}
+    fn focus_changed(&mut self, focused: bool) {}
+    fn file_dropped(&mut self, path: PathBuf) {}
+
+    /// Called once when the main loop ends, while the context is still current. GPU
+    /// resources should be deleted here, in debug builds the ones that are not are
+    /// reported by [report_leaks] afterwards.
+    fn exit(self, gl: &Context) {}
+
     fn sdl2_window(window_title: impl Into<String>) -> StartBuilder<Self> {
         StartBuilder::new(window_title.into())
     }
@@ -84,9 +159,17 @@ pub struct StartBuilder<T: MicroGLUT> {
     gl_version: Option<(u8, u8)>,
     micro_glut: PhantomData<T>,
     debug_message_callback: Option<Box<DebugMessageCallback>>,
+    debug_output: Option<DebugOutput>,
     imgui_ini_filename: Option<String>,
+    headless_frames: Option<u32>,
+    time_source: Option<TimeSource>,
+    start_paused: bool,
 }
 
+/// Environment variable that switches any [StartBuilder] into headless mode.
+/// The value is the number of frames to render, e.g. `MICROGLUT_HEADLESS=10`.
+pub const HEADLESS_ENV_VAR: &str = "MICROGLUT_HEADLESS";
+
 impl<T: MicroGLUT> StartBuilder<T> {
     pub fn new(window_title: String) -> Self {
         StartBuilder {
@@ -96,7 +179,11 @@ impl<T: MicroGLUT> StartBuilder<T> {
             gl_version: None,
             micro_glut: PhantomData,
             debug_message_callback: None,
+            debug_output: None,
             imgui_ini_filename: None,
+            headless_frames: None,
+            time_source: None,
+            start_paused: false,
         }
     }
 
@@ -119,40 +206,105 @@ impl<T: MicroGLUT> StartBuilder<T> {
         self
     }
 
+    /// Requests a debug context and reports its messages as configured by `debug_output`.
+    /// Takes precedence over [StartBuilder::debug_message_callback].
+    pub fn debug_output(mut self, debug_output: DebugOutput) -> Self {
+        self.debug_output = Some(debug_output);
+        self
+    }
+
     pub fn imgui_ini_filename(mut self, filename: impl Into<String>) -> Self {
         self.imgui_ini_filename = Some(filename.into());
         self
     }
 
+    /// Run without a display: render `frames` frames offscreen and then return from
+    /// [StartBuilder::start].
+    ///
+    /// SDL's offscreen video driver is used, which creates the GL context on top of an
+    /// EGL pbuffer (e.g. Mesa llvmpipe), so the default framebuffer never reaches a screen.
+    /// Can also be enabled without code changes through [HEADLESS_ENV_VAR]. Requests a GL 4.5
+    /// context unless [StartBuilder::gl_version] is given.
+    pub fn headless(mut self, frames: u32) -> Self {
+        self.headless_frames = Some(frames);
+        self
+    }
+
+    /// Where [delta_time] comes from. Defaults to [TimeSource::RealTime], or to a fixed
+    /// 1/60 s per frame in headless mode so that repeated runs render identical frames.
+    pub fn time_source(mut self, time_source: TimeSource) -> Self {
+        self.time_source = Some(time_source);
+        self
+    }
+
+    /// Starts with time paused, see [set_paused].
+    pub fn paused(mut self, paused: bool) -> Self {
+        self.start_paused = paused;
+        self
+    }
+
     pub fn start(mut self) {
-        time::initialize();
+        let headless_frames = self.headless_frames.or_else(|| {
+            let frames = std::env::var(HEADLESS_ENV_VAR).ok()?;
+            match frames.parse() {
+                Ok(frames) => Some(frames),
+                Err(error) => {
+                    eprintln!("Ignoring {HEADLESS_ENV_VAR}={frames:?}: {error}");
+                    None
+                }
+            }
+        });
 
-        let (gl_major_version, gl_minor_version) = self.gl_version.unwrap_or((3, 2));
+        // Headless runs are meant for testing the GL 4.5 code paths on software renderers
+        let default_gl_version = if headless_frames.is_some() {
+            (4, 5)
+        } else {
+            (3, 2)
+        };
+        let (gl_major_version, gl_minor_version) = self.gl_version.unwrap_or(default_gl_version);
+
+        let time_source = self.time_source.unwrap_or(if headless_frames.is_some() {
+            TimeSource::Fixed(1.0 / 60.0)
+        } else {
+            TimeSource::RealTime
+        });
+        time::initialize(time_source, self.start_paused);
+        if headless_frames.is_some() {
+            sdl2::hint::set("SDL_VIDEODRIVER", "offscreen");
+        }
 
         let sdl = sdl2::init().unwrap();
         let video = sdl.video().unwrap();
         let gl_attr = video.gl_attr();
         gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
         gl_attr.set_context_version(gl_major_version, gl_minor_version);
-        let window = video
-            .window(
-                &self.window_title,
-                self.window_width.unwrap_or(800),
-                self.window_height.unwrap_or(800),
-            )
-            .allow_highdpi()
-            .opengl()
-            .resizable()
-            .build()
-            .unwrap();
+        if self.debug_output.is_some() {
+            gl_attr.set_context_flags().debug().set();
+        }
+        let mut window_builder = video.window(
+            &self.window_title,
+            self.window_width.unwrap_or(800),
+            self.window_height.unwrap_or(800),
+        );
+        window_builder.allow_highdpi().opengl().resizable();
+        if headless_frames.is_some() {
+            window_builder.hidden();
+        }
+        let window = window_builder.build().unwrap();
         let gl_context = window.gl_create_context().unwrap();
         window.gl_make_current(&gl_context).unwrap();
-        window.subsystem().gl_set_swap_interval(1).unwrap();
+        if headless_frames.is_none() {
+            window.subsystem().gl_set_swap_interval(1).unwrap();
+        }
 
         let mut gl =
             unsafe { Context::from_loader_function(|s| video.gl_get_proc_address(s) as *const _) };
+        model::load_gl_functions(|s| video.gl_get_proc_address(s) as *const _);
+        program::load_gl_functions(|s| video.gl_get_proc_address(s) as *const _);
 
-        if let Some(callback) = self.debug_message_callback.take() {
+        if let Some(debug_output) = self.debug_output.take() {
+            debug_output.install(&mut gl);
+        } else if let Some(callback) = self.debug_message_callback.take() {
             unsafe {
                 gl.debug_message_callback(move |source, typ, id, severity, message| {
                     callback(source, typ, id, severity, message.to_string())
@@ -169,9 +321,18 @@ impl<T: MicroGLUT> StartBuilder<T> {
             imgui.set_ini_filename(self.imgui_ini_filename.map(PathBuf::from));
             imgui.set_log_filename(None);
 
+            // Rasterise the font at the pixel size and scale it back down, so text stays
+            // sharp when the framebuffer is larger than the window
+            let dpi_scale = dpi_scale(&window);
             imgui
                 .fonts()
-                .add_font(&[imgui::FontSource::DefaultFontData { config: None }]);
+                .add_font(&[imgui::FontSource::DefaultFontData {
+                    config: Some(imgui::FontConfig {
+                        size_pixels: 13.0 * dpi_scale,
+                        ..Default::default()
+                    }),
+                }]);
+            imgui.io_mut().font_global_scale = 1.0 / dpi_scale;
 
             let platform = imgui_sdl2_support::SdlPlatform::new(&mut imgui);
             let renderer = imgui_glow_renderer::AutoRenderer::new(gl, &mut imgui).unwrap();
@@ -182,28 +343,79 @@ impl<T: MicroGLUT> StartBuilder<T> {
         let gl = Rc::new(gl);
 
         let mut running = true;
+        let mut frame_count = 0;
         let mut event_loop = sdl.event_pump().unwrap();
-        while running {
+        while running && headless_frames.is_none_or(|frames| frame_count < frames) {
+            input::begin_frame();
             for event in event_loop.poll_iter() {
-                use sdl2::event::Event;
+                use sdl2::{
+                    event::{Event, WindowEvent},
+                    mouse::MouseWheelDirection,
+                };
 
                 #[cfg(feature = "imgui")]
                 platform.handle_event(&mut imgui, &event);
 
+                let scale = dpi_scale(&window);
+                let to_pixels =
+                    |x: i32, y: i32| ((x as f32 * scale) as i32, (y as f32 * scale) as i32);
+
                 match event {
                     Event::Quit { .. } => running = false,
+                    Event::Window {
+                        win_event: WindowEvent::SizeChanged(..) | WindowEvent::DisplayChanged(..),
+                        ..
+                    } => {
+                        let (width, height) = window.drawable_size();
+                        app.resize(&gl, width, height);
+                    }
+                    Event::Window {
+                        win_event: WindowEvent::FocusGained,
+                        ..
+                    } => app.focus_changed(true),
+                    Event::Window {
+                        win_event: WindowEvent::FocusLost,
+                        ..
+                    } => {
+                        input::focus_lost();
+                        app.focus_changed(false);
+                    }
                     Event::MouseButtonUp {
                         mouse_btn, x, y, ..
-                    } => app.mouse_up(mouse_btn, x, y),
+                    } => {
+                        let (x, y) = to_pixels(x, y);
+                        input::set_mouse_button(mouse_btn, false);
+                        app.mouse_up(mouse_btn, x, y)
+                    }
                     Event::MouseButtonDown {
                         mouse_btn, x, y, ..
-                    } => app.mouse_down(mouse_btn, x, y),
+                    } => {
+                        let (x, y) = to_pixels(x, y);
+                        input::set_mouse_button(mouse_btn, true);
+                        app.mouse_down(mouse_btn, x, y)
+                    }
                     Event::MouseMotion {
                         x, y, xrel, yrel, ..
                     } => {
+                        let (x, y) = to_pixels(x, y);
+                        input::set_mouse_position(x, y);
                         app.mouse_moved_to(x, y);
                         app.mouse_moved_rel(xrel, yrel);
                     }
+                    Event::MouseWheel {
+                        precise_x,
+                        precise_y,
+                        direction,
+                        ..
+                    } => {
+                        let sign = match direction {
+                            MouseWheelDirection::Flipped => -1.0,
+                            _ => 1.0,
+                        };
+                        let (x, y) = (precise_x * sign, precise_y * sign);
+                        input::add_wheel_delta(x, y);
+                        app.mouse_wheel(x, y);
+                    }
                     Event::KeyDown {
                         keycode,
                         scancode,
@@ -211,6 +423,9 @@ impl<T: MicroGLUT> StartBuilder<T> {
                         repeat,
                         ..
                     } => {
+                        if let Some(scancode) = scancode {
+                            input::set_key(scancode, true);
+                        }
                         app.key_down(keycode, scancode, keymod, repeat);
                     }
                     Event::KeyUp {
@@ -219,11 +434,20 @@ impl<T: MicroGLUT> StartBuilder<T> {
                         keymod,
                         repeat,
                         ..
-                    } => app.key_up(keycode, scancode, keymod, repeat),
+                    } => {
+                        if let Some(scancode) = scancode {
+                            input::set_key(scancode, false);
+                        }
+                        app.key_up(keycode, scancode, keymod, repeat)
+                    }
+                    Event::TextInput { text, .. } => app.text_input(&text),
+                    Event::DropFile { filename, .. } => app.file_dropped(PathBuf::from(filename)),
                     _ => {}
                 }
             }
 
+            app.update(delta_time());
+
             #[cfg(feature = "imgui")]
             {
                 platform.prepare_frame(&mut imgui, &window, &event_loop);
@@ -243,8 +467,18 @@ impl<T: MicroGLUT> StartBuilder<T> {
             window.gl_swap_window();
 
             let now = Instant::now();
-            set_delta_time(now.duration_since(prev_frame).as_secs_f32());
+            time::end_frame(now.duration_since(prev_frame).as_secs_f32());
             prev_frame = now;
+
+            frame_count += 1;
         }
+
+        if headless_frames.is_some() {
+            // Make sure all rendering has completed before handing control back
+            unsafe { gl.finish() };
+        }
+
+        app.exit(&gl);
+        report_leaks();
     }
 }
@@ -1,46 +1,290 @@
-use std::path::Path;
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    path::{Path, PathBuf},
+};
 
 use glow::{
-    Context, HasContext as _, NativeProgram, COMPUTE_SHADER, FRAGMENT_SHADER, GEOMETRY_SHADER,
-    TESS_CONTROL_SHADER, TESS_EVALUATION_SHADER, VERTEX_SHADER,
+    Context, HasContext as _, NativeProgram, NativeShader, COMPUTE_SHADER, FRAGMENT_SHADER,
+    GEOMETRY_SHADER, TESS_CONTROL_SHADER, TESS_EVALUATION_SHADER, VERTEX_SHADER,
 };
 
+use crate::{delete_object, track_object};
+
+use preprocess::{inject_defines, preprocess, IncludeResolver};
+pub use reload::ReloadableProgram;
+
+mod preprocess;
+mod reload;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+    Geometry,
+    TesselationControl,
+    TesselationEvaluation,
+    Compute,
+}
+
+impl ShaderStage {
+    pub fn gl_type(self) -> u32 {
+        match self {
+            ShaderStage::Vertex => VERTEX_SHADER,
+            ShaderStage::Fragment => FRAGMENT_SHADER,
+            ShaderStage::Geometry => GEOMETRY_SHADER,
+            ShaderStage::TesselationControl => TESS_CONTROL_SHADER,
+            ShaderStage::TesselationEvaluation => TESS_EVALUATION_SHADER,
+            ShaderStage::Compute => COMPUTE_SHADER,
+        }
+    }
+
+    fn default_source_name(self) -> &'static str {
+        match self {
+            ShaderStage::Vertex => "<vertex>",
+            ShaderStage::Fragment => "<fragment>",
+            ShaderStage::Geometry => "<geometry>",
+            ShaderStage::TesselationControl => "<tesselation control>",
+            ShaderStage::TesselationEvaluation => "<tesselation evaluation>",
+            ShaderStage::Compute => "<compute>",
+        }
+    }
+}
+
+impl fmt::Display for ShaderStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ShaderStage::Vertex => "vertex",
+            ShaderStage::Fragment => "fragment",
+            ShaderStage::Geometry => "geometry",
+            ShaderStage::TesselationControl => "tesselation control",
+            ShaderStage::TesselationEvaluation => "tesselation evaluation",
+            ShaderStage::Compute => "compute",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A single message from the driver's shader info log.
+#[derive(Debug, Clone)]
+pub struct ShaderMessage {
+    pub severity: Severity,
+    /// Name or path of the source the message refers to.
+    pub source: String,
+    /// `None` if the driver message could not be parsed.
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+impl fmt::Display for ShaderMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(
+                f,
+                "{}:{}: {}: {}",
+                self.source, line, self.severity, self.message
+            ),
+            None => write!(f, "{}: {}: {}", self.source, self.severity, self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ShaderError {
+    /// A shader source could not be read from disk.
+    Read {
+        path: PathBuf,
+        error: String,
+    },
+    /// An `#include` directive could not be resolved.
+    Include {
+        source_name: String,
+        line: u32,
+        message: String,
+    },
+    Compile {
+        stage: ShaderStage,
+        source_name: String,
+        messages: Vec<ShaderMessage>,
+        log: String,
+    },
+    Link {
+        log: String,
+    },
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Read { path, error } => {
+                write!(f, "failed to read shader {}: {error}", path.display())
+            }
+            ShaderError::Include {
+                source_name,
+                line,
+                message,
+            } => write!(f, "{source_name}:{line}: {message}"),
+            ShaderError::Compile {
+                stage,
+                source_name,
+                messages,
+                log,
+            } => {
+                write!(f, "failed to compile {stage} shader {source_name}")?;
+                if messages.is_empty() {
+                    write!(f, "\n{}", log.trim_end())
+                } else {
+                    messages
+                        .iter()
+                        .try_for_each(|message| write!(f, "\n{message}"))
+                }
+            }
+            ShaderError::Link { log } => write!(f, "failed to link program\n{}", log.trim_end()),
+        }
+    }
+}
+
+impl Error for ShaderError {}
+
+#[derive(Debug, Clone)]
+enum Source {
+    Inline(String),
+    Path(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+struct ShaderSource {
+    stage: ShaderStage,
+    name: String,
+    source: Source,
+}
+
+impl ShaderSource {
+    fn inline(stage: ShaderStage, source: impl Into<String>) -> Self {
+        ShaderSource {
+            stage,
+            name: stage.default_source_name().to_string(),
+            source: Source::Inline(source.into()),
+        }
+    }
+
+    fn path(stage: ShaderStage, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        ShaderSource {
+            stage,
+            name: path.display().to_string(),
+            source: Source::Path(path.to_path_buf()),
+        }
+    }
+
+    fn read(&self) -> Result<String, ShaderError> {
+        match &self.source {
+            Source::Inline(source) => Ok(source.clone()),
+            Source::Path(path) => {
+                std::fs::read_to_string(path).map_err(|error| ShaderError::Read {
+                    path: path.clone(),
+                    error: error.to_string(),
+                })
+            }
+        }
+    }
+
+    fn file_path(&self) -> Option<&Path> {
+        match &self.source {
+            Source::Inline(_) => None,
+            Source::Path(path) => Some(path),
+        }
+    }
+
+    fn prepare(
+        &self,
+        resolver: &IncludeResolver,
+        defines: &[(String, String)],
+    ) -> Result<PreparedShader, ShaderError> {
+        let preprocessed = preprocess(&self.name, self.file_path(), &self.read()?, resolver)?;
+        Ok(PreparedShader {
+            stage: self.stage,
+            source_names: preprocessed.source_names,
+            source: inject_defines(&preprocessed.source, defines),
+            dependencies: preprocessed.dependencies,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct LoadShaders {
-    vertex: String,
-    fragment: String,
-    geometry: Option<String>,
-    tesselation_evaluation: Option<String>,
-    tesselation_control: Option<String>,
+    vertex: ShaderSource,
+    fragment: ShaderSource,
+    geometry: Option<ShaderSource>,
+    tesselation_evaluation: Option<ShaderSource>,
+    tesselation_control: Option<ShaderSource>,
+    include_dirs: Vec<PathBuf>,
+    virtual_includes: HashMap<String, String>,
+    defines: Vec<(String, String)>,
 }
 
 impl LoadShaders {
     pub fn new(vertex: impl Into<String>, fragment: impl Into<String>) -> Self {
         LoadShaders {
-            vertex: vertex.into(),
-            fragment: fragment.into(),
+            vertex: ShaderSource::inline(ShaderStage::Vertex, vertex),
+            fragment: ShaderSource::inline(ShaderStage::Fragment, fragment),
             geometry: None,
             tesselation_evaluation: None,
             tesselation_control: None,
+            include_dirs: vec![],
+            virtual_includes: HashMap::new(),
+            defines: vec![],
         }
     }
 
+    /// Like [LoadShaders::new] but the sources are read from disk when compiling. Errors
+    /// refer to the shaders by their paths.
     pub fn new_from_path(
         vertex_shader_path: impl AsRef<Path>,
         fragment_shader_path: impl AsRef<Path>,
     ) -> Self {
-        let vertex = std::fs::read_to_string(vertex_shader_path).unwrap();
-        let fragment = std::fs::read_to_string(fragment_shader_path).unwrap();
-        Self::new(vertex, fragment)
+        LoadShaders {
+            vertex: ShaderSource::path(ShaderStage::Vertex, vertex_shader_path),
+            fragment: ShaderSource::path(ShaderStage::Fragment, fragment_shader_path),
+            geometry: None,
+            tesselation_evaluation: None,
+            tesselation_control: None,
+            include_dirs: vec![],
+            virtual_includes: HashMap::new(),
+            defines: vec![],
+        }
     }
 
     pub fn geometry(mut self, geometry_shader: impl Into<String>) -> Self {
-        self.geometry = Some(geometry_shader.into());
+        self.geometry = Some(ShaderSource::inline(ShaderStage::Geometry, geometry_shader));
         self
     }
 
-    pub fn geometry_from_path(self, geometry_shader_path: impl AsRef<Path>) -> Self {
-        let geometry = std::fs::read_to_string(geometry_shader_path).unwrap();
-        self.geometry(geometry)
+    pub fn geometry_from_path(mut self, geometry_shader_path: impl AsRef<Path>) -> Self {
+        self.geometry = Some(ShaderSource::path(
+            ShaderStage::Geometry,
+            geometry_shader_path,
+        ));
+        self
     }
 
     pub fn tesselation(
@@ -48,74 +292,321 @@ impl LoadShaders {
         tesselation_control_shader: impl Into<String>,
         tesselation_evaluation_shader: impl Into<String>,
     ) -> Self {
-        self.tesselation_control = Some(tesselation_control_shader.into());
-        self.tesselation_evaluation = Some(tesselation_evaluation_shader.into());
+        self.tesselation_control = Some(ShaderSource::inline(
+            ShaderStage::TesselationControl,
+            tesselation_control_shader,
+        ));
+        self.tesselation_evaluation = Some(ShaderSource::inline(
+            ShaderStage::TesselationEvaluation,
+            tesselation_evaluation_shader,
+        ));
+        self
+    }
+
+    /// Name used for the source of `stage` in error messages, e.g. the file name of an
+    /// `include_str!`:d shader. Does nothing if the stage isn't used.
+    pub fn source_name(mut self, stage: ShaderStage, name: impl Into<String>) -> Self {
+        if let Some(source) = self.stages_mut().find(|source| source.stage == stage) {
+            source.name = name.into();
+        }
+        self
+    }
+
+    /// Makes `source` available to `#include "name"` directives, e.g. for sharing
+    /// `include_str!`:d code between shaders.
+    pub fn include(mut self, name: impl Into<String>, source: impl Into<String>) -> Self {
+        self.virtual_includes.insert(name.into(), source.into());
+        self
+    }
+
+    /// [LoadShaders::include] for a list of `(name, source)` pairs.
+    pub fn includes(mut self, files: &[(&str, &str)]) -> Self {
+        self.virtual_includes.extend(
+            files
+                .iter()
+                .map(|(name, source)| (name.to_string(), source.to_string())),
+        );
+        self
+    }
+
+    /// Directory to search for `#include`:d files that are not found next to the including
+    /// file.
+    pub fn include_dir(mut self, directory: impl AsRef<Path>) -> Self {
+        self.include_dirs.push(directory.as_ref().to_path_buf());
         self
     }
 
-    pub fn compile(self, gl: &Context) -> NativeProgram {
-        let shaders: Vec<_> = [
-            (VERTEX_SHADER, self.vertex),
-            (FRAGMENT_SHADER, self.fragment),
-        ]
-        .into_iter()
-        .chain(self.geometry.map(|geometry| (GEOMETRY_SHADER, geometry)))
-        .chain(
-            self.tesselation_control
-                .map(|tess_control| (TESS_CONTROL_SHADER, tess_control)),
-        )
-        .chain(
-            self.tesselation_evaluation
-                .map(|tess_evaluation| (TESS_EVALUATION_SHADER, tess_evaluation)),
-        )
-        .collect();
+    /// Adds `#define name value` to all stages, right after their `#version` line.
+    /// Defining the same name again replaces the previous value.
+    pub fn define(mut self, name: impl Into<String>, value: impl ToString) -> Self {
+        let name = name.into();
+        let value = value.to_string();
+        match self
+            .defines
+            .iter_mut()
+            .find(|(defined, _)| *defined == name)
+        {
+            Some((_, old_value)) => *old_value = value,
+            None => self.defines.push((name, value)),
+        }
+        self
+    }
 
-        compile_shaders(gl, &shaders)
+    fn stages(&self) -> impl Iterator<Item = &ShaderSource> {
+        [Some(&self.vertex), Some(&self.fragment)]
+            .into_iter()
+            .chain([
+                self.geometry.as_ref(),
+                self.tesselation_control.as_ref(),
+                self.tesselation_evaluation.as_ref(),
+            ])
+            .flatten()
     }
+
+    fn stages_mut(&mut self) -> impl Iterator<Item = &mut ShaderSource> {
+        [Some(&mut self.vertex), Some(&mut self.fragment)]
+            .into_iter()
+            .chain([
+                self.geometry.as_mut(),
+                self.tesselation_control.as_mut(),
+                self.tesselation_evaluation.as_mut(),
+            ])
+            .flatten()
+    }
+
+    fn prepare(&self) -> Result<Vec<PreparedShader>, ShaderError> {
+        let resolver = IncludeResolver {
+            include_dirs: &self.include_dirs,
+            virtual_files: &self.virtual_includes,
+        };
+        self.stages()
+            .map(|shader| shader.prepare(&resolver, &self.defines))
+            .collect()
+    }
+
+    pub fn compile(&self, gl: &Context) -> Result<NativeProgram, ShaderError> {
+        compile_shaders(gl, &self.prepare()?)
+    }
+}
+
+/// Compiled programs keyed by their final sources, i.e. after includes and defines have
+/// been resolved. Makes switching between permutations of a shader at runtime cheap.
+#[derive(Debug, Default)]
+pub struct ProgramCache {
+    programs: HashMap<CacheKey, NativeProgram>,
 }
 
-fn compile_shaders(gl: &Context, shaders: &[(u32, String)]) -> NativeProgram {
+impl ProgramCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the cached program for `shaders`, compiling it on a miss. The sources are
+    /// read and preprocessed on every call, so call this when the permutation changes
+    /// rather than every frame.
+    pub fn get_or_compile(
+        &mut self,
+        gl: &Context,
+        shaders: &LoadShaders,
+    ) -> Result<NativeProgram, ShaderError> {
+        self.get_or_compile_prepared(gl, &shaders.prepare()?)
+    }
+
+    fn get_or_compile_prepared(
+        &mut self,
+        gl: &Context,
+        prepared: &[PreparedShader],
+    ) -> Result<NativeProgram, ShaderError> {
+        let key = cache_key(prepared);
+        if let Some(program) = self.programs.get(&key) {
+            return Ok(*program);
+        }
+        let program = compile_shaders(gl, prepared)?;
+        self.programs.insert(key, program);
+        Ok(program)
+    }
+
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+
+    /// Deletes all cached programs.
+    pub fn clear(&mut self, gl: &Context) {
+        for (_, program) in self.programs.drain() {
+            delete_object(gl, program);
+        }
+    }
+}
+
+/// The final source of every stage.
+type CacheKey = Vec<(ShaderStage, String)>;
+
+fn cache_key(prepared: &[PreparedShader]) -> CacheKey {
+    prepared
+        .iter()
+        .map(|shader| (shader.stage, shader.source.clone()))
+        .collect()
+}
+
+/// Shader source ready to be handed to the driver. `source_names` maps the source string
+/// numbers used in the driver's info log back to names.
+struct PreparedShader {
+    stage: ShaderStage,
+    source_names: Vec<String>,
+    source: String,
+    dependencies: Vec<PathBuf>,
+}
+
+fn compile_shaders(gl: &Context, shaders: &[PreparedShader]) -> Result<NativeProgram, ShaderError> {
     unsafe {
-        // TODO: should program be created outside?
         let program = gl.create_program().expect("Cannot create program");
 
-        let shaders: Vec<_> = shaders
-            .iter()
-            .map(|(shader_type, shader_source)| {
-                let shader = gl.create_shader(*shader_type).unwrap();
-                gl.shader_source(shader, shader_source);
-                gl.compile_shader(shader);
-                if !gl.get_shader_compile_status(shader) {
-                    panic!("{}", gl.get_shader_info_log(shader));
-                }
-                gl.attach_shader(program, shader);
-                shader
-            })
-            .collect();
+        let mut compiled: Vec<NativeShader> = Vec::with_capacity(shaders.len());
+        let delete_all = |compiled: &[NativeShader]| {
+            for &shader in compiled {
+                gl.delete_shader(shader);
+            }
+            gl.delete_program(program);
+        };
+
+        for shader in shaders {
+            let gl_shader = gl.create_shader(shader.stage.gl_type()).unwrap();
+            gl.shader_source(gl_shader, &shader.source);
+            gl.compile_shader(gl_shader);
+            compiled.push(gl_shader);
+            if !gl.get_shader_compile_status(gl_shader) {
+                let log = gl.get_shader_info_log(gl_shader);
+                delete_all(&compiled);
+                return Err(ShaderError::Compile {
+                    stage: shader.stage,
+                    source_name: shader.source_names[0].clone(),
+                    messages: parse_info_log(&log, &shader.source_names),
+                    log,
+                });
+            }
+            gl.attach_shader(program, gl_shader);
+        }
 
         gl.link_program(program);
         if !gl.get_program_link_status(program) {
-            panic!("{}", gl.get_program_info_log(program));
+            let log = gl.get_program_info_log(program);
+            delete_all(&compiled);
+            return Err(ShaderError::Link { log });
         }
 
-        for shader in shaders {
+        for shader in compiled {
             gl.detach_shader(program, shader);
             gl.delete_shader(shader);
         }
 
-        program
+        Ok(track_object(program))
+    }
+}
+
+fn parse_info_log(log: &str, source_names: &[String]) -> Vec<ShaderMessage> {
+    let source_name = |index: usize| {
+        source_names
+            .get(index)
+            .cloned()
+            .unwrap_or_else(|| index.to_string())
+    };
+
+    log.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| match parse_log_line(line) {
+            Some((severity, index, line, message)) => ShaderMessage {
+                severity,
+                source: source_name(index),
+                line: Some(line),
+                message,
+            },
+            None => ShaderMessage {
+                severity: if line.to_ascii_lowercase().contains("error") {
+                    Severity::Error
+                } else {
+                    Severity::Info
+                },
+                source: source_name(0),
+                line: None,
+                message: line.to_string(),
+            },
+        })
+        .collect()
+}
+
+fn parse_severity(word: &str) -> Option<Severity> {
+    match word.to_ascii_lowercase().as_str() {
+        "error" | "fatal" => Some(Severity::Error),
+        "warning" => Some(Severity::Warning),
+        "info" | "note" => Some(Severity::Info),
+        _ => None,
     }
 }
 
+/// Parses one line of an info log into severity, source string number, line and message.
+///
+/// Handles the formats of the common drivers:
+/// - `ERROR: 0:12: message` (AMD, Intel on Windows, Apple)
+/// - `0:12(5): error: message` (Mesa)
+/// - `0(12) : error C0000: message` (NVIDIA)
+fn parse_log_line(line: &str) -> Option<(Severity, usize, u32, String)> {
+    if let Some((severity, rest)) = line.split_once(':') {
+        if let Some(severity) = parse_severity(severity) {
+            let (source, rest) = rest.trim_start().split_once(':')?;
+            let (line, message) = rest.split_once(':')?;
+            return Some((
+                severity,
+                source.trim().parse().ok()?,
+                line.trim().parse().ok()?,
+                message.trim().to_string(),
+            ));
+        }
+    }
+
+    let (location, rest) = line.split_once(": ")?;
+    let location = location.trim();
+    let (source, line) = match location.split_once(':') {
+        Some((source, rest)) => (source, rest.split('(').next()?),
+        None => {
+            let (source, rest) = location.split_once('(')?;
+            (source, rest.strip_suffix(')')?)
+        }
+    };
+    let (kind, message) = rest.split_once(':')?;
+    let severity = parse_severity(kind.split_whitespace().next()?)?;
+    Some((
+        severity,
+        source.trim().parse().ok()?,
+        line.trim().parse().ok()?,
+        message.trim().to_string(),
+    ))
+}
+
 /// Simplified [LoadShaders] for just loading vertex and fragment shader.
 pub fn load_shaders(
     gl: &Context,
     vertex_shader_source: impl Into<String>,
     fragment_shader_source: impl Into<String>,
-) -> NativeProgram {
+) -> Result<NativeProgram, ShaderError> {
     LoadShaders::new(vertex_shader_source, fragment_shader_source).compile(gl)
 }
 
-pub fn load_compute_shader(gl: &Context, compute_shader: impl Into<String>) -> NativeProgram {
-    compile_shaders(gl, &[(COMPUTE_SHADER, compute_shader.into())])
+pub fn load_compute_shader(
+    gl: &Context,
+    compute_shader: impl Into<String>,
+) -> Result<NativeProgram, ShaderError> {
+    compile_shaders(
+        gl,
+        &[PreparedShader {
+            stage: ShaderStage::Compute,
+            source_names: vec![ShaderStage::Compute.default_source_name().to_string()],
+            source: compute_shader.into(),
+            dependencies: vec![],
+        }],
+    )
 }
@@ -0,0 +1,243 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use super::ShaderError;
+
+/// Output of [preprocess]. `source_names[n]` is the file that `#line _ n` refers to.
+pub(super) struct Preprocessed {
+    pub source: String,
+    pub source_names: Vec<String>,
+    /// Files read from disk, including the top level file if it has a path.
+    pub dependencies: Vec<PathBuf>,
+}
+
+/// Where `#include` directives are looked up, in order: next to the including file (if it
+/// was read from disk), in `include_dirs` and finally among the `virtual_files`.
+pub(super) struct IncludeResolver<'a> {
+    pub include_dirs: &'a [PathBuf],
+    pub virtual_files: &'a HashMap<String, String>,
+}
+
+struct File {
+    /// Identifies the file for include-once and cycle checks.
+    key: String,
+    name: String,
+    path: Option<PathBuf>,
+    directory: Option<PathBuf>,
+    source: String,
+}
+
+impl IncludeResolver<'_> {
+    fn resolve(
+        &self,
+        directory: Option<&Path>,
+        include: &str,
+    ) -> Result<Option<File>, ShaderError> {
+        let disk_candidates = directory
+            .into_iter()
+            .chain(self.include_dirs.iter().map(PathBuf::as_path))
+            .map(|directory| directory.join(include));
+        for path in disk_candidates {
+            if path.is_file() {
+                return read_file(&path).map(Some);
+            }
+        }
+
+        Ok(self.virtual_files.get(include).map(|source| File {
+            key: format!("<virtual>/{include}"),
+            name: include.to_string(),
+            path: None,
+            directory: None,
+            source: source.clone(),
+        }))
+    }
+}
+
+fn read_file(path: &Path) -> Result<File, ShaderError> {
+    let source = std::fs::read_to_string(path).map_err(|error| ShaderError::Read {
+        path: path.to_path_buf(),
+        error: error.to_string(),
+    })?;
+    let key = path
+        .canonicalize()
+        .unwrap_or_else(|_| path.to_path_buf())
+        .display()
+        .to_string();
+    Ok(File {
+        key,
+        name: path.display().to_string(),
+        path: Some(path.to_path_buf()),
+        directory: path.parent().map(Path::to_path_buf),
+        source,
+    })
+}
+
+/// Resolves `#include "file"` directives in `source`.
+///
+/// Every file is included at most once per shader, so shared files don't need include
+/// guards. `#line` directives are inserted around included files to keep the line numbers
+/// in driver messages pointing into the right file.
+pub(super) fn preprocess(
+    name: &str,
+    path: Option<&Path>,
+    source: &str,
+    resolver: &IncludeResolver,
+) -> Result<Preprocessed, ShaderError> {
+    let file = File {
+        key: match path {
+            Some(path) => path
+                .canonicalize()
+                .unwrap_or_else(|_| path.to_path_buf())
+                .display()
+                .to_string(),
+            None => name.to_string(),
+        },
+        name: name.to_string(),
+        path: path.map(Path::to_path_buf),
+        directory: path.and_then(Path::parent).map(Path::to_path_buf),
+        source: source.to_string(),
+    };
+
+    let mut preprocessor = Preprocessor {
+        resolver,
+        source_names: vec![],
+        dependencies: vec![],
+        included: HashSet::new(),
+        stack: vec![],
+        output: String::with_capacity(source.len()),
+    };
+    preprocessor.process(file)?;
+
+    Ok(Preprocessed {
+        source: preprocessor.output,
+        source_names: preprocessor.source_names,
+        dependencies: preprocessor.dependencies,
+    })
+}
+
+struct Preprocessor<'a> {
+    resolver: &'a IncludeResolver<'a>,
+    source_names: Vec<String>,
+    dependencies: Vec<PathBuf>,
+    included: HashSet<String>,
+    /// Keys and names of the files currently being processed.
+    stack: Vec<(String, String)>,
+    output: String,
+}
+
+impl Preprocessor<'_> {
+    fn process(&mut self, file: File) -> Result<(), ShaderError> {
+        let file_index = self.source_names.len();
+        self.source_names.push(file.name.clone());
+        self.dependencies.extend(file.path.clone());
+        self.included.insert(file.key.clone());
+        self.stack.push((file.key.clone(), file.name.clone()));
+
+        for (line_index, line) in file.source.lines().enumerate() {
+            let line_number = line_index as u32 + 1;
+            let directive = directive(line);
+
+            if file_index != 0
+                && matches!(directive, Some(("version", _)) | Some(("pragma", "once")))
+            {
+                // Only the top level file may declare a version. Keep the line so that the
+                // numbering stays intact.
+                self.output.push('\n');
+                continue;
+            }
+
+            let Some(("include", argument)) = directive else {
+                self.output.push_str(line);
+                self.output.push('\n');
+                continue;
+            };
+
+            let include_error = |message: String| ShaderError::Include {
+                source_name: file.name.clone(),
+                line: line_number,
+                message,
+            };
+            let include = parse_include_argument(argument)
+                .ok_or_else(|| include_error(format!("malformed #include {argument}")))?;
+            let included_file = self
+                .resolver
+                .resolve(file.directory.as_deref(), include)?
+                .ok_or_else(|| include_error(format!("cannot find include file \"{include}\"")))?;
+
+            if self.stack.iter().any(|(key, _)| *key == included_file.key) {
+                let names: Vec<_> = self.stack.iter().map(|(_, name)| name.as_str()).collect();
+                return Err(include_error(format!(
+                    "\"{include}\" includes itself through {}",
+                    names.join(" -> ")
+                )));
+            }
+            if self.included.contains(&included_file.key) {
+                self.output.push('\n');
+                continue;
+            }
+
+            self.output
+                .push_str(&format!("#line 1 {}\n", self.source_names.len()));
+            self.process(included_file)?;
+            self.output
+                .push_str(&format!("#line {} {file_index}\n", line_number + 1));
+        }
+
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+/// Splits a preprocessor line like `#  include "a.glsl"` into `("include", "\"a.glsl\"")`.
+fn directive(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_start().strip_prefix('#')?.trim_start();
+    let end = line
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(line.len());
+    Some((&line[..end], line[end..].trim()))
+}
+
+fn parse_include_argument(argument: &str) -> Option<&str> {
+    argument
+        .strip_prefix('"')
+        .and_then(|rest| rest.split_once('"'))
+        .or_else(|| {
+            argument
+                .strip_prefix('<')
+                .and_then(|rest| rest.split_once('>'))
+        })
+        .map(|(include, _)| include)
+}
+
+/// Inserts `#define name value` lines after the `#version` directive, followed by a `#line`
+/// directive so that the line numbers of the rest of the source are unaffected.
+pub(super) fn inject_defines(source: &str, defines: &[(String, String)]) -> String {
+    if defines.is_empty() {
+        return source.to_string();
+    }
+
+    let mut define_lines = String::new();
+    for (name, value) in defines {
+        define_lines.push_str(&format!("#define {name} {value}\n"));
+    }
+
+    let version_line = source
+        .lines()
+        .position(|line| matches!(directive(line), Some(("version", _))));
+    let Some(version_line) = version_line else {
+        return format!("{define_lines}#line 1 0\n{source}");
+    };
+
+    let mut output = String::with_capacity(source.len() + define_lines.len());
+    for (line_index, line) in source.lines().enumerate() {
+        output.push_str(line);
+        output.push('\n');
+        if line_index == version_line {
+            output.push_str(&define_lines);
+            output.push_str(&format!("#line {} 0\n", line_index + 2));
+        }
+    }
+    output
+}
@@ -0,0 +1,163 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use glow::{Context, NativeProgram};
+
+use crate::Program;
+
+use super::{cache_key, compile_shaders, LoadShaders, PreparedShader, ProgramCache, ShaderError};
+
+/// How often the source files are checked for modifications.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A program that is rebuilt when any of its source files, including `#include`:d files,
+/// change on disk. Only sources given by path, e.g. through [LoadShaders::new_from_path],
+/// are watched.
+///
+/// Use [ReloadableProgram::program] every time the program is bound since the underlying
+/// program changes on reload. If a rebuild fails the previous program is kept and the error
+/// is available through [ReloadableProgram::error].
+pub struct ReloadableProgram {
+    shaders: LoadShaders,
+    program: Program,
+    programs: ProgramCache,
+    dependencies: Vec<(PathBuf, Option<SystemTime>)>,
+    error: Option<ShaderError>,
+    last_poll: Instant,
+    label: Option<String>,
+}
+
+fn modification_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn modification_times(prepared: &[PreparedShader]) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let mut dependencies: Vec<_> = prepared
+        .iter()
+        .flat_map(|shader| shader.dependencies.iter().cloned())
+        .collect();
+    dependencies.sort();
+    dependencies.dedup();
+    dependencies
+        .into_iter()
+        .map(|path| {
+            let modified = modification_time(&path);
+            (path, modified)
+        })
+        .collect()
+}
+
+impl ReloadableProgram {
+    pub fn new(gl: &Context, shaders: LoadShaders) -> Result<Self, ShaderError> {
+        let prepared = shaders.prepare()?;
+        let mut programs = ProgramCache::new();
+        let program = programs.get_or_compile_prepared(gl, &prepared)?;
+        Ok(ReloadableProgram {
+            shaders,
+            program: Program::new(gl, program),
+            programs,
+            dependencies: modification_times(&prepared),
+            error: None,
+            last_poll: Instant::now(),
+            label: None,
+        })
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Names the program in debug output and capture tools. The label is kept when the
+    /// program is reloaded or its shaders are changed.
+    pub fn label(&mut self, gl: &Context, label: impl Into<String>) {
+        let label = label.into();
+        self.program.label(gl, &label);
+        self.label = Some(label);
+    }
+
+    /// The error from the last failed rebuild, if the current sources don't compile.
+    pub fn error(&self) -> Option<&ShaderError> {
+        self.error.as_ref()
+    }
+
+    /// Switches to other shaders, e.g. another permutation of the same sources. Previously
+    /// used permutations are cached. Returns true if the program changed.
+    pub fn set_shaders(&mut self, gl: &Context, shaders: LoadShaders) -> bool {
+        self.shaders = shaders;
+        let result = self.shaders.prepare().and_then(|prepared| {
+            self.dependencies = modification_times(&prepared);
+            self.programs.get_or_compile_prepared(gl, &prepared)
+        });
+        self.swap(gl, result)
+    }
+
+    /// Rebuilds the program if any source file has been modified. Returns true if the
+    /// program changed, in which case uniforms and block bindings need to be set again.
+    pub fn poll(&mut self, gl: &Context) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let modified = self
+            .dependencies
+            .iter()
+            .any(|(path, modified)| modification_time(path) != *modified);
+        if !modified {
+            return false;
+        }
+
+        let result = self.shaders.prepare().and_then(|prepared| {
+            self.dependencies = modification_times(&prepared);
+            let program = compile_shaders(gl, &prepared)?;
+            // Cached permutations were built from the old sources
+            self.programs.clear(gl);
+            self.programs.programs.insert(cache_key(&prepared), program);
+            Ok(program)
+        });
+        self.swap(gl, result)
+    }
+
+    fn swap(&mut self, gl: &Context, result: Result<NativeProgram, ShaderError>) -> bool {
+        match result {
+            Ok(program) => {
+                self.error = None;
+                if program == self.program.id() {
+                    return false;
+                }
+                self.program = Program::new(gl, program);
+                if let Some(label) = &self.label {
+                    self.program.label(gl, label);
+                }
+                true
+            }
+            Err(error) => {
+                eprintln!("{error}");
+                // Don't retry until something is modified again
+                for (path, modified) in &mut self.dependencies {
+                    *modified = modification_time(path);
+                }
+                self.error = Some(error);
+                false
+            }
+        }
+    }
+
+    /// Shows the current error, if any, in a window.
+    #[cfg(feature = "imgui")]
+    pub fn error_window(&self, ui: &imgui::Ui, name: &str) {
+        if let Some(error) = &self.error {
+            ui.window(format!("Shader error: {name}"))
+                .always_auto_resize(true)
+                .build(|| ui.text_colored([1.0, 0.4, 0.4, 1.0], error.to_string()));
+        }
+    }
+
+    pub fn delete(mut self, gl: &Context) {
+        self.programs.clear(gl);
+    }
+}
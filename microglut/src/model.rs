@@ -1,117 +1,195 @@
-#![expect(clippy::missing_safety_doc)]
 use core::fmt;
 use std::{
     collections::HashMap,
     error::Error,
-    io::{BufRead, BufReader},
-    path::Path,
+    fs,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    rc::Rc,
     str::{FromStr, SplitWhitespace},
 };
 
-use glam::{Vec2, Vec3};
+use glam::{Vec2, Vec3, Vec4Swizzles as _};
 use glow::{
-    Buffer, Context, HasContext as _, NativeProgram, VertexArray, ARRAY_BUFFER,
-    ELEMENT_ARRAY_BUFFER, FLOAT, LINEAR, LINEAR_MIPMAP_LINEAR, MAX_TEXTURE_MAX_ANISOTROPY_EXT,
-    REPEAT, STATIC_DRAW, TEXTURE0, TEXTURE_2D, TEXTURE_MAG_FILTER, TEXTURE_MAX_ANISOTROPY_EXT,
-    TEXTURE_MIN_FILTER, TEXTURE_WRAP_R, TEXTURE_WRAP_S, TRIANGLES, UNSIGNED_INT,
+    Buffer, Context, HasContext as _, VertexArray, ARRAY_BUFFER, ELEMENT_ARRAY_BUFFER, LINEAR,
+    LINEAR_MIPMAP_LINEAR, MAX_TEXTURE_MAX_ANISOTROPY_EXT, REPEAT, STATIC_DRAW, TEXTURE0,
+    TEXTURE_2D, TEXTURE_MAG_FILTER, TEXTURE_MAX_ANISOTROPY_EXT, TEXTURE_MIN_FILTER, TEXTURE_WRAP_R,
+    TEXTURE_WRAP_S, TRIANGLES, UNSIGNED_INT,
 };
 
-use crate::Texture;
+use crate::{
+    delete_object, track_object, Aabb, BoundingSphere, Program, Texture, TextureCache,
+    TextureError, TextureOptions, TextureUnit, Uniform,
+};
+
+mod batch;
+mod gltf_import;
+mod obj;
+mod tangents;
+mod vertex;
+
+pub(crate) use batch::load_gl_functions;
+pub use batch::BatchedModel;
+pub use obj::ObjOptions;
+pub use tangents::{generate_tangents, TangentMesh};
+pub use vertex::Vertex;
 
 type MaterialLoader = dyn Fn(&Path) -> tobj::MTLLoadResult;
 type TextureLoader = dyn Fn(&str) -> Vec<u8>;
+/// Resolves the name of a texture map in a material to the path of its file.
+type TexturePathResolver = dyn Fn(&str) -> PathBuf;
 type TangentLoader = dyn Fn(&str) -> Vec<u8>;
 
-#[derive(Debug, Clone)]
+const OBJ_LOAD_OPTIONS: tobj::LoadOptions = tobj::LoadOptions {
+    // use the same index for every vertex/normal/texture coordinate
+    single_index: true,
+    ..tobj::GPU_LOAD_OPTIONS
+};
+
+/// Owns the GPU resources of its meshes and materials until [Model::delete] is called.
+/// Textures may be shared with other models.
+#[derive(Debug)]
 pub struct Model {
     pub meshes: Vec<Mesh>,
     material: Vec<Material>,
+    aabb: Aabb,
+    bounding_sphere: BoundingSphere,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug)]
 pub struct Mesh {
     vertex_array: VertexArray,
     vertex_buffer: Buffer,
-    normal_buffer: Option<Buffer>,
-    tangent_buffer: Option<Buffer>,
-    bitangent_buffer: Option<Buffer>,
-    texture_coordinate_buffer: Option<Buffer>,
     index_buffer: Buffer,
+    num_vertices: u32,
     num_indices: u32,
     pub material: Option<usize>, // index into Model.material, if any
+    aabb: Aabb,
+    bounding_sphere: BoundingSphere,
 }
 
-fn is_invalid_tangent(v: Vec3) -> bool {
-    return !v.is_finite() || (v.cmpgt(Vec3::ONE * -0.5).all() && v.cmplt(Vec3::ONE * 0.5).all());
-}
-
-fn is_invalid_bitangent(v: Vec3) -> bool {
-    return is_invalid_tangent(v);
-}
-
-fn reconstruct_tangent(tangent: Vec3, bitangent: Vec3, normal: Vec3) -> Vec3 {
-    if is_invalid_tangent(tangent) && !is_invalid_bitangent(bitangent) {
-        normal.cross(bitangent).normalize()
-    } else {
-        tangent
-    }
+/// Where the tangents and bitangents of a mesh come from.
+enum Tangents<'a> {
+    Given(&'a [Vec3], &'a [Vec3]),
+    Generate,
+    None,
 }
 
-fn reconstruct_bitangent(tangent: Vec3, bitangent: Vec3, normal: Vec3) -> Vec3 {
-    if is_invalid_bitangent(bitangent) && !is_invalid_tangent(tangent) {
-        tangent.cross(normal).normalize()
-    } else {
-        bitangent
-    }
-}
-
-fn parse_vec3(word: SplitWhitespace) -> Result<Vec3, LoadError> {
-    let v = word
+fn parse_vec3(words: SplitWhitespace) -> Result<Vec3, String> {
+    let v = words
         .take(3)
         .map(FromStr::from_str)
         .collect::<Result<Vec<f32>, _>>()
-        .map_err(|_| LoadError::ParseError)
-        .map(|v| Vec3::from_slice(&v))
-        .unwrap();
-    Ok(v)
+        .map_err(|error| error.to_string())?;
+    if v.len() < 3 {
+        return Err(format!("expected 3 numbers, found {}", v.len()));
+    }
+    Ok(Vec3::from_slice(&v))
 }
 
 #[derive(Debug)]
 pub enum LoadError {
-    ReadError,
-    ParseError,
+    /// A model or material library could not be read.
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// A file could not be parsed. `line` starts at 1.
+    Parse {
+        path: Option<PathBuf>,
+        line: Option<usize>,
+        message: String,
+    },
+    /// A texture referenced by a material could not be read.
+    MissingTexture {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// A texture could not be decoded or uploaded.
+    Texture {
+        path: Option<PathBuf>,
+        error: TextureError,
+    },
+    Gltf(gltf::Error),
 }
 
 impl fmt::Display for LoadError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let msg = match *self {
-            LoadError::ReadError => "read error",
-            LoadError::ParseError => "parse error",
-        };
-        f.write_str(msg)
+        match self {
+            LoadError::Io { path, error } => {
+                write!(f, "failed to read {}: {error}", path.display())
+            }
+            LoadError::Parse {
+                path,
+                line,
+                message,
+            } => {
+                if let Some(path) = path {
+                    write!(f, "{}:", path.display())?;
+                }
+                if let Some(line) = line {
+                    write!(f, "{line}:")?;
+                }
+                if path.is_some() || line.is_some() {
+                    f.write_str(" ")?;
+                }
+                write!(f, "parse error: {message}")
+            }
+            LoadError::MissingTexture { path, error } => {
+                write!(f, "missing texture {}: {error}", path.display())
+            }
+            LoadError::Texture {
+                path: Some(path),
+                error,
+            } => write!(f, "failed to load texture {}: {error}", path.display()),
+            LoadError::Texture { path: None, error } => {
+                write!(f, "failed to load texture: {error}")
+            }
+            LoadError::Gltf(error) => write!(f, "failed to load glTF: {error}"),
+        }
     }
 }
 
-impl Error for LoadError {}
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io { error, .. } | LoadError::MissingTexture { error, .. } => Some(error),
+            LoadError::Texture { error, .. } => Some(error),
+            LoadError::Gltf(error) => Some(error),
+            LoadError::Parse { .. } => None,
+        }
+    }
+}
+
+impl From<gltf::Error> for LoadError {
+    fn from(error: gltf::Error) -> Self {
+        LoadError::Gltf(error)
+    }
+}
+
+impl From<TextureError> for LoadError {
+    fn from(error: TextureError) -> Self {
+        LoadError::Texture { path: None, error }
+    }
+}
 
 pub fn load_tangent_buf(data: &[u8]) -> Result<(Vec<Vec3>, Vec<Vec3>), LoadError> {
     let reader = BufReader::new(data);
+    let parse_error = |line: usize, message: String| LoadError::Parse {
+        path: None,
+        line: Some(line + 1),
+        message,
+    };
 
     let mut tangents: Vec<Vec3> = vec![];
     let mut bitangents: Vec<Vec3> = vec![];
-    for line in reader.lines() {
-        let (line, mut words) = match line {
-            Ok(ref line) => (&line[..], line[..].split_whitespace()),
-            Err(_e) => {
-                #[cfg(feature = "log")]
-                log::error!("load_tangent_buf - failed to read line due to {}", _e);
-                return Err(LoadError::ReadError);
-            }
-        };
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.map_err(|error| parse_error(number, error.to_string()))?;
+        let mut words = line.split_whitespace();
         match words.next() {
             Some("#") | None => continue,
-            Some("t") => tangents.push(parse_vec3(words)?),
-            Some("bt") => bitangents.push(parse_vec3(words)?),
+            Some("t") => tangents.push(parse_vec3(words).map_err(|e| parse_error(number, e))?),
+            Some("bt") => bitangents.push(parse_vec3(words).map_err(|e| parse_error(number, e))?),
             Some(_) => {}
         }
     }
@@ -126,321 +204,178 @@ impl Mesh {
         name: &str,
         tangent_loader: Option<&TangentLoader>,
         generate_tangents: bool,
-    ) -> Self {
-        let (vertex_array, vertex_buffer, index_buffer) = unsafe {
-            (
-                gl.create_vertex_array().unwrap(),
-                gl.create_buffer().unwrap(),
-                gl.create_buffer().unwrap(),
-            )
-        };
-
-        let normals = if mesh_data.normals.is_empty() {
-            None
-        } else {
-            Some(mesh_data.normals)
-        };
-        let texture_coordinates = if mesh_data.texcoords.is_empty() {
-            None
-        } else {
-            Some(mesh_data.texcoords)
+    ) -> Result<Self, LoadError> {
+        let loaded_tangents = tangent_loader
+            .map(|tangent_loader| load_tangent_buf(&tangent_loader(name)))
+            .transpose()?;
+        let tangents = match &loaded_tangents {
+            Some((tangents, bitangents)) => Tangents::Given(tangents, bitangents),
+            None if generate_tangents => Tangents::Generate,
+            None => Tangents::None,
         };
-        let has_normals = normals.is_some();
-        let has_texture_coordinates = texture_coordinates.is_some();
-
-        let can_generate_tangets = generate_tangents && has_normals && has_texture_coordinates;
-
-        let mesh = Mesh {
-            vertex_array,
-            vertex_buffer,
-            index_buffer,
-            num_indices: mesh_data.indices.len() as u32,
-            normal_buffer: if has_normals {
-                unsafe { Some(gl.create_buffer().unwrap()) }
-            } else {
-                None
-            },
-            tangent_buffer: if tangent_loader.is_some() || can_generate_tangets {
-                unsafe { Some(gl.create_buffer().unwrap()) }
-            } else {
-                None
-            },
-            bitangent_buffer: if tangent_loader.is_some() || can_generate_tangets {
-                unsafe { Some(gl.create_buffer().unwrap()) }
-            } else {
-                None
-            },
-            texture_coordinate_buffer: if has_texture_coordinates {
-                unsafe { Some(gl.create_buffer().unwrap()) }
-            } else {
-                None
-            },
+        Ok(Self::from_data(
+            gl,
+            bytemuck::cast_slice(&mesh_data.positions),
+            (!mesh_data.normals.is_empty()).then(|| bytemuck::cast_slice(&mesh_data.normals)),
+            (!mesh_data.texcoords.is_empty()).then(|| bytemuck::cast_slice(&mesh_data.texcoords)),
+            tangents,
+            &mesh_data.indices,
             material,
-        };
+        ))
+    }
 
-        unsafe {
-            mesh.vertex_data_f32(gl, &mesh_data.positions);
-            mesh.index_data(gl, &mesh_data.indices);
-            if let Some(normals) = &normals {
-                mesh.normal_data_f32(gl, normals);
-            }
-            if let Some(texture_coordinates) = &texture_coordinates {
-                mesh.texture_data_f32(gl, texture_coordinates);
+    /// Creates a mesh from vertex attributes that share one index buffer. Tangents can only
+    /// be generated if the mesh has normals and texture coordinates.
+    fn from_data(
+        gl: &Context,
+        positions: &[Vec3],
+        normals: Option<&[Vec3]>,
+        texture_coordinates: Option<&[Vec2]>,
+        tangents: Tangents,
+        indices: &[u32],
+        material: Option<usize>,
+    ) -> Self {
+        let mut vertices: Vec<Vertex> = positions
+            .iter()
+            .map(|&position| Vertex {
+                position,
+                ..Default::default()
+            })
+            .collect();
+        if let Some(normals) = normals {
+            for (vertex, &normal) in vertices.iter_mut().zip(normals) {
+                vertex.normal = normal;
             }
-
-            if let Some(tangent_loader) = tangent_loader {
-                let (tangents, bitangents) = load_tangent_buf(&tangent_loader(name)).unwrap();
-                mesh.load_tangents(gl, &tangents, &bitangents);
+        }
+        if let Some(texture_coordinates) = texture_coordinates {
+            for (vertex, &texture_coordinate) in vertices.iter_mut().zip(texture_coordinates) {
+                vertex.texture_coordinate = texture_coordinate;
             }
-
-            if generate_tangents {
-                if let Some(normals) = &normals {
-                    if let Some(texture_coordinates) = &texture_coordinates {
-                        mesh.generate_tangents(
-                            gl,
-                            &mesh_data.indices,
-                            &mesh_data.positions,
-                            normals,
-                            texture_coordinates,
-                        );
-                    }
+        }
+        match (tangents, normals, texture_coordinates) {
+            (Tangents::Given(tangents, bitangents), _, _) => {
+                for (vertex, &tangent) in vertices.iter_mut().zip(tangents) {
+                    vertex.tangent = tangent;
+                }
+                for (vertex, &bitangent) in vertices.iter_mut().zip(bitangents) {
+                    vertex.bitangent = bitangent;
                 }
             }
+            (Tangents::Generate, Some(normals), Some(texture_coordinates)) => {
+                let generated =
+                    tangents::generate_tangents(positions, normals, texture_coordinates, indices);
+                let vertices: Vec<Vertex> = generated
+                    .source_vertices
+                    .iter()
+                    .zip(&generated.tangents)
+                    .map(|(&source, tangent)| {
+                        let vertex = vertices[source as usize];
+                        Vertex {
+                            tangent: tangent.xyz(),
+                            bitangent: vertex.normal.cross(tangent.xyz()) * tangent.w,
+                            ..vertex
+                        }
+                    })
+                    .collect();
+                return Self::from_vertices(gl, &vertices, &generated.indices, material);
+            }
+            _ => {}
         }
 
-        mesh
+        Self::from_vertices(gl, &vertices, indices, material)
     }
 
-    pub fn num_indices(&self) -> usize {
-        self.num_indices as usize
-    }
+    /// Uploads vertices and triangle indices. `material` indexes the materials of the model
+    /// the mesh becomes part of.
+    pub fn from_vertices(
+        gl: &Context,
+        vertices: &[Vertex],
+        indices: &[u32],
+        material: Option<usize>,
+    ) -> Self {
+        let aabb = Aabb::from_points(vertices.iter().map(|vertex| vertex.position));
+        let positions: Vec<Vec3> = vertices.iter().map(|vertex| vertex.position).collect();
 
-    pub unsafe fn vertex_data(&self, gl: &Context, data: &[Vec3]) {
-        self.vertex_data_f32(gl, bytemuck::cast_slice(data))
+        unsafe {
+            let vertex_array = track_object(gl.create_vertex_array().unwrap());
+            let vertex_buffer = track_object(gl.create_buffer().unwrap());
+            let index_buffer = track_object(gl.create_buffer().unwrap());
+
+            gl.bind_vertex_array(Some(vertex_array));
+            gl.bind_buffer(ARRAY_BUFFER, Some(vertex_buffer));
+            gl.buffer_data_u8_slice(ARRAY_BUFFER, bytemuck::cast_slice(vertices), STATIC_DRAW);
+            Vertex::configure_attributes(gl);
+            gl.bind_buffer(ELEMENT_ARRAY_BUFFER, Some(index_buffer));
+            gl.buffer_data_u8_slice(
+                ELEMENT_ARRAY_BUFFER,
+                bytemuck::cast_slice(indices),
+                STATIC_DRAW,
+            );
+            gl.bind_vertex_array(None);
+
+            Mesh {
+                vertex_array,
+                vertex_buffer,
+                index_buffer,
+                num_vertices: vertices.len() as u32,
+                num_indices: indices.len() as u32,
+                material,
+                aabb,
+                bounding_sphere: BoundingSphere::from_points(&positions),
+            }
+        }
     }
 
-    pub unsafe fn vertex_data_f32(&self, gl: &Context, data: &[f32]) {
-        gl.bind_vertex_array(Some(self.vertex_array));
-        gl.bind_buffer(ARRAY_BUFFER, Some(self.vertex_buffer));
-        gl.buffer_data_u8_slice(ARRAY_BUFFER, bytemuck::cast_slice(data), STATIC_DRAW);
+    pub fn num_vertices(&self) -> usize {
+        self.num_vertices as usize
     }
 
-    pub unsafe fn index_data(&self, gl: &Context, data: &[u32]) {
-        gl.bind_vertex_array(Some(self.vertex_array));
-        gl.bind_buffer(ELEMENT_ARRAY_BUFFER, Some(self.index_buffer));
-        gl.buffer_data_u8_slice(
-            ELEMENT_ARRAY_BUFFER,
-            bytemuck::cast_slice(data),
-            STATIC_DRAW,
-        );
+    pub fn num_indices(&self) -> usize {
+        self.num_indices as usize
     }
 
-    pub unsafe fn normal_data(&self, gl: &Context, data: &[Vec3]) {
-        self.normal_data_f32(gl, bytemuck::cast_slice(data))
+    /// Bounds of the vertex positions when the mesh was loaded.
+    pub fn aabb(&self) -> Aabb {
+        self.aabb
     }
 
-    pub unsafe fn normal_data_f32(&self, gl: &Context, data: &[f32]) {
-        // TODO: handle no buffer
-        if let Some(normal_buffer) = self.normal_buffer {
-            gl.bind_vertex_array(Some(self.vertex_array));
-            gl.bind_buffer(ARRAY_BUFFER, Some(normal_buffer));
-            gl.buffer_data_u8_slice(ARRAY_BUFFER, bytemuck::cast_slice(data), STATIC_DRAW);
-        }
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        self.bounding_sphere
     }
 
-    pub unsafe fn texture_data(&self, gl: &Context, data: &[Vec2]) {
-        self.texture_data_f32(gl, bytemuck::cast_slice(data))
+    pub fn vertex_array(&self) -> VertexArray {
+        self.vertex_array
     }
 
-    pub unsafe fn texture_data_f32(&self, gl: &Context, data: &[f32]) {
-        // TODO: handle no buffer
-        if let Some(texture_coordinate_buffer) = self.texture_coordinate_buffer {
-            gl.bind_vertex_array(Some(self.vertex_array));
-            gl.bind_buffer(ARRAY_BUFFER, Some(texture_coordinate_buffer));
-            gl.buffer_data_u8_slice(ARRAY_BUFFER, bytemuck::cast_slice(data), STATIC_DRAW);
-        }
+    /// The [Vertex] data.
+    pub fn vertex_buffer(&self) -> Buffer {
+        self.vertex_buffer
     }
 
-    pub unsafe fn generate_tangents(
-        &self,
-        gl: &Context,
-        indices: &[u32],
-        positions: &[f32],
-        normals: &[f32],
-        texture_coordinates: &[f32],
-    ) {
-        let vertices: Vec<(Vec3, Vec3, Vec2)> = indices
-            .iter()
-            .map(|i| {
-                let position = Vec3::new(
-                    positions[(i * 3) as usize],
-                    positions[(i * 3 + 1) as usize],
-                    positions[(i * 3 + 2) as usize],
-                );
-                let normal = Vec3::new(
-                    normals[(i * 3) as usize],
-                    normals[(i * 3 + 1) as usize],
-                    normals[(i * 3 + 2) as usize],
-                );
-                let tex_coord = Vec2::new(
-                    texture_coordinates[(i * 2) as usize],
-                    texture_coordinates[(i * 2 + 1) as usize],
-                );
-                (position, normal, tex_coord)
-            })
-            .collect();
-
-        let mut tangents: Vec<Vec3> = vec![];
-        let mut bitangents: Vec<Vec3> = vec![];
-
-        for face in indices.chunks_exact(3) {
-            let (p0, n0, t0) = vertices[face[0] as usize];
-            let (p1, n1, t1) = vertices[face[1] as usize];
-            let (p2, n2, t2) = vertices[face[2] as usize];
-
-            let v = p1 - p0;
-            let w = p2 - p0;
-
-            let mut sx = t1.x - t0.x;
-            let mut sy = t1.y - t0.y;
-            let mut tx = t2.x - t0.x;
-            let mut ty = t2.y - t0.y;
-            let dir_correction = if tx * sy - ty * sx < 0.0 { -1.0 } else { 1.0 };
-
-            if sx * ty == sy * tx {
-                sx = 0.0;
-                sy = 1.0;
-                tx = 1.0;
-                ty = 0.0;
-            }
-
-            let tangent = Vec3::new(
-                (w.x * sy - v.x * ty) * dir_correction,
-                (w.y * sy - v.y * ty) * dir_correction,
-                (w.z * sy - v.z * ty) * dir_correction,
-            );
-            let bitangent = Vec3::new(
-                (w.x * sx - v.x * tx) * dir_correction,
-                (w.y * sx - v.y * tx) * dir_correction,
-                (w.z * sx - v.z * tx) * dir_correction,
-            );
-
-            // Calculate local tangents and bitangents
-            let local_tangent0 = (tangent - n0 * n0.dot(tangent)).normalize();
-            let local_tangent1 = (tangent - n1 * n1.dot(tangent)).normalize();
-            let local_tangent2 = (tangent - n2 * n2.dot(tangent)).normalize();
-
-            let local_bitangent0 = (bitangent - n0 * n0.dot(bitangent)).normalize();
-            let local_bitangent1 = (bitangent - n1 * n1.dot(bitangent)).normalize();
-            let local_bitangent2 = (bitangent - n2 * n2.dot(bitangent)).normalize();
-            tangents.push(reconstruct_tangent(local_tangent0, local_bitangent0, n0));
-            tangents.push(reconstruct_tangent(local_tangent1, local_bitangent1, n1));
-            tangents.push(reconstruct_tangent(local_tangent2, local_bitangent2, n2));
-            bitangents.push(reconstruct_bitangent(local_tangent0, local_bitangent0, n0));
-            bitangents.push(reconstruct_bitangent(local_tangent1, local_bitangent1, n1));
-            bitangents.push(reconstruct_bitangent(local_tangent2, local_bitangent2, n2));
-        }
-
-        gl.bind_vertex_array(Some(self.vertex_array));
-        gl.bind_buffer(ARRAY_BUFFER, self.tangent_buffer);
-        gl.buffer_data_u8_slice(ARRAY_BUFFER, bytemuck::cast_slice(&tangents), STATIC_DRAW);
-
-        gl.bind_buffer(ARRAY_BUFFER, self.bitangent_buffer);
-        gl.buffer_data_u8_slice(ARRAY_BUFFER, bytemuck::cast_slice(&bitangents), STATIC_DRAW);
+    /// `u32` triangle indices.
+    pub fn index_buffer(&self) -> Buffer {
+        self.index_buffer
     }
 
-    pub unsafe fn load_tangents(&self, gl: &Context, tangents: &[Vec3], bitangents: &[Vec3]) {
-        if let Some(tangent_buffer) = self.tangent_buffer {
-            gl.bind_vertex_array(Some(self.vertex_array));
-            gl.bind_buffer(ARRAY_BUFFER, Some(tangent_buffer));
-            gl.buffer_data_u8_slice(ARRAY_BUFFER, bytemuck::cast_slice(tangents), STATIC_DRAW);
-        }
-        if let Some(bitangent_buffer) = self.bitangent_buffer {
-            gl.bind_vertex_array(Some(self.vertex_array));
-            gl.bind_buffer(ARRAY_BUFFER, Some(bitangent_buffer));
-            gl.buffer_data_u8_slice(ARRAY_BUFFER, bytemuck::cast_slice(bitangents), STATIC_DRAW);
-        }
+    /// Binds the vertex array, which has the attributes at the locations of [Vertex].
+    pub fn bind(&self, gl: &Context) {
+        unsafe { gl.bind_vertex_array(Some(self.vertex_array)) }
     }
 
-    pub fn bind(
-        &self,
-        gl: &Context,
-        program: NativeProgram,
-        vertex_binding: &str,
-        normal_binding: Option<&str>,
-        texture_binding: Option<&str>,
-        tangent_binding: Option<&str>,
-        bitangent_binding: Option<&str>,
-    ) {
-        unsafe {
-            gl.bind_vertex_array(Some(self.vertex_array));
-
-            gl.bind_buffer(ARRAY_BUFFER, Some(self.vertex_buffer));
-            let loc = gl.get_attrib_location(program, vertex_binding).unwrap();
-            gl.vertex_attrib_pointer_f32(loc, 3, FLOAT, false, 0, 0);
-            gl.enable_vertex_attrib_array(loc);
-
-            if let Some(normal_binding) = normal_binding {
-                if let Some(loc) = gl.get_attrib_location(program, normal_binding) {
-                    gl.bind_buffer(ARRAY_BUFFER, Some(self.normal_buffer.unwrap()));
-                    gl.vertex_attrib_pointer_f32(loc, 3, FLOAT, false, 0, 0);
-                    gl.enable_vertex_attrib_array(loc);
-                } else {
-                    // TODO: warn once
-                }
-            }
-
-            if let Some(texture_binding) = texture_binding {
-                if let Some(loc) = gl.get_attrib_location(program, texture_binding) {
-                    gl.bind_buffer(ARRAY_BUFFER, Some(self.texture_coordinate_buffer.unwrap()));
-                    gl.vertex_attrib_pointer_f32(loc, 2, FLOAT, false, 0, 0);
-                    gl.enable_vertex_attrib_array(loc);
-                } else {
-                    // TODO: warn once
-                }
-            }
-
-            if let Some(tangent_binding) = tangent_binding {
-                if let Some(loc) = gl.get_attrib_location(program, tangent_binding) {
-                    gl.bind_buffer(ARRAY_BUFFER, Some(self.tangent_buffer.unwrap()));
-                    gl.vertex_attrib_pointer_f32(loc, 3, FLOAT, false, 0, 0);
-                    gl.enable_vertex_attrib_array(loc);
-                }
-            }
-            if let Some(bitangent_binding) = bitangent_binding {
-                if let Some(loc) = gl.get_attrib_location(program, bitangent_binding) {
-                    gl.bind_buffer(ARRAY_BUFFER, Some(self.bitangent_buffer.unwrap()));
-                    gl.vertex_attrib_pointer_f32(loc, 3, FLOAT, false, 0, 0);
-                    gl.enable_vertex_attrib_array(loc);
-                }
-            }
-        }
+    pub fn draw(&self, gl: &Context) {
+        self.bind(gl);
+        unsafe { gl.draw_elements(TRIANGLES, self.num_indices as _, UNSIGNED_INT, 0) }
     }
 
-    pub fn draw(
-        &self,
-        gl: &Context,
-        program: NativeProgram,
-        vertex_binding: &str,
-        normal_binding: Option<&str>,
-        texture_binding: Option<&str>,
-        tangent_binding: Option<&str>,
-        bitangent_binding: Option<&str>,
-    ) {
-        self.bind(
-            gl,
-            program,
-            vertex_binding,
-            normal_binding,
-            texture_binding,
-            tangent_binding,
-            bitangent_binding,
-        );
-        unsafe { gl.draw_elements(TRIANGLES, self.num_indices as _, UNSIGNED_INT, 0) }
+    pub fn delete(self, gl: &Context) {
+        delete_object(gl, self.vertex_array);
+        delete_object(gl, self.vertex_buffer);
+        delete_object(gl, self.index_buffer);
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Texture maps are shared between the materials (and models) that use the same image.
+#[derive(Debug)]
 struct Material {
     ambient: Option<Vec3>,
     emissive: Option<Vec3>,
@@ -449,308 +384,265 @@ struct Material {
     shininess: Option<f32>,
     dissolve: Option<f32>,
     optical_density: Option<f32>,
-    ambient_texture: Option<Texture>,
-    diffuse_texture: Option<Texture>,
-    specular_texture: Option<Texture>,
-    normal_texture: Option<Texture>,
-    shininess_texture: Option<Texture>,
-    dissolve_texture: Option<Texture>,
+    ambient_texture: Option<Rc<Texture>>,
+    diffuse_texture: Option<Rc<Texture>>,
+    specular_texture: Option<Rc<Texture>>,
+    normal_texture: Option<Rc<Texture>>,
+    shininess_texture: Option<Rc<Texture>>,
+    dissolve_texture: Option<Rc<Texture>>,
     illumination_model: Option<u8>,
+    metallic: Option<f32>,
+    roughness: Option<f32>,
+    emissive_texture: Option<Rc<Texture>>,
+    occlusion_texture: Option<Rc<Texture>>,
+    metallic_roughness_texture: Option<Rc<Texture>>,
+}
+
+/// The uniform a material property is bound to, and the `has_<name>` uniform telling
+/// whether the material has it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaterialBinding {
+    name: String,
+    has_name: String,
 }
 
+impl MaterialBinding {
+    pub fn new(name: impl Into<String>) -> Self {
+        let name = name.into();
+        MaterialBinding {
+            has_name: format!("has_{name}"),
+            name,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn set(&self, gl: &Context, program: &Program, value: &dyn Uniform, has_value: bool) {
+        if let Some(location) = program.uniform_location(&self.name) {
+            value.set(gl, location);
+        }
+        if let Some(location) = program.uniform_location(&self.has_name) {
+            has_value.set(gl, location);
+        }
+    }
+}
+
+/// Uniforms (and texture units) to bind material properties to. Every property that is
+/// bound also sets a `has_<name>` uniform telling whether the material has it. Uniforms the
+/// program doesn't use are skipped.
+///
+/// Materials loaded from glTF set the base colour as `diffuse` and `diffuse_texture`, and its
+/// alpha as `dissolve`.
+#[derive(Debug, Clone, Default)]
 pub struct MaterialBindings {
-    pub ambient: Option<String>,
-    pub emissive: Option<String>,
-    pub diffuse: Option<String>,
-    pub specular: Option<String>,
-    pub shininess: Option<String>,
-    pub dissolve: Option<String>,
-    pub optical_density: Option<String>,
-    pub ambient_texture: Option<(String, u32)>,
-    pub diffuse_texture: Option<(String, u32)>,
-    pub specular_texture: Option<(String, u32)>,
-    pub normal_texture: Option<(String, u32)>,
-    pub shininess_texture: Option<(String, u32)>,
-    pub dissolve_texture: Option<(String, u32)>,
-    pub illumination_model: Option<String>,
+    pub ambient: Option<MaterialBinding>,
+    pub emissive: Option<MaterialBinding>,
+    pub diffuse: Option<MaterialBinding>,
+    pub specular: Option<MaterialBinding>,
+    pub shininess: Option<MaterialBinding>,
+    pub dissolve: Option<MaterialBinding>,
+    pub optical_density: Option<MaterialBinding>,
+    pub ambient_texture: Option<(MaterialBinding, u32)>,
+    pub diffuse_texture: Option<(MaterialBinding, u32)>,
+    pub specular_texture: Option<(MaterialBinding, u32)>,
+    pub normal_texture: Option<(MaterialBinding, u32)>,
+    pub shininess_texture: Option<(MaterialBinding, u32)>,
+    pub dissolve_texture: Option<(MaterialBinding, u32)>,
+    pub illumination_model: Option<MaterialBinding>,
+    pub metallic: Option<MaterialBinding>,
+    pub roughness: Option<MaterialBinding>,
+    pub emissive_texture: Option<(MaterialBinding, u32)>,
+    pub occlusion_texture: Option<(MaterialBinding, u32)>,
+    /// Roughness in the green channel and metalness in the blue channel.
+    pub metallic_roughness_texture: Option<(MaterialBinding, u32)>,
+}
+
+/// Sampling of OBJ texture maps. Normal maps are filtered anisotropically.
+fn obj_texture_options(gl: &Context, normal_map: bool) -> TextureOptions {
+    if normal_map {
+        let max_anisotropy = unsafe { gl.get_parameter_f32(MAX_TEXTURE_MAX_ANISOTROPY_EXT) };
+        TextureOptions::new()
+            .gen_mipmap(true)
+            .int_param(TEXTURE_WRAP_S, REPEAT as _)
+            .int_param(TEXTURE_WRAP_R, REPEAT as _)
+            .int_param(TEXTURE_MIN_FILTER, LINEAR_MIPMAP_LINEAR as _)
+            .int_param(TEXTURE_MAG_FILTER, LINEAR as _)
+            .float_param(TEXTURE_MAX_ANISOTROPY_EXT, max_anisotropy)
+    } else {
+        TextureOptions::new().gen_mipmap(true)
+    }
 }
 
 impl Material {
-    fn new(gl: &Context, material: tobj::Material, texture_loader: Option<&TextureLoader>) -> Self {
-        let emissive = material.unknown_param.get("Ke").map(|word| {
-            word.split_whitespace()
-                .take(3)
-                .map(FromStr::from_str)
-                .collect::<Result<Vec<f32>, _>>()
-                .map(|v| Vec3::from_slice(&v))
-                .unwrap()
-        });
-
-        if let Some(tex_loader) = texture_loader {
-            unsafe {
-                let ambient_texture = material
-                    .ambient_texture
-                    .map(|texture_name| tex_loader(&texture_name))
-                    .map(|data| Texture::load(gl, &data, true));
-                let diffuse_texture = material
-                    .diffuse_texture
-                    .map(|texture_name| tex_loader(&texture_name))
-                    .map(|data| Texture::load(gl, &data, true));
-                let specular_texture = material
-                    .specular_texture
-                    .map(|texture_name| tex_loader(&texture_name))
-                    .map(|data| Texture::load(gl, &data, true));
-
-                let max_anisotropy = gl.get_parameter_f32(MAX_TEXTURE_MAX_ANISOTROPY_EXT);
-                let normal_texture = material
-                    .normal_texture
-                    .map(|texture_name| tex_loader(&texture_name))
-                    .map(|data| {
-                        Texture::load_with_parameters(
-                            gl,
-                            &data,
-                            &[
-                                (TEXTURE_WRAP_S, REPEAT as _),
-                                (TEXTURE_WRAP_R, REPEAT as _),
-                                (TEXTURE_MIN_FILTER, LINEAR_MIPMAP_LINEAR as _),
-                                (TEXTURE_MAG_FILTER, LINEAR as _),
-                            ],
-                            &[(TEXTURE_MAX_ANISOTROPY_EXT, max_anisotropy)],
-                            true,
-                        )
-                    });
-                let shininess_texture = material
-                    .shininess_texture
-                    .map(|texture_name| tex_loader(&texture_name))
-                    .map(|data| Texture::load(gl, &data, true));
-                let dissolve_texture = material
-                    .dissolve_texture
-                    .map(|texture_name| tex_loader(&texture_name))
-                    .map(|data| Texture::load(gl, &data, true));
-                Material {
-                    ambient: material.ambient.map(Vec3::from_array),
-                    emissive,
-                    diffuse: material.diffuse.map(Vec3::from_array),
-                    specular: material.specular.map(Vec3::from_array),
-                    shininess: material.shininess,
-                    dissolve: material.dissolve,
-                    optical_density: material.optical_density,
-                    ambient_texture,
-                    diffuse_texture,
-                    specular_texture,
-                    normal_texture,
-                    shininess_texture,
-                    dissolve_texture,
-                    illumination_model: material.illumination_model,
-                }
-            }
-        } else {
-            Material {
-                ambient: material.ambient.map(Vec3::from_array),
-                emissive,
-                diffuse: material.diffuse.map(Vec3::from_array),
-                specular: material.specular.map(Vec3::from_array),
-                shininess: material.shininess,
-                dissolve: material.dissolve,
-                optical_density: material.optical_density,
-                ambient_texture: None,
-                diffuse_texture: None,
-                specular_texture: None,
-                normal_texture: None,
-                shininess_texture: None,
-                dissolve_texture: None,
-                illumination_model: material.illumination_model,
-            }
-        }
+    /// Converts an OBJ material, loading its texture maps with `load_texture`, which gets
+    /// the name of the map and whether it is a normal map.
+    fn from_obj(
+        material: tobj::Material,
+        mut load_texture: impl FnMut(&str, bool) -> Result<Option<Rc<Texture>>, LoadError>,
+    ) -> Result<Self, LoadError> {
+        let emissive =
+            match material.unknown_param.get("Ke") {
+                Some(words) => Some(parse_vec3(words.split_whitespace()).map_err(|message| {
+                    LoadError::Parse {
+                        path: None,
+                        line: None,
+                        message: format!("material {}: Ke: {message}", material.name),
+                    }
+                })?),
+                None => None,
+            };
+
+        let mut load = |texture_name: Option<String>, normal_map: bool| match texture_name {
+            Some(texture_name) => load_texture(&texture_name, normal_map),
+            None => Ok(None),
+        };
+        Ok(Material {
+            ambient: material.ambient.map(Vec3::from_array),
+            emissive,
+            diffuse: material.diffuse.map(Vec3::from_array),
+            specular: material.specular.map(Vec3::from_array),
+            shininess: material.shininess,
+            dissolve: material.dissolve,
+            optical_density: material.optical_density,
+            ambient_texture: load(material.ambient_texture, false)?,
+            diffuse_texture: load(material.diffuse_texture, false)?,
+            specular_texture: load(material.specular_texture, false)?,
+            normal_texture: load(material.normal_texture, true)?,
+            shininess_texture: load(material.shininess_texture, false)?,
+            dissolve_texture: load(material.dissolve_texture, false)?,
+            illumination_model: material.illumination_model,
+            metallic: None,
+            roughness: None,
+            emissive_texture: None,
+            occlusion_texture: None,
+            metallic_roughness_texture: None,
+        })
     }
 
-    fn bind(&self, gl: &Context, program: NativeProgram, bindings: &MaterialBindings) {
-        unsafe {
-            if let Some(ambient_binding) = &bindings.ambient {
-                gl.uniform_3_f32_slice(
-                    gl.get_uniform_location(program, &ambient_binding).as_ref(),
-                    self.ambient.unwrap_or_default().as_ref(),
-                );
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &format!("has_{}", ambient_binding))
-                        .as_ref(),
-                    self.ambient.is_some() as i32,
-                );
-            }
-            if let Some(emissive_binding) = &bindings.emissive {
-                gl.uniform_3_f32_slice(
-                    gl.get_uniform_location(program, &emissive_binding).as_ref(),
-                    self.emissive.unwrap_or_default().as_ref(),
-                );
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &format!("has_{}", emissive_binding))
-                        .as_ref(),
-                    self.emissive.is_some() as i32,
-                );
-            }
-            if let Some(diffuse_binding) = &bindings.diffuse {
-                gl.uniform_3_f32_slice(
-                    gl.get_uniform_location(program, &diffuse_binding).as_ref(),
-                    self.diffuse.unwrap_or_default().as_ref(),
-                );
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &format!("has_{}", diffuse_binding))
-                        .as_ref(),
-                    self.diffuse.is_some() as i32,
-                );
-            }
-            if let Some(specular_binding) = &bindings.specular {
-                gl.uniform_3_f32_slice(
-                    gl.get_uniform_location(program, &specular_binding).as_ref(),
-                    self.specular.unwrap_or_default().as_ref(),
-                );
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &format!("has_{}", specular_binding))
-                        .as_ref(),
-                    self.specular.is_some() as i32,
-                );
-            }
-            if let Some(shininess_binding) = &bindings.shininess {
-                gl.uniform_1_f32(
-                    gl.get_uniform_location(program, &shininess_binding)
-                        .as_ref(),
-                    self.shininess.unwrap_or_default(),
-                );
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &format!("has_{}", shininess_binding))
-                        .as_ref(),
-                    self.shininess.is_some() as i32,
-                );
-            }
-            if let Some(dissolve_binding) = &bindings.dissolve {
-                gl.uniform_1_f32(
-                    gl.get_uniform_location(program, &dissolve_binding).as_ref(),
-                    self.dissolve.unwrap_or_default(),
-                );
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &format!("has_{}", dissolve_binding))
-                        .as_ref(),
-                    self.dissolve.is_some() as i32,
-                );
-            }
-            if let Some(optical_density_binding) = &bindings.optical_density {
-                gl.uniform_1_f32(
-                    gl.get_uniform_location(program, &optical_density_binding)
-                        .as_ref(),
-                    self.optical_density.unwrap_or_default(),
-                );
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &format!("has_{}", optical_density_binding))
-                        .as_ref(),
-                    self.optical_density.is_some() as i32,
-                );
-            }
+    /// The texture maps in the order of the `MATERIAL_*_TEXTURE` slots of `materials.glsl`.
+    fn textures(&self) -> [Option<&Texture>; 9] {
+        [
+            &self.diffuse_texture,
+            &self.normal_texture,
+            &self.specular_texture,
+            &self.dissolve_texture,
+            &self.ambient_texture,
+            &self.shininess_texture,
+            &self.emissive_texture,
+            &self.occlusion_texture,
+            &self.metallic_roughness_texture,
+        ]
+        .map(Option::as_deref)
+    }
 
-            if let Some((ambient_texture_binding, texture_unit)) = &bindings.ambient_texture {
-                gl.active_texture(TEXTURE0 + texture_unit);
-                gl.bind_texture(TEXTURE_2D, self.ambient_texture.map(|t| t.id()));
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &ambient_texture_binding)
-                        .as_ref(),
-                    *texture_unit as i32,
-                );
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &format!("has_{}", ambient_texture_binding))
-                        .as_ref(),
-                    self.ambient_texture.is_some() as i32,
-                );
+    /// Deletes the textures that no other material uses any more.
+    fn delete(self, gl: &Context) {
+        for texture in [
+            self.diffuse_texture,
+            self.normal_texture,
+            self.specular_texture,
+            self.dissolve_texture,
+            self.ambient_texture,
+            self.shininess_texture,
+            self.emissive_texture,
+            self.occlusion_texture,
+            self.metallic_roughness_texture,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if let Some(texture) = Rc::into_inner(texture) {
+                texture.delete(gl);
             }
-            if let Some((diffuse_texture_binding, texture_unit)) = &bindings.diffuse_texture {
-                gl.active_texture(TEXTURE0 + texture_unit);
-                gl.bind_texture(TEXTURE_2D, self.diffuse_texture.map(|t| t.id()));
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &diffuse_texture_binding)
-                        .as_ref(),
-                    *texture_unit as i32,
-                );
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &format!("has_{}", diffuse_texture_binding))
-                        .as_ref(),
-                    self.diffuse_texture.is_some() as i32,
-                );
-            }
-            if let Some((specular_texture_binding, texture_unit)) = &bindings.specular_texture {
-                gl.active_texture(TEXTURE0 + texture_unit);
-                gl.bind_texture(TEXTURE_2D, self.specular_texture.map(|t| t.id()));
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &specular_texture_binding)
-                        .as_ref(),
-                    *texture_unit as i32,
-                );
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &format!("has_{}", specular_texture_binding))
-                        .as_ref(),
-                    self.specular_texture.is_some() as i32,
-                );
-            }
-            if let Some((normal_texture_binding, texture_unit)) = &bindings.normal_texture {
-                gl.active_texture(TEXTURE0 + texture_unit);
-                gl.bind_texture(TEXTURE_2D, self.normal_texture.map(|t| t.id()));
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &normal_texture_binding)
-                        .as_ref(),
-                    *texture_unit as i32,
-                );
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &format!("has_{}", normal_texture_binding))
-                        .as_ref(),
-                    self.normal_texture.is_some() as i32,
-                );
-            }
-            if let Some((shininess_texture_binding, texture_unit)) = &bindings.shininess_texture {
-                gl.active_texture(TEXTURE0 + texture_unit);
-                gl.bind_texture(TEXTURE_2D, self.shininess_texture.map(|t| t.id()));
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &shininess_texture_binding)
-                        .as_ref(),
-                    *texture_unit as i32,
-                );
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &format!("has_{}", shininess_texture_binding))
-                        .as_ref(),
-                    self.shininess_texture.is_some() as i32,
-                );
-            }
-            if let Some((dissolve_texture_binding, texture_unit)) = &bindings.dissolve_texture {
-                gl.active_texture(TEXTURE0 + texture_unit);
-                gl.bind_texture(TEXTURE_2D, self.dissolve_texture.map(|t| t.id()));
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &dissolve_texture_binding)
-                        .as_ref(),
-                    *texture_unit as i32,
-                );
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(program, &format!("has_{}", dissolve_texture_binding))
-                        .as_ref(),
-                    self.dissolve_texture.is_some() as i32,
-                );
+        }
+    }
+
+    /// Sets the uniforms of `bindings` that are active in `program`, which has to be in use.
+    fn bind(&self, gl: &Context, program: &Program, bindings: &MaterialBindings) {
+        fn set<T: Uniform + Default>(
+            gl: &Context,
+            program: &Program,
+            binding: &Option<MaterialBinding>,
+            value: Option<T>,
+        ) {
+            if let Some(binding) = binding {
+                let has_value = value.is_some();
+                binding.set(gl, program, &value.unwrap_or_default(), has_value);
             }
+        }
+        set(gl, program, &bindings.ambient, self.ambient);
+        set(gl, program, &bindings.emissive, self.emissive);
+        set(gl, program, &bindings.diffuse, self.diffuse);
+        set(gl, program, &bindings.specular, self.specular);
+        set(gl, program, &bindings.shininess, self.shininess);
+        set(gl, program, &bindings.dissolve, self.dissolve);
+        set(gl, program, &bindings.optical_density, self.optical_density);
+        set(gl, program, &bindings.metallic, self.metallic);
+        set(gl, program, &bindings.roughness, self.roughness);
+        set(
+            gl,
+            program,
+            &bindings.illumination_model,
+            self.illumination_model.map(u32::from),
+        );
 
-            if let Some(illumination_model_binding) = &bindings.illumination_model {
-                gl.uniform_1_u32(
-                    gl.get_uniform_location(program, &illumination_model_binding)
-                        .as_ref(),
-                    self.illumination_model.unwrap_or_default() as u32,
-                );
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(
-                        program,
-                        &format!("has_{}", illumination_model_binding),
-                    )
-                    .as_ref(),
-                    self.illumination_model.is_some() as i32,
-                );
+        let textures = [
+            (&bindings.ambient_texture, &self.ambient_texture),
+            (&bindings.diffuse_texture, &self.diffuse_texture),
+            (&bindings.specular_texture, &self.specular_texture),
+            (&bindings.normal_texture, &self.normal_texture),
+            (&bindings.shininess_texture, &self.shininess_texture),
+            (&bindings.dissolve_texture, &self.dissolve_texture),
+            (&bindings.emissive_texture, &self.emissive_texture),
+            (&bindings.occlusion_texture, &self.occlusion_texture),
+            (
+                &bindings.metallic_roughness_texture,
+                &self.metallic_roughness_texture,
+            ),
+        ];
+        for (binding, texture) in textures {
+            if let Some((binding, texture_unit)) = binding {
+                unsafe {
+                    gl.active_texture(TEXTURE0 + texture_unit);
+                    gl.bind_texture(TEXTURE_2D, texture.as_ref().map(|t| t.id()));
+                }
+                binding.set(gl, program, &TextureUnit(*texture_unit), texture.is_some());
             }
         }
     }
 }
 
 impl Model {
+    fn new(meshes: Vec<Mesh>, material: Vec<Material>) -> Self {
+        Model {
+            aabb: meshes.iter().map(Mesh::aabb).collect(),
+            bounding_sphere: meshes.iter().map(Mesh::bounding_sphere).collect(),
+            meshes,
+            material,
+        }
+    }
+
+    /// Bounds of all meshes in model space, computed at load time.
+    pub fn aabb(&self) -> Aabb {
+        self.aabb
+    }
+
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        self.bounding_sphere
+    }
+
+    /// Deletes the meshes, and the textures that are not shared with another model.
+    pub fn delete(self, gl: &Context) {
+        for mesh in self.meshes {
+            mesh.delete(gl);
+        }
+        for material in self.material {
+            material.delete(gl);
+        }
+    }
+
     pub fn load_raw_data(
         gl: &Context,
         vertices: &[f32],
@@ -759,52 +651,16 @@ impl Model {
         _colors: Option<&[f32]>,
         indices: &[u32],
     ) -> Self {
-        let has_normals = normals.is_some();
-        let has_texture_coordinates = texture_coordinates.is_some();
-
-        let (vertex_array, vertex_buffer, index_buffer) = unsafe {
-            (
-                gl.create_vertex_array().unwrap(),
-                gl.create_buffer().unwrap(),
-                gl.create_buffer().unwrap(),
-            )
-        };
-
-        let mesh = Mesh {
-            vertex_array,
-            vertex_buffer,
-            index_buffer,
-            num_indices: indices.len() as u32,
-            normal_buffer: if has_normals {
-                unsafe { Some(gl.create_buffer().unwrap()) }
-            } else {
-                None
-            },
-            texture_coordinate_buffer: if has_texture_coordinates {
-                unsafe { Some(gl.create_buffer().unwrap()) }
-            } else {
-                None
-            },
-            tangent_buffer: None,
-            bitangent_buffer: None,
-            material: None,
-        };
-
-        unsafe {
-            mesh.vertex_data_f32(gl, vertices);
-            mesh.index_data(gl, indices);
-            if let Some(normals) = &normals {
-                mesh.normal_data_f32(gl, normals);
-            }
-            if let Some(texture_coordinates) = &texture_coordinates {
-                mesh.texture_data_f32(gl, texture_coordinates);
-            }
-        }
-
-        Model {
-            meshes: vec![mesh],
-            material: Vec::new(),
-        }
+        let mesh = Mesh::from_data(
+            gl,
+            bytemuck::cast_slice(vertices),
+            normals.map(bytemuck::cast_slice),
+            texture_coordinates.map(bytemuck::cast_slice),
+            Tangents::None,
+            indices,
+            None,
+        );
+        Model::new(vec![mesh], Vec::new())
     }
 
     // TODO: load_obj builder function? if more params are needed
@@ -816,72 +672,142 @@ impl Model {
         tangent_loader: Option<&TangentLoader>,
         generate_tangents: bool,
     ) -> Self {
-        let model = tobj::load_obj_buf(
-            &mut BufReader::new(data),
-            &tobj::LoadOptions {
-                // use the same index for every vertex/normal/texture coordinate
-                single_index: true,
-                ..tobj::GPU_LOAD_OPTIONS
+        // The names of the maps are only known to refer to the same files within this
+        // model, so they key a cache of its own
+        let mut textures = TextureCache::new();
+        Self::load_obj_data_with(
+            gl,
+            data,
+            material_loader,
+            tangent_loader,
+            generate_tangents,
+            |texture_name, normal_map| match texture_loader {
+                Some(tex_loader) => {
+                    let options = obj_texture_options(gl, normal_map);
+                    let texture =
+                        textures.get_or_load(Path::new(texture_name), &options, || {
+                            Texture::load_with_options(gl, &tex_loader(texture_name), &options)
+                        })?;
+                    Ok(Some(texture))
+                }
+                None => Ok(None),
             },
-            |path| match &material_loader {
-                Some(f) => f(path),
-                None => Ok((Vec::new(), HashMap::new())),
+        )
+        .unwrap()
+    }
+
+    /// Like [Model::load_obj_data], sharing textures with other models loaded through
+    /// `texture_cache`. Texture maps are read from the files that `texture_path` resolves
+    /// their names to, and cached by those paths.
+    pub fn load_obj_data_with_cache(
+        gl: &Context,
+        data: &[u8],
+        material_loader: Option<&MaterialLoader>,
+        texture_path: Option<&TexturePathResolver>,
+        tangent_loader: Option<&TangentLoader>,
+        generate_tangents: bool,
+        texture_cache: &mut TextureCache,
+    ) -> Result<Self, LoadError> {
+        Self::load_obj_data_with(
+            gl,
+            data,
+            material_loader,
+            tangent_loader,
+            generate_tangents,
+            |texture_name, normal_map| {
+                let Some(texture_path) = texture_path else {
+                    return Ok(None);
+                };
+                let path = texture_path(texture_name);
+                let options = obj_texture_options(gl, normal_map);
+                let texture = texture_cache.get_or_load(&path, &options, || {
+                    let data = fs::read(&path).map_err(|error| LoadError::MissingTexture {
+                        path: path.clone(),
+                        error,
+                    })?;
+                    Texture::load_with_options(gl, &data, &options).map_err(|error| {
+                        LoadError::Texture {
+                            path: Some(path.clone()),
+                            error,
+                        }
+                    })
+                })?;
+                Ok(Some(texture))
             },
         )
-        .unwrap();
+    }
+
+    fn load_obj_data_with(
+        gl: &Context,
+        data: &[u8],
+        material_loader: Option<&MaterialLoader>,
+        tangent_loader: Option<&TangentLoader>,
+        generate_tangents: bool,
+        mut load_texture: impl FnMut(&str, bool) -> Result<Option<Rc<Texture>>, LoadError>,
+    ) -> Result<Self, LoadError> {
+        let load_material_library = |path: &Path| match &material_loader {
+            Some(f) => f(path),
+            None => Ok((Vec::new(), HashMap::new())),
+        };
+        let (models, materials) = tobj::load_obj_buf(
+            &mut BufReader::new(data),
+            &OBJ_LOAD_OPTIONS,
+            load_material_library,
+        )
+        .map_err(|error| LoadError::Parse {
+            path: None,
+            line: obj::find_error_line(data, |prefix| {
+                tobj::load_obj_buf(&mut BufReader::new(prefix), &OBJ_LOAD_OPTIONS, |_| {
+                    Ok((Vec::new(), HashMap::new()))
+                })
+                .is_ok()
+            }),
+            message: error.to_string(),
+        })?;
+        let materials = materials.map_err(|error| LoadError::Parse {
+            path: None,
+            line: None,
+            message: format!("failed to load material library: {error}"),
+        })?;
 
-        let meshes = model
-            .0
-            .into_iter()
-            .map(|mesh| {
-                let material_id = mesh.mesh.material_id;
-                Mesh::new(
-                    gl,
-                    mesh.mesh,
-                    material_id,
-                    &mesh.name,
-                    tangent_loader,
-                    generate_tangents,
-                )
-            })
-            .collect();
         // TODO: can we have materials without textures?
-        let material = model
-            .1
-            .unwrap()
+        let material: Vec<Material> = materials
             .into_iter()
-            .map(|material| Material::new(gl, material, texture_loader))
-            .collect();
+            .map(|material| Material::from_obj(material, &mut load_texture))
+            .collect::<Result<_, _>>()?;
+
+        let mut meshes = Vec::with_capacity(models.len());
+        for model in models {
+            let material_id = model.mesh.material_id;
+            let mesh = Mesh::new(
+                gl,
+                model.mesh,
+                material_id,
+                &model.name,
+                tangent_loader,
+                generate_tangents,
+            );
+            match mesh {
+                Ok(mesh) => meshes.push(mesh),
+                Err(error) => {
+                    Model::new(meshes, material).delete(gl);
+                    return Err(error);
+                }
+            }
+        }
 
-        Model { meshes, material }
+        Ok(Model::new(meshes, material))
     }
 
+    /// Draws every mesh, binding its material first if `material_bindings` are given.
     pub fn draw(
         &self,
         gl: &Context,
-        program: NativeProgram,
-        vertex_binding: &str,
-        normal_binding: Option<&str>,
-        texture_binding: Option<&str>,
-        tangent_binding: Option<&str>,
-        bitangent_binding: Option<&str>,
+        program: &Program,
         material_bindings: Option<&MaterialBindings>,
     ) {
-        for mesh in &self.meshes {
-            if let Some(material) = mesh.material {
-                if let Some(m) = material_bindings {
-                    self.material[material].bind(gl, program, m);
-                }
-            }
-            mesh.draw(
-                gl,
-                program,
-                vertex_binding,
-                normal_binding,
-                texture_binding,
-                tangent_binding,
-                bitangent_binding,
-            );
+        for mesh_idx in 0..self.meshes.len() {
+            self.draw_mesh(gl, mesh_idx, program, material_bindings);
         }
     }
 
@@ -889,12 +815,7 @@ impl Model {
         &self,
         gl: &Context,
         mesh_idx: usize,
-        program: NativeProgram,
-        vertex_binding: &str,
-        normal_binding: Option<&str>,
-        texture_binding: Option<&str>,
-        tangent_binding: Option<&str>,
-        bitangent_binding: Option<&str>,
+        program: &Program,
         material_bindings: Option<&MaterialBindings>,
     ) {
         let Some(mesh) = self.meshes.get(mesh_idx) else {
@@ -905,14 +826,6 @@ impl Model {
                 self.material[material].bind(gl, program, m);
             }
         }
-        mesh.draw(
-            gl,
-            program,
-            vertex_binding,
-            normal_binding,
-            texture_binding,
-            tangent_binding,
-            bitangent_binding,
-        );
+        mesh.draw(gl);
     }
 }
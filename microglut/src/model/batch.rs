@@ -0,0 +1,483 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::c_void,
+    sync::OnceLock,
+};
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec3, Vec4};
+use glow::{
+    Buffer, Context, HasContext as _, NativeTexture, VertexArray, ARRAY_BUFFER, COPY_READ_BUFFER,
+    COPY_WRITE_BUFFER, DRAW_INDIRECT_BUFFER, ELEMENT_ARRAY_BUFFER, MAX_TEXTURE_MAX_ANISOTROPY_EXT,
+    REPEAT, SHADER_STORAGE_BUFFER, STATIC_DRAW, TEXTURE_2D, TEXTURE_2D_ARRAY,
+    TEXTURE_MAX_ANISOTROPY_EXT, TRIANGLES, UNSIGNED_INT,
+};
+
+use super::{Material, Model, Vertex};
+use crate::{delete_object, track_object, Program, Texture, TextureArray2D, TextureError};
+
+type MultiDrawElementsIndirect = unsafe extern "system" fn(
+    mode: u32,
+    element_type: u32,
+    indirect: *const c_void,
+    draw_count: i32,
+    stride: i32,
+);
+
+// glow does not wrap glMultiDrawElementsIndirect, so it is looked up when the context is
+// created. It is missing before OpenGL 4.3.
+static MULTI_DRAW_ELEMENTS_INDIRECT: OnceLock<Option<MultiDrawElementsIndirect>> = OnceLock::new();
+
+pub(crate) fn load_gl_functions(loader: impl Fn(&str) -> *const c_void) {
+    let function = loader("glMultiDrawElementsIndirect");
+    let function = (!function.is_null()).then(|| unsafe {
+        std::mem::transmute::<*const c_void, MultiDrawElementsIndirect>(function)
+    });
+    let _ = MULTI_DRAW_ELEMENTS_INDIRECT.set(function);
+}
+
+/// Layout of `DrawElementsIndirectCommand`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct DrawCommand {
+    count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    base_instance: u32,
+}
+
+/// A material as laid out in `materials.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct GpuMaterial {
+    diffuse: Vec4,
+    emissive: Vec4,
+    specular: Vec4,
+    ambient: Vec4,
+    metallic: f32,
+    roughness: f32,
+    flags: u32,
+    illumination_model: i32,
+    textures: [i32; 12],
+}
+
+const HAS_AMBIENT: u32 = 1;
+const HAS_EMISSIVE: u32 = 2;
+const HAS_DIFFUSE: u32 = 4;
+const HAS_SPECULAR: u32 = 8;
+const HAS_SHININESS: u32 = 16;
+const HAS_DISSOLVE: u32 = 32;
+const HAS_OPTICAL_DENSITY: u32 = 64;
+const HAS_METALLIC: u32 = 128;
+const HAS_ROUGHNESS: u32 = 256;
+
+impl GpuMaterial {
+    /// What meshes without a material get.
+    const NONE: GpuMaterial = GpuMaterial {
+        diffuse: Vec4::ONE,
+        emissive: Vec4::ZERO,
+        specular: Vec4::ZERO,
+        ambient: Vec4::ZERO,
+        metallic: 0.0,
+        roughness: 1.0,
+        flags: 0,
+        illumination_model: -1,
+        textures: [-1; 12],
+    };
+
+    fn new(material: &Material, texture_ref: impl Fn(Option<&Texture>) -> i32) -> Self {
+        let flag = |present: bool, flag: u32| if present { flag } else { 0 };
+        let flags = flag(material.ambient.is_some(), HAS_AMBIENT)
+            | flag(material.emissive.is_some(), HAS_EMISSIVE)
+            | flag(material.diffuse.is_some(), HAS_DIFFUSE)
+            | flag(material.specular.is_some(), HAS_SPECULAR)
+            | flag(material.shininess.is_some(), HAS_SHININESS)
+            | flag(material.dissolve.is_some(), HAS_DISSOLVE)
+            | flag(material.optical_density.is_some(), HAS_OPTICAL_DENSITY)
+            | flag(material.metallic.is_some(), HAS_METALLIC)
+            | flag(material.roughness.is_some(), HAS_ROUGHNESS);
+
+        let mut textures = [-1; 12];
+        for (slot, texture) in material.textures().into_iter().enumerate() {
+            textures[slot] = texture_ref(texture);
+        }
+
+        GpuMaterial {
+            diffuse: material
+                .diffuse
+                .unwrap_or(Vec3::ONE)
+                .extend(material.dissolve.unwrap_or(1.0)),
+            emissive: material.emissive.unwrap_or_default().extend(0.0),
+            specular: material
+                .specular
+                .unwrap_or_default()
+                .extend(material.shininess.unwrap_or_default()),
+            ambient: material
+                .ambient
+                .unwrap_or_default()
+                .extend(material.optical_density.unwrap_or(1.0)),
+            metallic: material.metallic.unwrap_or_default(),
+            roughness: material.roughness.unwrap_or(1.0),
+            flags,
+            illumination_model: material.illumination_model.map_or(-1, i32::from),
+            textures,
+        }
+    }
+}
+
+/// A [Model] packed into shared buffers so that all of its meshes are drawn with a single
+/// `glMultiDrawElementsIndirect`, see [Model::batch].
+///
+/// Materials are read from a storage buffer instead of uniforms, and textures from texture
+/// arrays, one for every combination of size and format. Shaders include
+/// [BatchedModel::MATERIALS_GLSL] for the declarations and get the material index of each
+/// draw at [BatchedModel::MATERIAL_INDEX_LOCATION].
+#[derive(Debug)]
+pub struct BatchedModel {
+    vertex_array: VertexArray,
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    material_index_buffer: Buffer,
+    command_buffer: Buffer,
+    material_buffer: Buffer,
+    texture_arrays: Vec<TextureArray2D>,
+    draw_count: i32,
+}
+
+impl BatchedModel {
+    /// The `uint` attribute holding the index of the material of a draw.
+    pub const MATERIAL_INDEX_LOCATION: u32 = 5;
+
+    /// GLSL declarations of the material table, the texture arrays and functions to sample
+    /// them.
+    pub const MATERIALS_GLSL: &'static str = include_str!("materials.glsl");
+
+    /// Textures in arrays beyond this are left out. Matches `MAX_MATERIAL_TEXTURE_ARRAYS` and
+    /// the cases of `material_texture` in [BatchedModel::MATERIALS_GLSL].
+    pub const MAX_TEXTURE_ARRAYS: usize = 16;
+
+    /// Number of meshes, which is the number of draws.
+    pub fn draw_count(&self) -> usize {
+        self.draw_count as usize
+    }
+
+    pub fn texture_arrays(&self) -> &[TextureArray2D] {
+        &self.texture_arrays
+    }
+
+    /// Binds the material table to storage buffer binding `table_binding` and the texture
+    /// arrays to the units starting at `first_texture_unit`. `program` has to be in use.
+    pub fn bind_materials(
+        &self,
+        gl: &Context,
+        program: &Program,
+        table_binding: u32,
+        first_texture_unit: u32,
+    ) {
+        unsafe {
+            gl.bind_buffer_base(
+                SHADER_STORAGE_BUFFER,
+                table_binding,
+                Some(self.material_buffer),
+            );
+        }
+        program.bind_storage_block(gl, "MaterialTable", table_binding);
+
+        for (i, texture_array) in self.texture_arrays.iter().enumerate() {
+            texture_array.bind(gl, first_texture_unit + i as u32);
+        }
+        // Every sampler in the array needs a unit of the right type, even the unused ones
+        let last = self.texture_arrays.len().saturating_sub(1) as u32;
+        let units: Vec<i32> = (0..Self::MAX_TEXTURE_ARRAYS as u32)
+            .map(|i| (first_texture_unit + i.min(last)) as i32)
+            .collect();
+        if let Some(location) = program.uniform_location("material_textures") {
+            unsafe { gl.uniform_1_i32_slice(Some(location), &units) };
+        }
+    }
+
+    /// Draws all meshes. The program in use needs the material table and texture arrays
+    /// bound with [BatchedModel::bind_materials] if it reads them.
+    pub fn draw(&self, gl: &Context) {
+        unsafe {
+            gl.bind_vertex_array(Some(self.vertex_array));
+            gl.bind_buffer(DRAW_INDIRECT_BUFFER, Some(self.command_buffer));
+            match MULTI_DRAW_ELEMENTS_INDIRECT.get().copied().flatten() {
+                Some(multi_draw_elements_indirect) => multi_draw_elements_indirect(
+                    TRIANGLES,
+                    UNSIGNED_INT,
+                    std::ptr::null(),
+                    self.draw_count,
+                    0,
+                ),
+                None => {
+                    for i in 0..self.draw_count {
+                        gl.draw_elements_indirect_offset(
+                            TRIANGLES,
+                            UNSIGNED_INT,
+                            i * size_of::<DrawCommand>() as i32,
+                        );
+                    }
+                }
+            }
+            gl.bind_buffer(DRAW_INDIRECT_BUFFER, None);
+        }
+    }
+
+    pub fn delete(self, gl: &Context) {
+        delete_object(gl, self.vertex_array);
+        for buffer in [
+            self.vertex_buffer,
+            self.index_buffer,
+            self.material_index_buffer,
+            self.command_buffer,
+            self.material_buffer,
+        ] {
+            delete_object(gl, buffer);
+        }
+        for texture_array in self.texture_arrays {
+            texture_array.delete(gl);
+        }
+    }
+}
+
+impl Model {
+    /// Packs the meshes, materials and textures of the model for drawing with
+    /// [BatchedModel::draw]. Everything is copied on the GPU, so the batch stays valid if the
+    /// model is deleted.
+    ///
+    /// The texture arrays repeat and filter trilinearly (and anisotropically if supported),
+    /// whatever the sampling of the original textures.
+    ///
+    /// Bindless textures are out of scope: materials refer to layers of at most
+    /// [BatchedModel::MAX_TEXTURE_ARRAYS] texture arrays, which works without
+    /// `ARB_bindless_texture`.
+    pub fn batch(&self, gl: &Context) -> Result<BatchedModel, TextureError> {
+        let (texture_arrays, texture_refs) = pack_textures(gl, &self.material)?;
+        let texture_ref = |texture: Option<&Texture>| {
+            texture
+                .and_then(|texture| texture_refs.get(&texture.id()).copied())
+                .unwrap_or(-1)
+        };
+        let materials: Vec<GpuMaterial> = self
+            .material
+            .iter()
+            .map(|material| GpuMaterial::new(material, texture_ref))
+            .chain([GpuMaterial::NONE])
+            .collect();
+        let no_material = self.material.len() as u32;
+
+        let mut commands = Vec::with_capacity(self.meshes.len());
+        let (mut num_vertices, mut num_indices) = (0, 0);
+        for (i, mesh) in self.meshes.iter().enumerate() {
+            commands.push(DrawCommand {
+                count: mesh.num_indices,
+                instance_count: 1,
+                first_index: num_indices,
+                base_vertex: num_vertices as i32,
+                // Selects the material index of the draw
+                base_instance: i as u32,
+            });
+            num_vertices += mesh.num_vertices;
+            num_indices += mesh.num_indices;
+        }
+        let material_indices: Vec<u32> = self
+            .meshes
+            .iter()
+            .map(|mesh| {
+                mesh.material
+                    .map_or(no_material, |material| material as u32)
+            })
+            .collect();
+
+        unsafe {
+            let vertex_array = track_object(gl.create_vertex_array().unwrap());
+            let vertex_buffer = track_object(gl.create_buffer().unwrap());
+            let index_buffer = track_object(gl.create_buffer().unwrap());
+            let material_index_buffer = track_object(gl.create_buffer().unwrap());
+            let command_buffer = track_object(gl.create_buffer().unwrap());
+            let material_buffer = track_object(gl.create_buffer().unwrap());
+
+            gl.bind_vertex_array(Some(vertex_array));
+
+            let vertex_size = size_of::<Vertex>() as u32;
+            gl.bind_buffer(ARRAY_BUFFER, Some(vertex_buffer));
+            gl.buffer_data_size(ARRAY_BUFFER, (num_vertices * vertex_size) as _, STATIC_DRAW);
+            let mut offset = 0;
+            for mesh in &self.meshes {
+                let size = mesh.num_vertices * vertex_size;
+                copy_buffer(gl, mesh.vertex_buffer, vertex_buffer, offset, size);
+                offset += size;
+            }
+            Vertex::configure_attributes(gl);
+
+            gl.bind_buffer(ARRAY_BUFFER, Some(material_index_buffer));
+            gl.buffer_data_u8_slice(
+                ARRAY_BUFFER,
+                bytemuck::cast_slice(&material_indices),
+                STATIC_DRAW,
+            );
+            let location = BatchedModel::MATERIAL_INDEX_LOCATION;
+            gl.enable_vertex_attrib_array(location);
+            gl.vertex_attrib_pointer_i32(location, 1, UNSIGNED_INT, 0, 0);
+            gl.vertex_attrib_divisor(location, 1);
+
+            gl.bind_buffer(ELEMENT_ARRAY_BUFFER, Some(index_buffer));
+            gl.buffer_data_size(
+                ELEMENT_ARRAY_BUFFER,
+                (num_indices * size_of::<u32>() as u32) as _,
+                STATIC_DRAW,
+            );
+            let mut offset = 0;
+            for mesh in &self.meshes {
+                let size = mesh.num_indices * size_of::<u32>() as u32;
+                copy_buffer(gl, mesh.index_buffer, index_buffer, offset, size);
+                offset += size;
+            }
+
+            gl.bind_vertex_array(None);
+
+            gl.bind_buffer(DRAW_INDIRECT_BUFFER, Some(command_buffer));
+            gl.buffer_data_u8_slice(
+                DRAW_INDIRECT_BUFFER,
+                bytemuck::cast_slice(&commands),
+                STATIC_DRAW,
+            );
+            gl.bind_buffer(DRAW_INDIRECT_BUFFER, None);
+
+            gl.bind_buffer(SHADER_STORAGE_BUFFER, Some(material_buffer));
+            gl.buffer_data_u8_slice(
+                SHADER_STORAGE_BUFFER,
+                bytemuck::cast_slice(&materials),
+                STATIC_DRAW,
+            );
+            gl.bind_buffer(SHADER_STORAGE_BUFFER, None);
+
+            Ok(BatchedModel {
+                vertex_array,
+                vertex_buffer,
+                index_buffer,
+                material_index_buffer,
+                command_buffer,
+                material_buffer,
+                texture_arrays,
+                draw_count: commands.len() as i32,
+            })
+        }
+    }
+}
+
+unsafe fn copy_buffer(gl: &Context, from: Buffer, to: Buffer, offset: u32, size: u32) {
+    gl.bind_buffer(COPY_READ_BUFFER, Some(from));
+    gl.bind_buffer(COPY_WRITE_BUFFER, Some(to));
+    gl.copy_buffer_sub_data(
+        COPY_READ_BUFFER,
+        COPY_WRITE_BUFFER,
+        0,
+        offset as _,
+        size as _,
+    );
+    gl.bind_buffer(COPY_READ_BUFFER, None);
+    gl.bind_buffer(COPY_WRITE_BUFFER, None);
+}
+
+/// Width, height, internal format and mip levels, which the textures in an array share.
+type ArrayKey = (u32, u32, u32, i32);
+
+/// Copies the textures of the materials into arrays of textures with the same size, format
+/// and number of mip levels. Returns the arrays and where each texture ended up, as
+/// `array << 16 | layer`.
+fn pack_textures(
+    gl: &Context,
+    materials: &[Material],
+) -> Result<(Vec<TextureArray2D>, HashMap<NativeTexture, i32>), TextureError> {
+    let mut groups: Vec<(ArrayKey, Vec<&Texture>)> = Vec::new();
+    let mut seen = HashSet::new();
+    for texture in materials
+        .iter()
+        .flat_map(|material| material.textures().into_iter().flatten())
+    {
+        if !seen.insert(texture.id()) {
+            continue;
+        }
+        let key = (
+            texture.width(),
+            texture.height(),
+            texture.internal_format(),
+            texture.levels(),
+        );
+        match groups.iter_mut().find(|(group_key, _)| *group_key == key) {
+            Some((_, textures)) => textures.push(texture),
+            None => groups.push((key, vec![texture])),
+        }
+    }
+    if groups.len() > BatchedModel::MAX_TEXTURE_ARRAYS {
+        eprintln!(
+            "Batched model: textures need {} arrays, only the first {} are used",
+            groups.len(),
+            BatchedModel::MAX_TEXTURE_ARRAYS
+        );
+        groups.truncate(BatchedModel::MAX_TEXTURE_ARRAYS);
+    }
+
+    let max_anisotropy = {
+        let extensions = gl.supported_extensions();
+        if extensions.contains("GL_EXT_texture_filter_anisotropic")
+            || extensions.contains("GL_ARB_texture_filter_anisotropic")
+        {
+            unsafe { gl.get_parameter_f32(MAX_TEXTURE_MAX_ANISOTROPY_EXT) }
+        } else {
+            1.0
+        }
+    };
+
+    let mut texture_arrays = Vec::with_capacity(groups.len());
+    let mut texture_refs = HashMap::new();
+    for (array_index, ((width, height, internal_format, levels), textures)) in
+        groups.into_iter().enumerate()
+    {
+        let texture_array = TextureArray2D::new(
+            gl,
+            internal_format,
+            width,
+            height,
+            textures.len() as u32,
+            levels > 1,
+        )?;
+        texture_array.set_wrap(gl, REPEAT);
+        if levels > 1 && max_anisotropy > 1.0 {
+            unsafe {
+                gl.bind_texture(TEXTURE_2D_ARRAY, Some(texture_array.id()));
+                gl.tex_parameter_f32(TEXTURE_2D_ARRAY, TEXTURE_MAX_ANISOTROPY_EXT, max_anisotropy);
+                gl.bind_texture(TEXTURE_2D_ARRAY, None);
+            }
+        }
+        for (layer, texture) in textures.iter().enumerate() {
+            for level in 0..levels {
+                unsafe {
+                    gl.copy_image_sub_data(
+                        texture.id(),
+                        TEXTURE_2D,
+                        level,
+                        0,
+                        0,
+                        0,
+                        texture_array.id(),
+                        TEXTURE_2D_ARRAY,
+                        level,
+                        0,
+                        0,
+                        layer as _,
+                        (width >> level).max(1) as _,
+                        (height >> level).max(1) as _,
+                        1,
+                    );
+                }
+            }
+            texture_refs.insert(texture.id(), ((array_index as i32) << 16) | layer as i32);
+        }
+        texture_arrays.push(texture_array);
+    }
+    Ok((texture_arrays, texture_refs))
+}
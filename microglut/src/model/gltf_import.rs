@@ -0,0 +1,362 @@
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use glam::{Mat3, Mat4, Vec2, Vec3};
+use glow::{
+    Context, HasContext as _, MAX_TEXTURE_MAX_ANISOTROPY_EXT, TEXTURE_MAG_FILTER,
+    TEXTURE_MAX_ANISOTROPY_EXT, TEXTURE_MIN_FILTER, TEXTURE_WRAP_S, TEXTURE_WRAP_T,
+};
+use gltf::{
+    image::{Format, Source},
+    mesh::Mode,
+    texture::{MinFilter, Sampler},
+    Document,
+};
+use image::{DynamicImage, ImageBuffer};
+
+use super::{LoadError, Material, Mesh, Model, Tangents};
+use crate::{ColorSpace, Texture, TextureCache, TextureError, TextureOptions};
+
+impl Model {
+    /// Loads a glTF 2.0 model, either a `.gltf` file with its buffers and images next to it
+    /// or a binary `.glb`.
+    ///
+    /// The meshes of the default scene are loaded with the transforms of their nodes
+    /// applied, so a mesh used by several nodes becomes several [Mesh]es. Texture
+    /// coordinates are flipped vertically to match those of OBJ files, so the same shaders
+    /// work for both.
+    pub fn load_gltf(gl: &Context, path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        Self::load_gltf_with_cache(gl, path, &mut TextureCache::new())
+    }
+
+    /// Like [Model::load_gltf], sharing textures with other models loaded through
+    /// `texture_cache`. Images in separate files are keyed by their path, embedded ones by
+    /// the path of the model and their index.
+    pub fn load_gltf_with_cache(
+        gl: &Context,
+        path: impl AsRef<Path>,
+        texture_cache: &mut TextureCache,
+    ) -> Result<Self, LoadError> {
+        let path = path.as_ref();
+        let (document, buffers, images) = gltf::import(path)?;
+        Self::from_gltf(gl, &document, &buffers, &images, Some(path), texture_cache)
+    }
+
+    /// Like [Model::load_gltf] for a file in memory, e.g. from `include_bytes!`. Buffers and
+    /// images must be embedded, which they always are in a `.glb`.
+    pub fn load_gltf_data(gl: &Context, data: &[u8]) -> Result<Self, LoadError> {
+        let (document, buffers, images) = gltf::import_slice(data)?;
+        Self::from_gltf(
+            gl,
+            &document,
+            &buffers,
+            &images,
+            None,
+            &mut TextureCache::new(),
+        )
+    }
+
+    fn from_gltf(
+        gl: &Context,
+        document: &Document,
+        buffers: &[gltf::buffer::Data],
+        images: &[gltf::image::Data],
+        path: Option<&Path>,
+        texture_cache: &mut TextureCache,
+    ) -> Result<Self, LoadError> {
+        let mut textures = TextureLoader::new(gl, images, path, texture_cache);
+        let material = document
+            .materials()
+            .map(|material| load_material(&mut textures, &material))
+            .collect::<Result<_, _>>()?;
+
+        let mut meshes = Vec::new();
+        match document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+        {
+            Some(scene) => {
+                for node in scene.nodes() {
+                    load_node(gl, &node, Mat4::IDENTITY, buffers, &mut meshes);
+                }
+            }
+            None => {
+                for mesh in document.meshes() {
+                    load_mesh(gl, &mesh, Mat4::IDENTITY, buffers, &mut meshes);
+                }
+            }
+        }
+
+        Ok(Model::new(meshes, material))
+    }
+}
+
+fn load_node(
+    gl: &Context,
+    node: &gltf::Node,
+    parent_transform: Mat4,
+    buffers: &[gltf::buffer::Data],
+    meshes: &mut Vec<Mesh>,
+) {
+    let transform = parent_transform * Mat4::from_cols_array_2d(&node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        load_mesh(gl, &mesh, transform, buffers, meshes);
+    }
+    for child in node.children() {
+        load_node(gl, &child, transform, buffers, meshes);
+    }
+}
+
+fn load_mesh(
+    gl: &Context,
+    mesh: &gltf::Mesh,
+    transform: Mat4,
+    buffers: &[gltf::buffer::Data],
+    meshes: &mut Vec<Mesh>,
+) {
+    let normal_matrix = Mat3::from_mat4(transform).inverse().transpose();
+    // Mirroring transforms turn counter-clockwise triangles clockwise
+    let flip_winding = transform.determinant() < 0.0;
+
+    for primitive in mesh.primitives() {
+        // Points, lines and strips are not supported
+        if primitive.mode() != Mode::Triangles {
+            continue;
+        }
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let Some(positions) = reader.read_positions() else {
+            continue;
+        };
+
+        let positions: Vec<Vec3> = positions
+            .map(|position| transform.transform_point3(Vec3::from(position)))
+            .collect();
+        let normals: Option<Vec<Vec3>> = reader.read_normals().map(|normals| {
+            normals
+                .map(|normal| (normal_matrix * Vec3::from(normal)).normalize())
+                .collect()
+        });
+        let texture_coordinates: Option<Vec<Vec2>> = reader
+            .read_tex_coords(0)
+            .map(|uvs| uvs.into_f32().map(|[u, v]| Vec2::new(u, 1.0 - v)).collect());
+        let tangents: Option<(Vec<Vec3>, Vec<Vec3>)> = match (reader.read_tangents(), &normals) {
+            (Some(tangents), Some(normals)) => Some(
+                tangents
+                    .zip(normals)
+                    .map(|([x, y, z, handedness], normal)| {
+                        let tangent = transform.transform_vector3(Vec3::new(x, y, z)).normalize();
+                        (tangent, normal.cross(tangent) * handedness)
+                    })
+                    .unzip(),
+            ),
+            _ => None,
+        };
+
+        let mut indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        if flip_winding {
+            for triangle in indices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+
+        meshes.push(Mesh::from_data(
+            gl,
+            &positions,
+            normals.as_deref(),
+            texture_coordinates.as_deref(),
+            match &tangents {
+                Some((tangents, bitangents)) => Tangents::Given(tangents, bitangents),
+                None => Tangents::Generate,
+            },
+            &indices,
+            primitive.material().index(),
+        ));
+    }
+}
+
+/// Maps a metallic-roughness material onto [Material]. The base colour becomes the diffuse
+/// colour and texture, and its alpha the dissolve.
+fn load_material(
+    textures: &mut TextureLoader,
+    material: &gltf::Material,
+) -> Result<Material, LoadError> {
+    let pbr = material.pbr_metallic_roughness();
+    let [r, g, b, a] = pbr.base_color_factor();
+
+    Ok(Material {
+        ambient: None,
+        emissive: Some(Vec3::from(material.emissive_factor())),
+        diffuse: Some(Vec3::new(r, g, b)),
+        specular: None,
+        shininess: None,
+        dissolve: Some(a),
+        optical_density: None,
+        ambient_texture: None,
+        diffuse_texture: textures.load(
+            pbr.base_color_texture().map(|info| info.texture()),
+            ColorSpace::Srgb,
+        )?,
+        specular_texture: None,
+        normal_texture: textures.load(
+            material.normal_texture().map(|info| info.texture()),
+            ColorSpace::Linear,
+        )?,
+        shininess_texture: None,
+        dissolve_texture: None,
+        illumination_model: None,
+        metallic: Some(pbr.metallic_factor()),
+        roughness: Some(pbr.roughness_factor()),
+        emissive_texture: textures.load(
+            material.emissive_texture().map(|info| info.texture()),
+            ColorSpace::Srgb,
+        )?,
+        occlusion_texture: textures.load(
+            material.occlusion_texture().map(|info| info.texture()),
+            ColorSpace::Linear,
+        )?,
+        metallic_roughness_texture: textures.load(
+            pbr.metallic_roughness_texture().map(|info| info.texture()),
+            ColorSpace::Linear,
+        )?,
+    })
+}
+
+/// Uploads the images of a glTF file through a [TextureCache], once for every combination of
+/// sampler and colour space they are used with.
+struct TextureLoader<'a> {
+    gl: &'a Context,
+    images: &'a [gltf::image::Data],
+    path: Option<&'a Path>,
+    cache: &'a mut TextureCache,
+    max_anisotropy: f32,
+}
+
+impl<'a> TextureLoader<'a> {
+    fn new(
+        gl: &'a Context,
+        images: &'a [gltf::image::Data],
+        path: Option<&'a Path>,
+        cache: &'a mut TextureCache,
+    ) -> Self {
+        let extensions = gl.supported_extensions();
+        let max_anisotropy = if extensions.contains("GL_EXT_texture_filter_anisotropic")
+            || extensions.contains("GL_ARB_texture_filter_anisotropic")
+        {
+            unsafe { gl.get_parameter_f32(MAX_TEXTURE_MAX_ANISOTROPY_EXT) }
+        } else {
+            1.0
+        };
+        TextureLoader {
+            gl,
+            images,
+            path,
+            cache,
+            max_anisotropy,
+        }
+    }
+
+    fn load(
+        &mut self,
+        texture: Option<gltf::Texture>,
+        color_space: ColorSpace,
+    ) -> Result<Option<Rc<Texture>>, TextureError> {
+        let Some(texture) = texture else {
+            return Ok(None);
+        };
+        let image = texture.source();
+        let options = self.options(&texture.sampler(), color_space);
+        let gl = self.gl;
+        let data = &self.images[image.index()];
+        let key = self.image_key(&image);
+        let texture = self.cache.get_or_load(&key, &options, || {
+            Texture::from_image(gl, to_dynamic_image(data)?, &options)
+        })?;
+        Ok(Some(texture))
+    }
+
+    /// The path of an image file next to the model, or the path of the model followed by
+    /// the index of an embedded image.
+    fn image_key(&self, image: &gltf::Image) -> PathBuf {
+        match (image.source(), self.path) {
+            (Source::Uri { uri, .. }, Some(path)) if !uri.starts_with("data:") => {
+                path.parent().unwrap_or(Path::new("")).join(uri)
+            }
+            (_, Some(path)) => format!("{}#{}", path.display(), image.index()).into(),
+            (_, None) => format!("#{}", image.index()).into(),
+        }
+    }
+
+    fn options(&self, sampler: &Sampler, color_space: ColorSpace) -> TextureOptions {
+        let mipmapped = !matches!(
+            sampler.min_filter(),
+            Some(MinFilter::Nearest | MinFilter::Linear)
+        );
+        let mut options = TextureOptions::new()
+            .color_space(color_space)
+            .gen_mipmap(mipmapped)
+            .int_param(TEXTURE_WRAP_S, sampler.wrap_s().as_gl_enum() as _)
+            .int_param(TEXTURE_WRAP_T, sampler.wrap_t().as_gl_enum() as _);
+        if let Some(filter) = sampler.min_filter() {
+            options = options.int_param(TEXTURE_MIN_FILTER, filter.as_gl_enum() as _);
+        }
+        if let Some(filter) = sampler.mag_filter() {
+            options = options.int_param(TEXTURE_MAG_FILTER, filter.as_gl_enum() as _);
+        }
+        if mipmapped && self.max_anisotropy > 1.0 {
+            options = options.float_param(TEXTURE_MAX_ANISOTROPY_EXT, self.max_anisotropy);
+        }
+        options
+    }
+}
+
+fn to_dynamic_image(data: &gltf::image::Data) -> Result<DynamicImage, TextureError> {
+    let (width, height) = (data.width, data.height);
+    let pixels = &data.pixels;
+    let image =
+        match data.format {
+            Format::R8 => {
+                ImageBuffer::from_raw(width, height, pixels.clone()).map(DynamicImage::ImageLuma8)
+            }
+            Format::R8G8 => {
+                ImageBuffer::from_raw(width, height, pixels.clone()).map(DynamicImage::ImageLumaA8)
+            }
+            Format::R8G8B8 => {
+                ImageBuffer::from_raw(width, height, pixels.clone()).map(DynamicImage::ImageRgb8)
+            }
+            Format::R8G8B8A8 => {
+                ImageBuffer::from_raw(width, height, pixels.clone()).map(DynamicImage::ImageRgba8)
+            }
+            Format::R16 => ImageBuffer::from_raw(width, height, cast_pixels(pixels))
+                .map(DynamicImage::ImageLuma16),
+            Format::R16G16 => ImageBuffer::from_raw(width, height, cast_pixels(pixels))
+                .map(DynamicImage::ImageLumaA16),
+            Format::R16G16B16 => ImageBuffer::from_raw(width, height, cast_pixels(pixels))
+                .map(DynamicImage::ImageRgb16),
+            Format::R16G16B16A16 => ImageBuffer::from_raw(width, height, cast_pixels(pixels))
+                .map(DynamicImage::ImageRgba16),
+            Format::R32G32B32FLOAT => ImageBuffer::from_raw(width, height, cast_pixels(pixels))
+                .map(DynamicImage::ImageRgb32F),
+            Format::R32G32B32A32FLOAT => ImageBuffer::from_raw(width, height, cast_pixels(pixels))
+                .map(DynamicImage::ImageRgba32F),
+        };
+    image.ok_or_else(|| {
+        TextureError::Decode(format!(
+            "{} bytes of {:?} pixels do not make a {width}x{height} image",
+            pixels.len(),
+            data.format
+        ))
+    })
+}
+
+/// Reinterprets bytes as 16-bit or float channels. The buffer is not necessarily aligned.
+fn cast_pixels<T: bytemuck::Pod>(pixels: &[u8]) -> Vec<T> {
+    pixels
+        .chunks_exact(size_of::<T>())
+        .map(bytemuck::pod_read_unaligned)
+        .collect()
+}
@@ -0,0 +1,83 @@
+// Material table of a microglut BatchedModel. Vertex shaders get the material of the
+// current draw as `layout(location = 5) in uint material_index;` and pass it on flat.
+
+#define MATERIAL_HAS_AMBIENT 1u
+#define MATERIAL_HAS_EMISSIVE 2u
+#define MATERIAL_HAS_DIFFUSE 4u
+#define MATERIAL_HAS_SPECULAR 8u
+#define MATERIAL_HAS_SHININESS 16u
+#define MATERIAL_HAS_DISSOLVE 32u
+#define MATERIAL_HAS_OPTICAL_DENSITY 64u
+#define MATERIAL_HAS_METALLIC 128u
+#define MATERIAL_HAS_ROUGHNESS 256u
+
+// Indices into Material.textures
+#define MATERIAL_DIFFUSE_TEXTURE 0
+#define MATERIAL_NORMAL_TEXTURE 1
+#define MATERIAL_SPECULAR_TEXTURE 2
+#define MATERIAL_DISSOLVE_TEXTURE 3
+#define MATERIAL_AMBIENT_TEXTURE 4
+#define MATERIAL_SHININESS_TEXTURE 5
+#define MATERIAL_EMISSIVE_TEXTURE 6
+#define MATERIAL_OCCLUSION_TEXTURE 7
+#define MATERIAL_METALLIC_ROUGHNESS_TEXTURE 8
+
+#define MAX_MATERIAL_TEXTURE_ARRAYS 16
+
+struct Material {
+    vec4 diffuse;  // alpha is the dissolve
+    vec4 emissive;
+    vec4 specular; // alpha is the shininess
+    vec4 ambient;  // alpha is the optical density
+    float metallic;
+    float roughness;
+    uint flags;    // MATERIAL_HAS_*
+    int illumination_model;
+    // Texture array index << 16 | layer, or -1
+    int textures[12];
+};
+
+layout(std430) readonly buffer MaterialTable {
+    Material materials[];
+};
+
+uniform sampler2DArray material_textures[MAX_MATERIAL_TEXTURE_ARRAYS];
+
+bool material_has(Material material, uint flag) {
+    return (material.flags & flag) != 0u;
+}
+
+bool material_has_texture(Material material, int slot) {
+    return material.textures[slot] >= 0;
+}
+
+#define MATERIAL_TEXTURE_CASE(i) \
+    case i: return textureGrad(material_textures[i], coord, coord_dx, coord_dy);
+
+vec4 material_texture(Material material, int slot, vec2 tex_coord) {
+    int texture_ref = material.textures[slot];
+    vec3 coord = vec3(tex_coord, float(texture_ref & 0xffff));
+    vec2 coord_dx = dFdx(tex_coord);
+    vec2 coord_dy = dFdy(tex_coord);
+    // The material comes from the draw, which is not dynamically uniform across a
+    // multi-draw, so the sampler array may only be indexed with constants
+    switch (texture_ref >> 16) {
+        MATERIAL_TEXTURE_CASE(0)
+        MATERIAL_TEXTURE_CASE(1)
+        MATERIAL_TEXTURE_CASE(2)
+        MATERIAL_TEXTURE_CASE(3)
+        MATERIAL_TEXTURE_CASE(4)
+        MATERIAL_TEXTURE_CASE(5)
+        MATERIAL_TEXTURE_CASE(6)
+        MATERIAL_TEXTURE_CASE(7)
+        MATERIAL_TEXTURE_CASE(8)
+        MATERIAL_TEXTURE_CASE(9)
+        MATERIAL_TEXTURE_CASE(10)
+        MATERIAL_TEXTURE_CASE(11)
+        MATERIAL_TEXTURE_CASE(12)
+        MATERIAL_TEXTURE_CASE(13)
+        MATERIAL_TEXTURE_CASE(14)
+        MATERIAL_TEXTURE_CASE(15)
+    }
+    return vec4(0.0);
+}
@@ -0,0 +1,230 @@
+use std::{cell::RefCell, collections::HashMap, fs, io::BufReader, iter, path::Path, rc::Rc};
+
+use glow::Context;
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use super::{obj_texture_options, LoadError, Material, Mesh, Model, OBJ_LOAD_OPTIONS};
+use crate::{Texture, TextureCache, TextureOptions};
+
+/// Options for [Model::load_obj_path_with_options].
+#[derive(Debug, Clone)]
+pub struct ObjOptions {
+    generate_tangents: bool,
+    placeholder_textures: bool,
+}
+
+impl Default for ObjOptions {
+    fn default() -> Self {
+        ObjOptions {
+            generate_tangents: true,
+            placeholder_textures: false,
+        }
+    }
+}
+
+impl ObjOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Generate tangents for meshes with normals and texture coordinates. On by default.
+    pub fn generate_tangents(mut self, generate_tangents: bool) -> Self {
+        self.generate_tangents = generate_tangents;
+        self
+    }
+
+    /// Replace texture maps that cannot be read with a 1x1 texture instead of failing,
+    /// white for colour maps and a flat normal for normal maps. Off by default.
+    pub fn placeholder_textures(mut self, placeholder_textures: bool) -> Self {
+        self.placeholder_textures = placeholder_textures;
+        self
+    }
+}
+
+impl Model {
+    /// Loads an OBJ file along with the material libraries and textures it refers to,
+    /// which are looked up relative to the OBJ and MTL files respectively.
+    pub fn load_obj_path(gl: &Context, path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        Self::load_obj_path_with_options(gl, path, &ObjOptions::default())
+    }
+
+    pub fn load_obj_path_with_options(
+        gl: &Context,
+        path: impl AsRef<Path>,
+        options: &ObjOptions,
+    ) -> Result<Self, LoadError> {
+        Self::load_obj_path_with_cache(gl, path, options, &mut TextureCache::new())
+    }
+
+    /// Like [Model::load_obj_path_with_options], sharing textures with other models loaded
+    /// through `texture_cache`.
+    pub fn load_obj_path_with_cache(
+        gl: &Context,
+        path: impl AsRef<Path>,
+        options: &ObjOptions,
+        texture_cache: &mut TextureCache,
+    ) -> Result<Self, LoadError> {
+        let path = path.as_ref();
+        let data = read(path)?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+
+        // The material loader cannot return our errors, so they are passed on the side
+        let material_error = RefCell::new(None);
+        // The MTL file of every material, to find the textures relative to
+        let material_paths = RefCell::new(Vec::new());
+        let result = tobj::load_obj_buf(&mut BufReader::new(&data[..]), &OBJ_LOAD_OPTIONS, |mtl| {
+            let mtl_path = directory.join(mtl);
+            match load_mtl(&mtl_path) {
+                Ok((materials, names)) => {
+                    material_paths
+                        .borrow_mut()
+                        .extend(iter::repeat_n(mtl_path, materials.len()));
+                    Ok((materials, names))
+                }
+                Err(error) => {
+                    material_error.borrow_mut().get_or_insert(error);
+                    Err(tobj::LoadError::GenericFailure)
+                }
+            }
+        });
+        if let Some(error) = material_error.into_inner() {
+            return Err(error);
+        }
+        let (models, materials) = result.map_err(|error| LoadError::Parse {
+            path: Some(path.to_owned()),
+            line: find_error_line(&data, |prefix| {
+                tobj::load_obj_buf(&mut BufReader::new(prefix), &OBJ_LOAD_OPTIONS, |_| {
+                    Ok((Vec::new(), HashMap::new()))
+                })
+                .is_ok()
+            }),
+            message: error.to_string(),
+        })?;
+        // Errors of the material libraries were caught above
+        let materials = materials.unwrap_or_default();
+
+        let mut textures = MaterialTextures::new(gl, texture_cache, options.placeholder_textures);
+        let material_paths = material_paths.into_inner();
+        let material = materials
+            .into_iter()
+            .zip(material_paths)
+            .map(|(material, mtl_path)| {
+                let directory = mtl_path.parent().unwrap_or(Path::new(""));
+                Material::from_obj(material, |texture_name, normal_map| {
+                    // Exporters on Windows write backslashes
+                    let texture_path = directory.join(texture_name.replace('\\', "/"));
+                    textures.load(&texture_path, normal_map).map(Some)
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        let meshes = models
+            .into_iter()
+            .map(|model| {
+                let material_id = model.mesh.material_id;
+                Mesh::new(
+                    gl,
+                    model.mesh,
+                    material_id,
+                    &model.name,
+                    None,
+                    options.generate_tangents,
+                )
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Model::new(meshes, material))
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, LoadError> {
+    fs::read(path).map_err(|error| LoadError::Io {
+        path: path.to_owned(),
+        error,
+    })
+}
+
+fn load_mtl(path: &Path) -> Result<(Vec<tobj::Material>, HashMap<String, usize>), LoadError> {
+    let data = read(path)?;
+    tobj::load_mtl_buf(&mut BufReader::new(&data[..])).map_err(|error| LoadError::Parse {
+        path: Some(path.to_owned()),
+        line: find_error_line(&data, |prefix| {
+            tobj::load_mtl_buf(&mut BufReader::new(prefix)).is_ok()
+        }),
+        message: error.to_string(),
+    })
+}
+
+/// tobj does not say where it failed, so this finds the first line at which `parses` stops
+/// accepting the file. Starts at 1.
+pub(super) fn find_error_line(data: &[u8], parses: impl Fn(&[u8]) -> bool) -> Option<usize> {
+    let line_ends: Vec<usize> = data
+        .iter()
+        .enumerate()
+        .filter(|(_, &byte)| byte == b'\n')
+        .map(|(i, _)| i + 1)
+        .chain((data.last() != Some(&b'\n')).then_some(data.len()))
+        .collect();
+    let line = line_ends.partition_point(|&end| parses(&data[..end]));
+    (line < line_ends.len()).then_some(line + 1)
+}
+
+/// Texture maps of the materials in a model, loaded through a [TextureCache] so that maps
+/// shared by several materials are only uploaded once.
+struct MaterialTextures<'a> {
+    gl: &'a Context,
+    cache: &'a mut TextureCache,
+    placeholders: bool,
+    placeholder: [Option<Rc<Texture>>; 2],
+}
+
+impl<'a> MaterialTextures<'a> {
+    fn new(gl: &'a Context, cache: &'a mut TextureCache, placeholders: bool) -> Self {
+        MaterialTextures {
+            gl,
+            cache,
+            placeholders,
+            placeholder: [None, None],
+        }
+    }
+
+    fn load(&mut self, path: &Path, normal_map: bool) -> Result<Rc<Texture>, LoadError> {
+        let gl = self.gl;
+        let options = obj_texture_options(gl, normal_map);
+        let result = self.cache.get_or_load(path, &options, || {
+            let data = fs::read(path).map_err(|error| LoadError::MissingTexture {
+                path: path.to_owned(),
+                error,
+            })?;
+            Texture::load_with_options(gl, &data, &options).map_err(|error| LoadError::Texture {
+                path: Some(path.to_owned()),
+                error,
+            })
+        });
+        match result {
+            Err(LoadError::MissingTexture { error, .. }) if self.placeholders => {
+                eprintln!(
+                    "Missing texture {}: {error}, using a placeholder",
+                    path.display()
+                );
+                self.placeholder(normal_map)
+            }
+            result => result,
+        }
+    }
+
+    fn placeholder(&mut self, normal_map: bool) -> Result<Rc<Texture>, LoadError> {
+        if let Some(texture) = &self.placeholder[normal_map as usize] {
+            return Ok(Rc::clone(texture));
+        }
+        let color = if normal_map {
+            Rgba([128, 128, 255, 255])
+        } else {
+            Rgba([255; 4])
+        };
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, color));
+        let texture = Rc::new(Texture::from_image(self.gl, image, &TextureOptions::new())?);
+        self.placeholder[normal_map as usize] = Some(Rc::clone(&texture));
+        Ok(texture)
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use bevy_mikktspace::Geometry;
+use glam::{Vec2, Vec3, Vec4};
+
+/// Indexed triangles as seen by the MikkTSpace implementation. Tangents are stored per
+/// face corner, since triangles sharing a vertex may disagree about its tangent.
+struct Triangles<'a> {
+    positions: &'a [Vec3],
+    normals: &'a [Vec3],
+    texture_coordinates: &'a [Vec2],
+    indices: &'a [u32],
+    tangents: Vec<Vec4>,
+}
+
+impl Triangles<'_> {
+    fn index(&self, face: usize, vert: usize) -> usize {
+        self.indices[face * 3 + vert] as usize
+    }
+}
+
+impl Geometry for Triangles<'_> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.positions[self.index(face, vert)].into()
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.normals[self.index(face, vert)].into()
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.texture_coordinates[self.index(face, vert)].into()
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.tangents[face * 3 + vert] = Vec4::from(tangent);
+    }
+}
+
+/// The result of [generate_tangents]. Vertices that ended up with different tangents in
+/// different triangles have been split, so the mesh has to be rebuilt from this.
+#[derive(Debug, Clone, Default)]
+pub struct TangentMesh {
+    /// For every vertex, the index of the input vertex it is a copy of.
+    pub source_vertices: Vec<u32>,
+    /// The tangent of every vertex, with the handedness in `w`.
+    pub tangents: Vec<Vec4>,
+    /// Triangle indices into the new vertices.
+    pub indices: Vec<u32>,
+}
+
+/// Generates MikkTSpace tangents for an indexed triangle mesh, the tangent space that
+/// normal maps are usually baked in (e.g. by Blender, and as required by glTF).
+///
+/// The mesh is split into separate face corners for the algorithm and welded together
+/// again afterwards wherever position, normal, texture coordinate and tangent agree.
+/// The bitangent is `normal.cross(tangent.xyz) * tangent.w`. Corners that the algorithm
+/// leaves out, like those of degenerate triangles, get an arbitrary tangent perpendicular
+/// to the normal.
+pub fn generate_tangents(
+    positions: &[Vec3],
+    normals: &[Vec3],
+    texture_coordinates: &[Vec2],
+    indices: &[u32],
+) -> TangentMesh {
+    let mut triangles = Triangles {
+        positions,
+        normals,
+        texture_coordinates,
+        indices,
+        tangents: indices
+            .iter()
+            .map(|&index| {
+                normals[index as usize]
+                    .normalize_or(Vec3::Z)
+                    .any_orthonormal_vector()
+                    .extend(1.0)
+            })
+            .collect(),
+    };
+    bevy_mikktspace::generate_tangents(&mut triangles);
+
+    let mut mesh = TangentMesh::default();
+    let mut welded = HashMap::new();
+    for (&source, tangent) in indices.iter().zip(triangles.tangents) {
+        let vertex = source as usize;
+        let key = (
+            positions[vertex].to_array().map(f32::to_bits),
+            normals[vertex].to_array().map(f32::to_bits),
+            texture_coordinates[vertex].to_array().map(f32::to_bits),
+            tangent.to_array().map(f32::to_bits),
+        );
+        let index = match welded.entry(key) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => {
+                let index = mesh.source_vertices.len() as u32;
+                mesh.source_vertices.push(source);
+                mesh.tangents.push(tangent);
+                *entry.insert(index)
+            }
+        };
+        mesh.indices.push(index);
+    }
+    mesh
+}
@@ -0,0 +1,54 @@
+use std::mem::offset_of;
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec2, Vec3};
+use glow::{Context, HasContext as _, FLOAT};
+
+/// The interleaved vertex format of every [Mesh](super::Mesh). Attributes a model does not
+/// have are zero.
+///
+/// The attributes are at fixed locations, so vertex shaders declare the ones they use as
+/// e.g. `layout(location = 1) in vec3 normal;`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Pod, Zeroable)]
+pub struct Vertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub texture_coordinate: Vec2,
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+}
+
+impl Vertex {
+    pub const POSITION_LOCATION: u32 = 0;
+    pub const NORMAL_LOCATION: u32 = 1;
+    pub const TEXTURE_COORDINATE_LOCATION: u32 = 2;
+    pub const TANGENT_LOCATION: u32 = 3;
+    pub const BITANGENT_LOCATION: u32 = 4;
+
+    /// Sets up the attributes of the bound vertex array to read from the bound array buffer.
+    pub(super) unsafe fn configure_attributes(gl: &Context) {
+        let attributes = [
+            (Self::POSITION_LOCATION, 3, offset_of!(Vertex, position)),
+            (Self::NORMAL_LOCATION, 3, offset_of!(Vertex, normal)),
+            (
+                Self::TEXTURE_COORDINATE_LOCATION,
+                2,
+                offset_of!(Vertex, texture_coordinate),
+            ),
+            (Self::TANGENT_LOCATION, 3, offset_of!(Vertex, tangent)),
+            (Self::BITANGENT_LOCATION, 3, offset_of!(Vertex, bitangent)),
+        ];
+        for (location, size, offset) in attributes {
+            gl.enable_vertex_attrib_array(location);
+            gl.vertex_attrib_pointer_f32(
+                location,
+                size,
+                FLOAT,
+                false,
+                size_of::<Vertex>() as _,
+                offset as _,
+            );
+        }
+    }
+}
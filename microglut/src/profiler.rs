@@ -0,0 +1,299 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+};
+
+use glow::{
+    Context, HasContext as _, NativeQuery, QUERY_RESULT, QUERY_RESULT_AVAILABLE, TIMESTAMP,
+};
+
+use crate::{
+    debug_markers::{pop_debug_group, push_debug_group},
+    delete_object, track_object,
+};
+
+/// Number of frames of history kept for every pass.
+const HISTORY_LENGTH: usize = 100;
+
+/// GPU time spent in one pass of a frame.
+#[derive(Debug, Clone)]
+pub struct PassTiming {
+    pub name: String,
+    /// Nesting level, 0 for top-level passes.
+    pub depth: usize,
+    pub gpu_ms: f32,
+}
+
+struct Scope {
+    name: String,
+    /// Names of all enclosing scopes and this one, separated by `/`. Identifies the pass
+    /// across frames.
+    path: String,
+    depth: usize,
+    start: usize,
+    end: Option<usize>,
+}
+
+/// Timestamp queries and scopes recorded during one frame.
+#[derive(Default)]
+struct Frame {
+    queries: Vec<NativeQuery>,
+    used: usize,
+    scopes: Vec<Scope>,
+}
+
+impl Frame {
+    fn query(&mut self, gl: &Context) -> usize {
+        if self.used == self.queries.len() {
+            self.queries.push(track_object(
+                unsafe { gl.create_query() }.expect("failed to create timer query"),
+            ));
+        }
+        self.used += 1;
+        self.used - 1
+    }
+
+    fn timestamp(&mut self, gl: &Context) -> usize {
+        let index = self.query(gl);
+        unsafe { gl.query_counter(self.queries[index], TIMESTAMP) };
+        index
+    }
+
+    fn is_available(&self, gl: &Context) -> bool {
+        // Queries complete in order, so the last one being done means all of them are
+        self.used == 0
+            || unsafe {
+                gl.get_query_parameter_u32(self.queries[self.used - 1], QUERY_RESULT_AVAILABLE) != 0
+            }
+    }
+
+    fn nanoseconds(&self, gl: &Context, index: usize) -> u64 {
+        let mut result = 0u64;
+        // With no buffer bound to QUERY_BUFFER the "offset" is a pointer to the result
+        unsafe {
+            gl.get_query_parameter_u64_with_offset(
+                self.queries[index],
+                QUERY_RESULT,
+                &mut result as *mut u64 as usize,
+            )
+        };
+        result
+    }
+
+    fn timings(&self, gl: &Context) -> Vec<(String, PassTiming)> {
+        self.scopes
+            .iter()
+            .filter_map(|scope| {
+                let end = scope.end?;
+                let elapsed = self
+                    .nanoseconds(gl, end)
+                    .saturating_sub(self.nanoseconds(gl, scope.start));
+                let timing = PassTiming {
+                    name: scope.name.clone(),
+                    depth: scope.depth,
+                    gpu_ms: elapsed as f32 * 1e-6,
+                };
+                Some((scope.path.clone(), timing))
+            })
+            .collect()
+    }
+
+    fn reset(&mut self) {
+        self.used = 0;
+        self.scopes.clear();
+    }
+}
+
+#[derive(Default)]
+struct ProfilerState {
+    /// Double-buffered so that the results of the previous frame can be read while the
+    /// current one is recorded, without waiting for the GPU.
+    frames: [Frame; 2],
+    current: usize,
+    open_scopes: Vec<usize>,
+    /// Path and timing of every pass of the most recent frame with results.
+    passes: Vec<(String, PassTiming)>,
+    history: HashMap<String, VecDeque<f32>>,
+    frame_history: VecDeque<f32>,
+}
+
+/// Measures the GPU time of nested passes with `GL_TIMESTAMP` queries. Every pass is also
+/// a debug group, so it shows up by name in frame capture tools.
+///
+/// Call [GpuProfiler::begin_frame] once at the start of every frame and wrap passes in
+/// [GpuProfiler::scope] (or [GpuProfiler::begin] and [GpuProfiler::end]). Results are
+/// available one frame later, and a frame whose queries the GPU has not finished yet is
+/// skipped rather than waited for.
+///
+/// Takes `&self` so that a profiler owned by the app can be used while other fields are
+/// borrowed mutably.
+#[derive(Default)]
+pub struct GpuProfiler {
+    state: RefCell<ProfilerState>,
+}
+
+impl GpuProfiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collects the results of the previous use of the next query pool and starts
+    /// recording into it.
+    pub fn begin_frame(&self, gl: &Context) {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        assert!(
+            state.open_scopes.is_empty(),
+            "GpuProfiler::begin_frame called with an open scope"
+        );
+
+        state.current = (state.current + 1) % state.frames.len();
+        let frame = &mut state.frames[state.current];
+        if frame.is_available(gl) && !frame.scopes.is_empty() {
+            let timings = frame.timings(gl);
+            let frame_ms = timings
+                .iter()
+                .filter(|(_, timing)| timing.depth == 0)
+                .map(|(_, timing)| timing.gpu_ms)
+                .sum();
+            push_history(&mut state.frame_history, frame_ms);
+
+            // Passes that were not recorded this frame keep their old history
+            for (path, timing) in &timings {
+                let history = state.history.entry(path.clone()).or_default();
+                push_history(history, timing.gpu_ms);
+            }
+            state.passes = timings;
+        }
+        frame.reset();
+    }
+
+    /// Starts a pass. Passes can be nested and must be ended in reverse order.
+    pub fn begin(&self, gl: &Context, name: impl Into<String>) {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        let name = name.into();
+        let frame = &mut state.frames[state.current];
+        let path = match state.open_scopes.last() {
+            Some(&parent) => format!("{}/{name}", frame.scopes[parent].path),
+            None => name.clone(),
+        };
+        push_debug_group(gl, &name);
+        let start = frame.timestamp(gl);
+        frame.scopes.push(Scope {
+            name,
+            path,
+            depth: state.open_scopes.len(),
+            start,
+            end: None,
+        });
+        state.open_scopes.push(frame.scopes.len() - 1);
+    }
+
+    /// Ends the innermost pass.
+    pub fn end(&self, gl: &Context) {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        let scope = state
+            .open_scopes
+            .pop()
+            .expect("GpuProfiler::end called without a matching begin");
+        let frame = &mut state.frames[state.current];
+        let end = frame.timestamp(gl);
+        frame.scopes[scope].end = Some(end);
+        pop_debug_group(gl);
+    }
+
+    /// Starts a pass that ends when the returned guard is dropped.
+    pub fn scope<'a>(&'a self, gl: &'a Context, name: impl Into<String>) -> GpuScope<'a> {
+        self.begin(gl, name);
+        GpuScope { profiler: self, gl }
+    }
+
+    /// The passes of the most recent frame with results, in the order they began.
+    pub fn passes(&self) -> Vec<PassTiming> {
+        let state = self.state.borrow();
+        state
+            .passes
+            .iter()
+            .map(|(_, timing)| timing.clone())
+            .collect()
+    }
+
+    /// GPU time of the top-level passes of the last frames, in milliseconds.
+    pub fn frame_history(&self) -> Vec<f32> {
+        self.state.borrow().frame_history.iter().copied().collect()
+    }
+
+    pub fn delete(self, gl: &Context) {
+        for frame in self.state.into_inner().frames {
+            for query in frame.queries {
+                delete_object(gl, query);
+            }
+        }
+    }
+
+    /// Shows a table with the last and average GPU time and the history of every pass.
+    #[cfg(feature = "imgui")]
+    pub fn ui(&self, ui: &imgui::Ui) {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+
+        let frame_history = state.frame_history.make_contiguous();
+        ui.plot_lines("GPU time", frame_history)
+            .overlay_text(format!("{:.3} ms", average(frame_history)))
+            .scale_min(0.0)
+            .build();
+
+        ui.columns(4, "GPU passes", true);
+        for header in ["Pass", "ms", "avg ms", "History"] {
+            ui.text(header);
+            ui.next_column();
+        }
+        ui.separator();
+
+        for (path, pass) in &state.passes {
+            let history = state.history.entry(path.clone()).or_default();
+            ui.text(format!("{}{}", "  ".repeat(pass.depth), pass.name));
+            ui.next_column();
+            ui.text(format!("{:.3}", pass.gpu_ms));
+            ui.next_column();
+            ui.text(format!("{:.3}", average(history.make_contiguous())));
+            ui.next_column();
+            ui.plot_lines(format!("##{path}"), history.make_contiguous())
+                .scale_min(0.0)
+                .graph_size([120.0, 20.0])
+                .build();
+            ui.next_column();
+        }
+        ui.columns(1, "", false);
+    }
+}
+
+/// Ends a [GpuProfiler] pass when dropped.
+pub struct GpuScope<'a> {
+    profiler: &'a GpuProfiler,
+    gl: &'a Context,
+}
+
+impl Drop for GpuScope<'_> {
+    fn drop(&mut self) {
+        self.profiler.end(self.gl);
+    }
+}
+
+fn push_history(history: &mut VecDeque<f32>, value: f32) {
+    if history.len() == HISTORY_LENGTH {
+        history.pop_front();
+    }
+    history.push_back(value);
+}
+
+#[cfg(feature = "imgui")]
+fn average(values: &[f32]) -> f32 {
+    if values.is_empty() {
+        0.0
+    } else {
+        values.iter().sum::<f32>() / values.len() as f32
+    }
+}
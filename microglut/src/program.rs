@@ -0,0 +1,249 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    ffi::{c_char, c_void},
+    sync::OnceLock,
+};
+
+use glam::{IVec2, IVec3, IVec4, Mat3, Mat4, UVec2, UVec3, UVec4, Vec2, Vec3, Vec4};
+use glow::{
+    Context, HasContext as _, NativeProgram, NativeUniformLocation, ACTIVE_RESOURCES,
+    MAX_NAME_LENGTH, SHADER_STORAGE_BLOCK,
+};
+
+use crate::{debug_markers::label_object, delete_object, LoadShaders, ShaderError};
+
+type GetProgramInterfaceiv =
+    unsafe extern "system" fn(program: u32, interface: u32, pname: u32, params: *mut i32);
+type GetProgramResourceName = unsafe extern "system" fn(
+    program: u32,
+    interface: u32,
+    index: u32,
+    buf_size: i32,
+    length: *mut i32,
+    name: *mut c_char,
+);
+
+// glow can't enumerate the storage blocks of a program, so the functions for it are looked
+// up when the context is created. They are missing before OpenGL 4.3.
+static PROGRAM_INTERFACE: OnceLock<Option<(GetProgramInterfaceiv, GetProgramResourceName)>> =
+    OnceLock::new();
+
+pub(crate) fn load_gl_functions(loader: impl Fn(&str) -> *const c_void) {
+    let interface = loader("glGetProgramInterfaceiv");
+    let resource_name = loader("glGetProgramResourceName");
+    let functions = (!interface.is_null() && !resource_name.is_null()).then(|| unsafe {
+        (
+            std::mem::transmute::<*const c_void, GetProgramInterfaceiv>(interface),
+            std::mem::transmute::<*const c_void, GetProgramResourceName>(resource_name),
+        )
+    });
+    let _ = PROGRAM_INTERFACE.set(functions);
+}
+
+/// Names of the active shader storage blocks of `program`.
+unsafe fn storage_block_names(program: NativeProgram) -> Vec<String> {
+    let Some((get_program_interface_iv, get_program_resource_name)) =
+        PROGRAM_INTERFACE.get().copied().flatten()
+    else {
+        return Vec::new();
+    };
+    let program = program.0.get();
+    let mut count = 0;
+    let mut max_length = 0;
+    get_program_interface_iv(program, SHADER_STORAGE_BLOCK, ACTIVE_RESOURCES, &mut count);
+    get_program_interface_iv(
+        program,
+        SHADER_STORAGE_BLOCK,
+        MAX_NAME_LENGTH,
+        &mut max_length,
+    );
+
+    (0..count as u32)
+        .map(|index| {
+            let mut name = vec![0u8; max_length as usize];
+            let mut length = 0;
+            get_program_resource_name(
+                program,
+                SHADER_STORAGE_BLOCK,
+                index,
+                max_length,
+                &mut length,
+                name.as_mut_ptr().cast(),
+            );
+            name.truncate(length as usize);
+            String::from_utf8_lossy(&name).into_owned()
+        })
+        .collect()
+}
+
+/// Value that can be assigned to a uniform with [Program::set_uniform].
+pub trait Uniform {
+    fn set(&self, gl: &Context, location: &NativeUniformLocation);
+}
+
+/// Texture unit for a sampler uniform, e.g. `TextureUnit(0)` for `TEXTURE0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureUnit(pub u32);
+
+macro_rules! impl_uniform {
+    ($type:ty, |$gl:ident, $location:ident, $value:ident| $set:expr) => {
+        impl Uniform for $type {
+            fn set(&self, $gl: &Context, $location: &NativeUniformLocation) {
+                let $value = self;
+                unsafe { $set }
+            }
+        }
+    };
+}
+
+impl_uniform!(f32, |gl, l, v| gl.uniform_1_f32(Some(l), *v));
+impl_uniform!(Vec2, |gl, l, v| gl.uniform_2_f32_slice(Some(l), v.as_ref()));
+impl_uniform!(Vec3, |gl, l, v| gl.uniform_3_f32_slice(Some(l), v.as_ref()));
+impl_uniform!(Vec4, |gl, l, v| gl.uniform_4_f32_slice(Some(l), v.as_ref()));
+impl_uniform!(i32, |gl, l, v| gl.uniform_1_i32(Some(l), *v));
+impl_uniform!(IVec2, |gl, l, v| gl
+    .uniform_2_i32_slice(Some(l), v.as_ref()));
+impl_uniform!(IVec3, |gl, l, v| gl
+    .uniform_3_i32_slice(Some(l), v.as_ref()));
+impl_uniform!(IVec4, |gl, l, v| gl
+    .uniform_4_i32_slice(Some(l), v.as_ref()));
+impl_uniform!(u32, |gl, l, v| gl.uniform_1_u32(Some(l), *v));
+impl_uniform!(UVec2, |gl, l, v| gl
+    .uniform_2_u32_slice(Some(l), v.as_ref()));
+impl_uniform!(UVec3, |gl, l, v| gl
+    .uniform_3_u32_slice(Some(l), v.as_ref()));
+impl_uniform!(UVec4, |gl, l, v| gl
+    .uniform_4_u32_slice(Some(l), v.as_ref()));
+impl_uniform!(bool, |gl, l, v| gl.uniform_1_i32(Some(l), *v as i32));
+impl_uniform!(Mat3, |gl, l, v| gl.uniform_matrix_3_f32_slice(
+    Some(l),
+    false,
+    v.as_ref()
+));
+impl_uniform!(Mat4, |gl, l, v| gl.uniform_matrix_4_f32_slice(
+    Some(l),
+    false,
+    v.as_ref()
+));
+impl_uniform!(TextureUnit, |gl, l, v| gl
+    .uniform_1_i32(Some(l), v.0 as i32));
+
+/// A linked program with its active uniforms, attributes and storage blocks looked up once,
+/// so that they can be set by name without querying the driver every frame.
+#[derive(Debug)]
+pub struct Program {
+    id: NativeProgram,
+    uniforms: HashMap<String, NativeUniformLocation>,
+    attributes: HashMap<String, u32>,
+    storage_blocks: HashMap<String, u32>,
+    warned: RefCell<HashSet<String>>,
+}
+
+impl Program {
+    pub fn new(gl: &Context, id: NativeProgram) -> Self {
+        let mut uniforms = HashMap::new();
+        let mut attributes = HashMap::new();
+        let mut storage_blocks = HashMap::new();
+        unsafe {
+            for index in 0..gl.get_active_uniforms(id) {
+                let Some(uniform) = gl.get_active_uniform(id, index) else {
+                    continue;
+                };
+                // Uniforms in blocks don't have a location
+                let Some(location) = gl.get_uniform_location(id, &uniform.name) else {
+                    continue;
+                };
+                // Arrays are reported as `name[0]`, make them available as `name` as well as
+                // every element
+                if let Some(array_name) = uniform.name.strip_suffix("[0]") {
+                    for element in 1..uniform.size {
+                        let element_name = format!("{array_name}[{element}]");
+                        if let Some(location) = gl.get_uniform_location(id, &element_name) {
+                            uniforms.insert(element_name, location);
+                        }
+                    }
+                    uniforms.insert(array_name.to_string(), location);
+                }
+                uniforms.insert(uniform.name, location);
+            }
+
+            for index in 0..gl.get_active_attributes(id) {
+                let Some(attribute) = gl.get_active_attribute(id, index) else {
+                    continue;
+                };
+                if let Some(location) = gl.get_attrib_location(id, &attribute.name) {
+                    attributes.insert(attribute.name, location);
+                }
+            }
+
+            for name in storage_block_names(id) {
+                if let Some(index) = gl.get_shader_storage_block_index(id, &name) {
+                    storage_blocks.insert(name, index);
+                }
+            }
+        }
+
+        Program {
+            id,
+            uniforms,
+            attributes,
+            storage_blocks,
+            warned: RefCell::new(HashSet::new()),
+        }
+    }
+
+    /// Compiles `shaders` and reflects the resulting program.
+    pub fn compile(gl: &Context, shaders: &LoadShaders) -> Result<Self, ShaderError> {
+        Ok(Self::new(gl, shaders.compile(gl)?))
+    }
+
+    pub fn id(&self) -> NativeProgram {
+        self.id
+    }
+
+    /// Names the program in debug output and capture tools.
+    pub fn label(&self, gl: &Context, label: &str) {
+        label_object(gl, self.id, label);
+    }
+
+    pub fn use_program(&self, gl: &Context) {
+        unsafe { gl.use_program(Some(self.id)) };
+    }
+
+    fn warn_once(&self, what: &str, name: &str) {
+        if self.warned.borrow_mut().insert(name.to_string()) {
+            eprintln!("program {:?}: no active {what} named \"{name}\"", self.id);
+        }
+    }
+
+    pub fn uniform_location(&self, name: &str) -> Option<&NativeUniformLocation> {
+        self.uniforms.get(name)
+    }
+
+    pub fn attribute_location(&self, name: &str) -> Option<u32> {
+        self.attributes.get(name).copied()
+    }
+
+    /// Sets a uniform of the program, which has to be in use. Names that are not active in
+    /// the program, e.g. because the compiler optimised them away, are reported once.
+    pub fn set_uniform(&self, gl: &Context, name: &str, value: impl Uniform) {
+        match self.uniforms.get(name) {
+            Some(location) => value.set(gl, location),
+            None => self.warn_once("uniform", name),
+        }
+    }
+
+    /// Binds the shader storage block `name` to `binding`, which buffers are bound to with
+    /// `bind_buffer_base(SHADER_STORAGE_BUFFER, binding, ..)`.
+    pub fn bind_storage_block(&self, gl: &Context, name: &str, binding: u32) {
+        match self.storage_blocks.get(name) {
+            Some(&index) => unsafe { gl.shader_storage_block_binding(self.id, index, binding) },
+            None => self.warn_once("storage block", name),
+        }
+    }
+
+    pub fn delete(self, gl: &Context) {
+        delete_object(gl, self.id);
+    }
+}
@@ -0,0 +1,339 @@
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{BufWriter, Write as _},
+    path::{Path, PathBuf},
+};
+
+use glow::{
+    Context, HasContext as _, NativeFramebuffer, NativeTexture, PixelPackData, BACK,
+    COLOR_ATTACHMENT0, DEPTH_ATTACHMENT, DEPTH_COMPONENT, DEPTH_STENCIL_ATTACHMENT, FLOAT,
+    FRAMEBUFFER_ATTACHMENT_ALPHA_SIZE, FRAMEBUFFER_ATTACHMENT_BLUE_SIZE,
+    FRAMEBUFFER_ATTACHMENT_DEPTH_SIZE, FRAMEBUFFER_ATTACHMENT_GREEN_SIZE,
+    FRAMEBUFFER_ATTACHMENT_OBJECT_TYPE, FRAMEBUFFER_ATTACHMENT_RED_SIZE, FRAMEBUFFER_DEFAULT,
+    FRONT, NONE, PACK_ALIGNMENT, READ_FRAMEBUFFER, READ_FRAMEBUFFER_BINDING, RED, RG, RGB, RGBA,
+};
+
+use crate::fbo::check_framebuffer_status;
+
+#[derive(Debug, Clone)]
+pub enum ReadbackError {
+    /// Nothing is attached at the requested attachment point.
+    MissingAttachment {
+        attachment: u32,
+    },
+    /// The temporary framebuffer used to read a texture is incomplete.
+    Framebuffer(String),
+    /// The file extension is not one of `png`, `exr` or `pfm`.
+    UnsupportedExtension(PathBuf),
+    Write {
+        path: PathBuf,
+        error: String,
+    },
+}
+
+impl fmt::Display for ReadbackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadbackError::MissingAttachment { attachment } => {
+                write!(f, "nothing is attached to attachment 0x{attachment:X}")
+            }
+            ReadbackError::Framebuffer(error) => write!(f, "cannot read texture: {error}"),
+            ReadbackError::UnsupportedExtension(path) => write!(
+                f,
+                "cannot save {}: expected a .png, .exr or .pfm extension",
+                path.display()
+            ),
+            ReadbackError::Write { path, error } => {
+                write!(f, "failed to write {}: {error}", path.display())
+            }
+        }
+    }
+}
+
+impl Error for ReadbackError {}
+
+/// Pixels read back from the GPU as 32-bit floats, with rows ordered top to bottom.
+///
+/// Normalised formats end up in [0, 1], float formats are kept exactly and depth is read as
+/// window-space depth.
+#[derive(Debug, Clone)]
+pub struct ReadbackImage {
+    width: u32,
+    height: u32,
+    channels: usize,
+    pixels: Vec<f32>,
+}
+
+impl ReadbackImage {
+    /// Reads `attachment` of `framebuffer`, which is `COLOR_ATTACHMENTi`, `DEPTH_ATTACHMENT`
+    /// or, for the default framebuffer (`None`), `BACK` or `FRONT`.
+    pub fn from_framebuffer(
+        gl: &Context,
+        framebuffer: Option<NativeFramebuffer>,
+        attachment: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, ReadbackError> {
+        unsafe {
+            let previous = gl.get_parameter_framebuffer(READ_FRAMEBUFFER_BINDING);
+            gl.bind_framebuffer(READ_FRAMEBUFFER, framebuffer);
+            let result = Self::read_bound(gl, framebuffer.is_none(), attachment, width, height);
+            gl.bind_framebuffer(READ_FRAMEBUFFER, previous);
+            result
+        }
+    }
+
+    /// Reads mip `level` of a 2D texture whose level 0 is `width` x `height`. Depth textures
+    /// are detected from their format.
+    pub fn from_texture(
+        gl: &Context,
+        texture: NativeTexture,
+        level: i32,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, ReadbackError> {
+        let width = (width >> level).max(1);
+        let height = (height >> level).max(1);
+        unsafe {
+            let previous = gl.get_parameter_framebuffer(READ_FRAMEBUFFER_BINDING);
+            let fb = gl
+                .create_framebuffer()
+                .map_err(ReadbackError::Framebuffer)?;
+            gl.bind_framebuffer(READ_FRAMEBUFFER, Some(fb));
+            gl.framebuffer_texture(READ_FRAMEBUFFER, COLOR_ATTACHMENT0, Some(texture), level);
+
+            let is_depth = gl.get_framebuffer_attachment_parameter_i32(
+                READ_FRAMEBUFFER,
+                COLOR_ATTACHMENT0,
+                FRAMEBUFFER_ATTACHMENT_DEPTH_SIZE,
+            ) > 0;
+            let attachment = if is_depth {
+                gl.framebuffer_texture(READ_FRAMEBUFFER, COLOR_ATTACHMENT0, None, 0);
+                gl.framebuffer_texture(READ_FRAMEBUFFER, DEPTH_ATTACHMENT, Some(texture), level);
+                DEPTH_ATTACHMENT
+            } else {
+                COLOR_ATTACHMENT0
+            };
+
+            let result = check_framebuffer_status(gl, READ_FRAMEBUFFER)
+                .map_err(|error| ReadbackError::Framebuffer(error.to_string()))
+                .and_then(|_| Self::read_bound(gl, false, attachment, width, height));
+
+            gl.bind_framebuffer(READ_FRAMEBUFFER, previous);
+            gl.delete_framebuffer(fb);
+            result
+        }
+    }
+
+    unsafe fn read_bound(
+        gl: &Context,
+        default_framebuffer: bool,
+        attachment: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, ReadbackError> {
+        unsafe {
+            let is_depth = matches!(attachment, DEPTH_ATTACHMENT | DEPTH_STENCIL_ATTACHMENT);
+            let (format, channels) = if is_depth {
+                (DEPTH_COMPONENT, 1)
+            } else if default_framebuffer {
+                (RGBA, 4)
+            } else {
+                Self::color_layout(gl, attachment)?
+            };
+            if !is_depth {
+                gl.read_buffer(if default_framebuffer && attachment != FRONT {
+                    BACK
+                } else {
+                    attachment
+                });
+            }
+
+            let mut pixels = vec![0.0f32; width as usize * height as usize * channels];
+            gl.pixel_store_i32(PACK_ALIGNMENT, 4);
+            gl.read_pixels(
+                0,
+                0,
+                width as _,
+                height as _,
+                format,
+                FLOAT,
+                PixelPackData::Slice(bytemuck::cast_slice_mut(&mut pixels)),
+            );
+
+            // OpenGL returns the bottom row first
+            let row_length = width as usize * channels;
+            let pixels = pixels
+                .chunks_exact(row_length)
+                .rev()
+                .flatten()
+                .copied()
+                .collect();
+
+            Ok(Self {
+                width,
+                height,
+                channels,
+                pixels,
+            })
+        }
+    }
+
+    /// Picks the smallest read format that keeps every channel of the attachment.
+    unsafe fn color_layout(gl: &Context, attachment: u32) -> Result<(u32, usize), ReadbackError> {
+        unsafe {
+            let parameter = |parameter| {
+                gl.get_framebuffer_attachment_parameter_i32(READ_FRAMEBUFFER, attachment, parameter)
+            };
+            let object_type = parameter(FRAMEBUFFER_ATTACHMENT_OBJECT_TYPE) as u32;
+            if object_type == NONE {
+                return Err(ReadbackError::MissingAttachment { attachment });
+            }
+            if object_type == FRAMEBUFFER_DEFAULT {
+                return Ok((RGBA, 4));
+            }
+
+            let sizes = [
+                FRAMEBUFFER_ATTACHMENT_RED_SIZE,
+                FRAMEBUFFER_ATTACHMENT_GREEN_SIZE,
+                FRAMEBUFFER_ATTACHMENT_BLUE_SIZE,
+                FRAMEBUFFER_ATTACHMENT_ALPHA_SIZE,
+            ]
+            .map(parameter);
+            Ok(match sizes.iter().rposition(|&size| size > 0) {
+                Some(0) => (RED, 1),
+                Some(1) => (RG, 2),
+                Some(2) => (RGB, 3),
+                _ => (RGBA, 4),
+            })
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Interleaved pixels, `channels` floats per pixel, rows top to bottom.
+    pub fn pixels(&self) -> &[f32] {
+        &self.pixels
+    }
+
+    /// Expands the pixels to `channels` floats each. Missing colour channels are filled
+    /// with 0, or with the grey value for single-channel images, and missing alpha with 1.
+    fn expanded(&self, channels: usize) -> Vec<f32> {
+        if channels == self.channels {
+            return self.pixels.clone();
+        }
+        let mut output = Vec::with_capacity(self.pixels.len() / self.channels * channels);
+        for pixel in self.pixels.chunks_exact(self.channels) {
+            for c in 0..channels {
+                let value = match (self.channels, c) {
+                    (_, c) if c < self.channels => pixel[c],
+                    (_, 3) => 1.0,
+                    (1, _) => pixel[0],
+                    _ => 0.0,
+                };
+                output.push(value);
+            }
+        }
+        output
+    }
+
+    /// Saves the image in the format given by the extension of `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReadbackError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("png") => self.save_png(path),
+            Some("exr") => self.save_exr(path),
+            Some("pfm") => self.save_pfm(path),
+            _ => Err(ReadbackError::UnsupportedExtension(path.to_path_buf())),
+        }
+    }
+
+    /// Saves an 8-bit PNG. Values are clamped to [0, 1], no tone mapping is applied.
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), ReadbackError> {
+        let (channels, color_type) = match self.channels {
+            1 => (1, image::ExtendedColorType::L8),
+            2 | 3 => (3, image::ExtendedColorType::Rgb8),
+            _ => (4, image::ExtendedColorType::Rgba8),
+        };
+        let bytes: Vec<u8> = self
+            .expanded(channels)
+            .iter()
+            .map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect();
+        image::save_buffer_with_format(
+            path.as_ref(),
+            &bytes,
+            self.width,
+            self.height,
+            color_type,
+            image::ImageFormat::Png,
+        )
+        .map_err(|error| write_error(path.as_ref(), error))
+    }
+
+    /// Saves a 32-bit float OpenEXR image, which keeps HDR values and depth exactly.
+    pub fn save_exr(&self, path: impl AsRef<Path>) -> Result<(), ReadbackError> {
+        let (channels, color_type) = match self.channels {
+            4 => (4, image::ExtendedColorType::Rgba32F),
+            _ => (3, image::ExtendedColorType::Rgb32F),
+        };
+        let pixels = self.expanded(channels);
+        image::save_buffer_with_format(
+            path.as_ref(),
+            bytemuck::cast_slice(&pixels),
+            self.width,
+            self.height,
+            color_type,
+            image::ImageFormat::OpenExr,
+        )
+        .map_err(|error| write_error(path.as_ref(), error))
+    }
+
+    /// Saves a little-endian Portable Float Map. PFM has no alpha channel, so single-channel
+    /// images are written as greyscale and everything else as RGB.
+    pub fn save_pfm(&self, path: impl AsRef<Path>) -> Result<(), ReadbackError> {
+        let path = path.as_ref();
+        let (channels, magic) = match self.channels {
+            1 => (1, "Pf"),
+            _ => (3, "PF"),
+        };
+        let pixels = self.expanded(channels);
+
+        let write = || -> std::io::Result<()> {
+            let mut file = BufWriter::new(File::create(path)?);
+            // A negative scale means little-endian
+            write!(file, "{magic}\n{} {}\n-1.0\n", self.width, self.height)?;
+            // PFM stores the bottom row first
+            for row in pixels.chunks_exact(self.width as usize * channels).rev() {
+                for value in row {
+                    file.write_all(&value.to_le_bytes())?;
+                }
+            }
+            file.flush()
+        };
+        write().map_err(|error| write_error(path, error))
+    }
+}
+
+fn write_error(path: &Path, error: impl fmt::Display) -> ReadbackError {
+    ReadbackError::Write {
+        path: path.to_path_buf(),
+        error: error.to_string(),
+    }
+}
@@ -0,0 +1,122 @@
+use std::{collections::BTreeMap, panic::Location, sync::Mutex};
+
+use glow::{
+    Context, HasContext as _, NativeBuffer, NativeFramebuffer, NativeProgram, NativeQuery,
+    NativeRenderbuffer, NativeShader, NativeTexture, NativeVertexArray,
+};
+
+use crate::ObjectLabel;
+
+/// A GL object that microglut knows how to delete, see [track_object].
+pub trait GlObject: ObjectLabel {
+    /// What kind of object this is in leak reports, e.g. `"texture"`.
+    const KIND: &'static str;
+
+    /// Deletes the object without removing it from the registry. Use [delete_object].
+    ///
+    /// # Safety
+    ///
+    /// The object must not be used afterwards.
+    unsafe fn delete_untracked(self, gl: &Context);
+}
+
+macro_rules! impl_gl_object {
+    ($($ty:ty => $kind:literal, $delete:ident);* $(;)?) => {
+        $(
+            impl GlObject for $ty {
+                const KIND: &'static str = $kind;
+
+                unsafe fn delete_untracked(self, gl: &Context) {
+                    gl.$delete(self)
+                }
+            }
+        )*
+    };
+}
+
+impl_gl_object!(
+    NativeBuffer => "buffer", delete_buffer;
+    NativeFramebuffer => "framebuffer", delete_framebuffer;
+    NativeProgram => "program", delete_program;
+    NativeQuery => "query", delete_query;
+    NativeRenderbuffer => "renderbuffer", delete_renderbuffer;
+    NativeShader => "shader", delete_shader;
+    NativeTexture => "texture", delete_texture;
+    NativeVertexArray => "vertex array", delete_vertex_array;
+);
+
+/// A GL object that has been created but not deleted.
+#[derive(Debug, Clone)]
+pub struct LiveObject {
+    pub kind: &'static str,
+    pub name: u32,
+    /// Set by [label_object](crate::label_object).
+    pub label: Option<String>,
+    pub created_at: &'static Location<'static>,
+}
+
+/// Live objects by `glObjectLabel` identifier and name.
+static LIVE_OBJECTS: Mutex<BTreeMap<(u32, u32), LiveObject>> = Mutex::new(BTreeMap::new());
+
+/// Records `object` as live until it is deleted with [delete_object]. Only debug builds keep
+/// the registry, in release builds this just returns `object`.
+#[track_caller]
+pub fn track_object<T: GlObject>(object: T) -> T {
+    if cfg!(debug_assertions) {
+        let live_object = LiveObject {
+            kind: T::KIND,
+            name: object.name(),
+            label: None,
+            created_at: Location::caller(),
+        };
+        LIVE_OBJECTS
+            .lock()
+            .unwrap()
+            .insert((T::IDENTIFIER, object.name()), live_object);
+    }
+    object
+}
+
+/// Deletes `object` and removes it from the registry.
+pub fn delete_object<T: GlObject>(gl: &Context, object: T) {
+    if cfg!(debug_assertions) {
+        LIVE_OBJECTS
+            .lock()
+            .unwrap()
+            .remove(&(T::IDENTIFIER, object.name()));
+    }
+    unsafe { object.delete_untracked(gl) };
+}
+
+pub(crate) fn set_label(identifier: u32, name: u32, label: &str) {
+    if let Some(object) = LIVE_OBJECTS.lock().unwrap().get_mut(&(identifier, name)) {
+        object.label = Some(label.to_string());
+    }
+}
+
+/// The tracked objects that have not been deleted yet, ordered by kind and name.
+pub fn live_objects() -> Vec<LiveObject> {
+    LIVE_OBJECTS.lock().unwrap().values().cloned().collect()
+}
+
+/// Prints every tracked object that is still alive and returns how many there are.
+/// Called by [StartBuilder::start](crate::StartBuilder::start) after [MicroGLUT::exit](crate::MicroGLUT::exit).
+pub fn report_leaks() -> usize {
+    let live_objects = live_objects();
+    if !live_objects.is_empty() {
+        eprintln!("{} GL objects were not deleted:", live_objects.len());
+    }
+    for object in &live_objects {
+        match &object.label {
+            Some(label) => eprintln!(
+                "  {} {} \"{label}\", created at {}",
+                object.kind, object.name, object.created_at
+            ),
+            None => eprintln!(
+                "  {} {}, created at {}",
+                object.kind, object.name, object.created_at
+            ),
+        }
+    }
+    live_objects.len()
+}
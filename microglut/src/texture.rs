@@ -1,61 +1,270 @@
+use std::{
+    error::Error,
+    fmt,
+    hash::{Hash, Hasher},
+};
+
 use glow::{
-    Context, HasContext as _, NativeTexture, LINEAR, LINEAR_MIPMAP_LINEAR, RED, RG, RGB, RGBA,
-    TEXTURE_2D, TEXTURE_MAG_FILTER, TEXTURE_MIN_FILTER, UNSIGNED_BYTE,
+    Context, HasContext as _, NativeTexture, FLOAT, LINEAR, LINEAR_MIPMAP_LINEAR, NO_ERROR, R16,
+    R8, RED, RG, RG16, RG8, RGB, RGB16, RGB32F, RGB8, RGBA, RGBA16, RGBA16F, RGBA32F, RGBA8, SRGB8,
+    SRGB8_ALPHA8, TEXTURE_2D, TEXTURE_MAG_FILTER, TEXTURE_MIN_FILTER, UNPACK_ALIGNMENT,
+    UNSIGNED_BYTE, UNSIGNED_SHORT,
 };
+use image::DynamicImage;
 
-use crate::print_error;
+use crate::{delete_object, print_error, track_object};
 
-#[derive(Debug, Clone, Copy)]
-pub struct Texture {
-    id: NativeTexture,
+mod cache;
+mod layered;
+pub use cache::{TextureCache, TextureCacheStats};
+pub use layered::{mip_level_count, Texture3D, TextureArray2D, TextureCube};
+
+#[derive(Debug, Clone)]
+pub enum TextureError {
+    /// The image data could not be decoded.
+    Decode(String),
+    /// The image has a pixel format that can't be uploaded.
+    UnsupportedFormat(String),
+    Create(String),
+    /// A dimension is zero, or the faces of a cubemap are not square.
+    InvalidSize {
+        width: u32,
+        height: u32,
+        layers: u32,
+    },
+    /// An image given for a layer differs in size from the first one.
+    SizeMismatch {
+        layer: u32,
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    /// An OpenGL call failed while uploading the texture.
+    Gl {
+        what: String,
+        code: u32,
+    },
 }
 
-impl Texture {
-    pub fn load(gl: &Context, data: &[u8], gen_mipmap: bool) -> Self {
-        use stb_image::image::{load_from_memory, LoadResult};
-        let image = match load_from_memory(data) {
-            LoadResult::Error(e) => panic!("{}", e),
-            LoadResult::ImageU8(image) => image,
-            LoadResult::ImageF32(_image) => todo!(),
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::Decode(error) => write!(f, "failed to decode texture: {error}"),
+            TextureError::UnsupportedFormat(format) => {
+                write!(f, "unsupported texture pixel format {format}")
+            }
+            TextureError::Create(error) => write!(f, "failed to create texture: {error}"),
+            TextureError::InvalidSize {
+                width,
+                height,
+                layers,
+            } => write!(f, "invalid texture size {width}x{height}x{layers}"),
+            TextureError::SizeMismatch {
+                layer,
+                expected,
+                found,
+            } => write!(
+                f,
+                "image for layer {layer} is {}x{} but expected {}x{}",
+                found.0, found.1, expected.0, expected.1
+            ),
+            TextureError::Gl { what, code } => write!(f, "{what}: OpenGL error {code:#x}"),
+        }
+    }
+}
+
+impl Error for TextureError {}
+
+/// Like [`print_error`], but keeps the first error code.
+fn check_error(gl: &Context, what: impl Into<String>) -> Result<(), TextureError> {
+    let code = unsafe { gl.get_error() };
+    if code == NO_ERROR {
+        return Ok(());
+    }
+    let what = what.into();
+    // Report and clear the rest of the error flags as well
+    let _ = print_error(gl, &what);
+    Err(TextureError::Gl { what, code })
+}
+
+/// How the values of an 8 or 16-bit image are to be interpreted.
+///
+/// Float images (.hdr, .exr) are always linear.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ColorSpace {
+    /// Colour data like albedo maps. The GPU converts to linear when sampling.
+    Srgb,
+    /// Data that must not be converted, like normal maps, or colours that are converted in
+    /// the shader.
+    #[default]
+    Linear,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TextureOptions {
+    color_space: ColorSpace,
+    gen_mipmap: bool,
+    int_params: Vec<(u32, i32)>,
+    float_params: Vec<(u32, f32)>,
+}
+
+// Float parameters are compared by their bits, so that options can key a [TextureCache]
+impl PartialEq for TextureOptions {
+    fn eq(&self, other: &Self) -> bool {
+        self.color_space == other.color_space
+            && self.gen_mipmap == other.gen_mipmap
+            && self.int_params == other.int_params
+            && self.float_params.len() == other.float_params.len()
+            && self
+                .float_params
+                .iter()
+                .zip(&other.float_params)
+                .all(|((a, x), (b, y))| a == b && x.to_bits() == y.to_bits())
+    }
+}
+
+impl Eq for TextureOptions {}
+
+impl Hash for TextureOptions {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.color_space.hash(state);
+        self.gen_mipmap.hash(state);
+        self.int_params.hash(state);
+        for (parameter, value) in &self.float_params {
+            parameter.hash(state);
+            value.to_bits().hash(state);
+        }
+    }
+}
+
+impl TextureOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
+    /// Generates mip-maps and, unless a min filter is given, filters with
+    /// `LINEAR_MIPMAP_LINEAR`.
+    pub fn gen_mipmap(mut self, gen_mipmap: bool) -> Self {
+        self.gen_mipmap = gen_mipmap;
+        self
+    }
+
+    pub fn int_param(mut self, parameter: u32, value: i32) -> Self {
+        self.int_params.push((parameter, value));
+        self
+    }
+
+    pub fn float_param(mut self, parameter: u32, value: f32) -> Self {
+        self.float_params.push((parameter, value));
+        self
+    }
+}
+
+/// Pixel data ready for `glTexImage2D`.
+struct Pixels {
+    internal_format: u32,
+    format: u32,
+    ty: u32,
+    data: Vec<u8>,
+}
+
+impl Pixels {
+    fn new(image: DynamicImage, color_space: ColorSpace) -> Result<Self, TextureError> {
+        let srgb = color_space == ColorSpace::Srgb;
+        let bytes = |internal_format, format, image: DynamicImage| Pixels {
+            internal_format,
+            format,
+            ty: UNSIGNED_BYTE,
+            data: image.into_bytes(),
+        };
+        let shorts = |internal_format, format, data: &[u16]| Pixels {
+            internal_format,
+            format,
+            ty: UNSIGNED_SHORT,
+            data: bytemuck::cast_slice(data).to_vec(),
+        };
+        let floats = |internal_format, format, data: &[f32]| Pixels {
+            internal_format,
+            format,
+            ty: FLOAT,
+            data: bytemuck::cast_slice(data).to_vec(),
         };
 
-        unsafe {
-            let tex_id = gl.create_texture().unwrap();
-            gl.bind_texture(TEXTURE_2D, Some(tex_id));
-            print_error(gl, "texture bind_texture").unwrap();
-            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MIN_FILTER, LINEAR as i32);
-            print_error(gl, "texture param min filter").unwrap();
-            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MAG_FILTER, LINEAR as i32);
-            print_error(gl, "texture param mag filter").unwrap();
-
-            let format = match image.depth {
-                1 => RED,
-                2 => RG,
-                3 => RGB,
-                4 => RGBA,
-                d => panic!("unsupported bit depth: {}", d),
-            };
+        Ok(match image {
+            // There are no one and two channel sRGB formats, so those are expanded
+            DynamicImage::ImageLuma8(_) if srgb => {
+                bytes(SRGB8, RGB, DynamicImage::ImageRgb8(image.to_rgb8()))
+            }
+            DynamicImage::ImageLumaA8(_) if srgb => bytes(
+                SRGB8_ALPHA8,
+                RGBA,
+                DynamicImage::ImageRgba8(image.to_rgba8()),
+            ),
+            DynamicImage::ImageRgb8(_) if srgb => bytes(SRGB8, RGB, image),
+            DynamicImage::ImageRgba8(_) if srgb => bytes(SRGB8_ALPHA8, RGBA, image),
+            DynamicImage::ImageLuma8(_) => bytes(R8, RED, image),
+            DynamicImage::ImageLumaA8(_) => bytes(RG8, RG, image),
+            DynamicImage::ImageRgb8(_) => bytes(RGB8, RGB, image),
+            DynamicImage::ImageRgba8(_) => bytes(RGBA8, RGBA, image),
 
-            gl.tex_image_2d(
-                TEXTURE_2D,
-                0,
-                RGBA as _,
-                image.width as _,
-                image.height as _,
-                0,
-                format as _,
-                UNSIGNED_BYTE,
-                Some(&image.data),
-            );
-            print_error(gl, "texture image_2d").unwrap();
-            if gen_mipmap {
-                gl.generate_mipmap(TEXTURE_2D);
-                print_error(gl, "texture gen mipmap").unwrap();
-                gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MIN_FILTER, LINEAR_MIPMAP_LINEAR as _);
-                print_error(gl, "texture mipmap min filter").unwrap();
+            // Neither are there 16-bit sRGB formats, so those are linearised here
+            DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_)
+                if srgb =>
+            {
+                let mut rgba = image.to_rgba32f();
+                for pixel in rgba.pixels_mut() {
+                    for c in &mut pixel.0[..3] {
+                        *c = srgb_to_linear(*c);
+                    }
+                }
+                floats(RGBA16F, RGBA, &rgba)
             }
-            Texture { id: tex_id }
-        }
+            DynamicImage::ImageLuma16(ref image) => shorts(R16, RED, image),
+            DynamicImage::ImageLumaA16(ref image) => shorts(RG16, RG, image),
+            DynamicImage::ImageRgb16(ref image) => shorts(RGB16, RGB, image),
+            DynamicImage::ImageRgba16(ref image) => shorts(RGBA16, RGBA, image),
+
+            DynamicImage::ImageRgb32F(ref image) => floats(RGB32F, RGB, image),
+            DynamicImage::ImageRgba32F(ref image) => floats(RGBA32F, RGBA, image),
+            image => {
+                return Err(TextureError::UnsupportedFormat(format!(
+                    "{:?}",
+                    image.color()
+                )))
+            }
+        })
+    }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[derive(Debug)]
+pub struct Texture {
+    id: NativeTexture,
+    width: u32,
+    height: u32,
+    internal_format: u32,
+    levels: i32,
+}
+
+impl Texture {
+    /// Loads a PNG, JPEG, TGA, BMP, Radiance HDR or OpenEXR image. 16-bit and float images
+    /// keep their precision.
+    pub fn load(gl: &Context, data: &[u8], gen_mipmap: bool) -> Result<Self, TextureError> {
+        Self::load_with_options(gl, data, &TextureOptions::new().gen_mipmap(gen_mipmap))
     }
 
     pub fn load_with_parameters(
@@ -64,57 +273,139 @@ impl Texture {
         int_params: &[(u32, i32)],
         float_params: &[(u32, f32)],
         gen_mipmap: bool,
-    ) -> Self {
-        use stb_image::image::{load_from_memory, LoadResult};
-        let image = match load_from_memory(data) {
-            LoadResult::Error(e) => panic!("{}", e),
-            LoadResult::ImageU8(image) => image,
-            LoadResult::ImageF32(_image) => todo!(),
+    ) -> Result<Self, TextureError> {
+        let options = TextureOptions {
+            gen_mipmap,
+            int_params: int_params.to_vec(),
+            float_params: float_params.to_vec(),
+            ..Default::default()
         };
+        Self::load_with_options(gl, data, &options)
+    }
+
+    pub fn load_with_options(
+        gl: &Context,
+        data: &[u8],
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError> {
+        let image =
+            image::load_from_memory(data).map_err(|e| TextureError::Decode(e.to_string()))?;
+        Self::from_image(gl, image, options)
+    }
+
+    /// Uploads an already decoded image.
+    pub fn from_image(
+        gl: &Context,
+        image: DynamicImage,
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError> {
+        let (width, height) = (image.width(), image.height());
+        let pixels = Pixels::new(image, options.color_space)?;
 
         unsafe {
-            let tex_id = gl.create_texture().unwrap();
-            gl.bind_texture(TEXTURE_2D, Some(tex_id));
+            let tex_id = track_object(gl.create_texture().map_err(TextureError::Create)?);
+            let upload = || -> Result<(), TextureError> {
+                gl.bind_texture(TEXTURE_2D, Some(tex_id));
+                gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MIN_FILTER, LINEAR as i32);
+                gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MAG_FILTER, LINEAR as i32);
+                if options.gen_mipmap {
+                    gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MIN_FILTER, LINEAR_MIPMAP_LINEAR as _);
+                }
 
-            for (parameter, value) in int_params {
-                gl.tex_parameter_i32(TEXTURE_2D, *parameter, *value);
-                print_error(gl, &format!("texture param {}", parameter)).unwrap();
-            }
+                for (parameter, value) in &options.int_params {
+                    gl.tex_parameter_i32(TEXTURE_2D, *parameter, *value);
+                    check_error(gl, format!("texture param {}", parameter))?;
+                }
 
-            for (parameter, value) in float_params {
-                gl.tex_parameter_f32(TEXTURE_2D, *parameter, *value);
-                print_error(gl, &format!("texture param {}", parameter)).unwrap();
-            }
+                for (parameter, value) in &options.float_params {
+                    gl.tex_parameter_f32(TEXTURE_2D, *parameter, *value);
+                    check_error(gl, format!("texture param {}", parameter))?;
+                }
 
-            let format = match image.depth {
-                1 => RED,
-                2 => RG,
-                3 => RGB,
-                4 => RGBA,
-                d => panic!("unsupported bit depth: {}", d),
+                // Rows of RGB8 images are not necessarily 4-byte aligned
+                gl.pixel_store_i32(UNPACK_ALIGNMENT, 1);
+                gl.tex_image_2d(
+                    TEXTURE_2D,
+                    0,
+                    pixels.internal_format as _,
+                    width as _,
+                    height as _,
+                    0,
+                    pixels.format,
+                    pixels.ty,
+                    Some(&pixels.data),
+                );
+                gl.pixel_store_i32(UNPACK_ALIGNMENT, 4);
+                check_error(gl, "texture image_2d")?;
+                if options.gen_mipmap {
+                    gl.generate_mipmap(TEXTURE_2D);
+                    check_error(gl, "texture gen mipmap")?;
+                }
+                Ok(())
             };
-
-            gl.tex_image_2d(
-                TEXTURE_2D,
-                0,
-                RGBA as _,
-                image.width as _,
-                image.height as _,
-                0,
-                format as _,
-                UNSIGNED_BYTE,
-                Some(&image.data),
-            );
-            print_error(gl, "texture image_2d").unwrap();
-            if gen_mipmap {
-                gl.generate_mipmap(TEXTURE_2D);
-                print_error(gl, "texture gen mipmap").unwrap();
+            if let Err(error) = upload() {
+                delete_object(gl, tex_id);
+                return Err(error);
             }
-            Texture { id: tex_id }
+            Ok(Texture {
+                id: tex_id,
+                width,
+                height,
+                internal_format: pixels.internal_format,
+                levels: if options.gen_mipmap {
+                    mip_level_count(width.max(height))
+                } else {
+                    1
+                },
+            })
         }
     }
 
     pub fn id(&self) -> NativeTexture {
         self.id
     }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn internal_format(&self) -> u32 {
+        self.internal_format
+    }
+
+    /// Number of mip levels, 1 unless mip-maps were generated.
+    pub fn levels(&self) -> i32 {
+        self.levels
+    }
+
+    /// Size of the texture and its mip levels in bytes. Approximate, as drivers may pad
+    /// e.g. RGB texels to four components.
+    pub fn memory_size(&self) -> u64 {
+        let texel_size = match self.internal_format {
+            R8 => 1,
+            RG8 | R16 => 2,
+            RGB8 | SRGB8 => 3,
+            RGBA8 | SRGB8_ALPHA8 | RG16 => 4,
+            RGB16 => 6,
+            RGBA16 | RGBA16F => 8,
+            RGB32F => 12,
+            RGBA32F => 16,
+            _ => 4,
+        };
+        (0..self.levels)
+            .map(|level| {
+                let width = (self.width >> level).max(1) as u64;
+                let height = (self.height >> level).max(1) as u64;
+                width * height * texel_size
+            })
+            .sum()
+    }
+
+    pub fn delete(self, gl: &Context) {
+        delete_object(gl, self.id);
+    }
 }
@@ -0,0 +1,109 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use glow::Context;
+
+use super::{Texture, TextureOptions};
+
+/// Textures shared by every material (and model) that uses the same file with the same
+/// options, so that each is only decoded and uploaded once.
+///
+/// Textures are handed out as [Rc]s. A texture is deleted by whichever of the cache and
+/// the [Model](crate::Model)s using it lets go of it last, see [TextureCache::clear].
+#[derive(Debug, Default)]
+pub struct TextureCache {
+    textures: HashMap<(PathBuf, TextureOptions), Rc<Texture>>,
+    hits: u64,
+    misses: u64,
+}
+
+/// Counters of a [TextureCache].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextureCacheStats {
+    /// Lookups that found the texture already loaded.
+    pub hits: u64,
+    /// Lookups that loaded the texture.
+    pub misses: u64,
+    pub textures: usize,
+    /// Approximate size of the cached textures in bytes, see [Texture::memory_size].
+    pub memory_size: u64,
+}
+
+impl TextureCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the texture of `path` with `options`, calling `load` to create it if it is
+    /// not cached yet. Paths are canonicalized when the file exists, so different paths to
+    /// the same file share a texture.
+    pub fn get_or_load<E>(
+        &mut self,
+        path: &Path,
+        options: &TextureOptions,
+        load: impl FnOnce() -> Result<Texture, E>,
+    ) -> Result<Rc<Texture>, E> {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        let key = (path, options.clone());
+        if let Some(texture) = self.textures.get(&key) {
+            self.hits += 1;
+            return Ok(Rc::clone(texture));
+        }
+
+        let texture = Rc::new(load()?);
+        self.misses += 1;
+        self.textures.insert(key, Rc::clone(&texture));
+        Ok(texture)
+    }
+
+    pub fn stats(&self) -> TextureCacheStats {
+        TextureCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            textures: self.textures.len(),
+            memory_size: self
+                .textures
+                .values()
+                .map(|texture| texture.memory_size())
+                .sum(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+
+    /// Deletes the textures that are no longer used by anything but the cache, e.g. after
+    /// the models of a scene have been deleted.
+    pub fn remove_unused(&mut self, gl: &Context) {
+        let unused: Vec<_> = self
+            .textures
+            .iter()
+            .filter(|(_, texture)| Rc::strong_count(texture) == 1)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in unused {
+            if let Some(texture) = self.textures.remove(&key).and_then(Rc::into_inner) {
+                texture.delete(gl);
+            }
+        }
+    }
+
+    /// Empties the cache, deleting the textures that are not used elsewhere. The rest are
+    /// deleted along with the last model using them.
+    pub fn clear(&mut self, gl: &Context) {
+        for (_, texture) in self.textures.drain() {
+            if let Some(texture) = Rc::into_inner(texture) {
+                texture.delete(gl);
+            }
+        }
+    }
+}
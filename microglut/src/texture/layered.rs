@@ -0,0 +1,522 @@
+use glow::{
+    Context, HasContext as _, NativeTexture, PixelUnpackData, CLAMP_TO_EDGE, LINEAR,
+    LINEAR_MIPMAP_LINEAR, SRGB8, SRGB8_ALPHA8, TEXTURE0, TEXTURE_2D_ARRAY, TEXTURE_3D,
+    TEXTURE_CUBE_MAP, TEXTURE_CUBE_MAP_POSITIVE_X, TEXTURE_MAG_FILTER, TEXTURE_MIN_FILTER,
+    TEXTURE_WRAP_R, TEXTURE_WRAP_S, TEXTURE_WRAP_T, UNPACK_ALIGNMENT,
+};
+use image::DynamicImage;
+
+use super::{ColorSpace, Pixels, TextureError, TextureOptions};
+use crate::{delete_object, track_object};
+
+/// Number of levels in a full mip chain for a texture whose largest side is `size`.
+pub fn mip_level_count(size: u32) -> i32 {
+    size.max(1).ilog2() as i32 + 1
+}
+
+/// Storage shared by the layered texture types. `layers` is the depth of a 3D texture, the
+/// layer count of an array and 6 for a cubemap.
+#[derive(Debug, Clone, Copy)]
+struct Storage {
+    id: NativeTexture,
+    target: u32,
+    internal_format: u32,
+    width: u32,
+    height: u32,
+    layers: u32,
+    levels: i32,
+}
+
+impl Storage {
+    fn new(
+        gl: &Context,
+        target: u32,
+        internal_format: u32,
+        (width, height, layers): (u32, u32, u32),
+        levels: i32,
+    ) -> Result<Self, TextureError> {
+        if width == 0
+            || height == 0
+            || layers == 0
+            || (target == TEXTURE_CUBE_MAP && width != height)
+        {
+            return Err(TextureError::InvalidSize {
+                width,
+                height,
+                layers,
+            });
+        }
+        let id = track_object(unsafe { gl.create_texture() }.map_err(TextureError::Create)?);
+        unsafe {
+            gl.bind_texture(target, Some(id));
+            if target == TEXTURE_CUBE_MAP {
+                gl.tex_storage_2d(target, levels, internal_format, width as _, height as _);
+            } else {
+                gl.tex_storage_3d(
+                    target,
+                    levels,
+                    internal_format,
+                    width as _,
+                    height as _,
+                    layers as _,
+                );
+            }
+            gl.tex_parameter_i32(target, TEXTURE_MIN_FILTER, LINEAR as _);
+            gl.tex_parameter_i32(target, TEXTURE_MAG_FILTER, LINEAR as _);
+            if levels > 1 {
+                gl.tex_parameter_i32(target, TEXTURE_MIN_FILTER, LINEAR_MIPMAP_LINEAR as _);
+            }
+            for wrap in [TEXTURE_WRAP_S, TEXTURE_WRAP_T, TEXTURE_WRAP_R] {
+                gl.tex_parameter_i32(target, wrap, CLAMP_TO_EDGE as _);
+            }
+            gl.bind_texture(target, None);
+        }
+        Ok(Self {
+            id,
+            target,
+            internal_format,
+            width,
+            height,
+            layers,
+            levels,
+        })
+    }
+
+    /// Creates storage for a list of encoded images of the same size and uploads them as
+    /// layers. All images are converted to RGBA so that they share one internal format.
+    fn from_images(
+        gl: &Context,
+        target: u32,
+        images: &[&[u8]],
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError> {
+        let images = images
+            .iter()
+            .map(|data| {
+                image::load_from_memory(data)
+                    .map(to_rgba)
+                    .map_err(|e| TextureError::Decode(e.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let Some(first) = images.first() else {
+            return Err(TextureError::InvalidSize {
+                width: 0,
+                height: 0,
+                layers: 0,
+            });
+        };
+        let (width, height) = (first.width(), first.height());
+        let layers = images.len() as u32;
+
+        let pixels = images
+            .into_iter()
+            .enumerate()
+            .map(|(layer, image)| {
+                if (image.width(), image.height()) != (width, height) {
+                    return Err(TextureError::SizeMismatch {
+                        layer: layer as u32,
+                        expected: (width, height),
+                        found: (image.width(), image.height()),
+                    });
+                }
+                Pixels::new(image, options.color_space)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let levels = if options.gen_mipmap {
+            mip_level_count(width.max(height))
+        } else {
+            1
+        };
+        let storage = Self::new(
+            gl,
+            target,
+            pixels[0].internal_format,
+            (width, height, layers),
+            levels,
+        )?;
+        for (layer, pixels) in pixels.iter().enumerate() {
+            storage.upload_layer(gl, layer as u32, 0, pixels.format, pixels.ty, &pixels.data);
+        }
+        storage.apply_options(gl, options);
+        if options.gen_mipmap {
+            storage.generate_mipmap(gl);
+        }
+        Ok(storage)
+    }
+
+    fn apply_options(&self, gl: &Context, options: &TextureOptions) {
+        unsafe {
+            gl.bind_texture(self.target, Some(self.id));
+            for (parameter, value) in &options.int_params {
+                gl.tex_parameter_i32(self.target, *parameter, *value);
+            }
+            for (parameter, value) in &options.float_params {
+                gl.tex_parameter_f32(self.target, *parameter, *value);
+            }
+            gl.bind_texture(self.target, None);
+        }
+    }
+
+    fn upload_layer(
+        &self,
+        gl: &Context,
+        layer: u32,
+        level: i32,
+        format: u32,
+        ty: u32,
+        data: &[u8],
+    ) {
+        let width = (self.width >> level).max(1) as i32;
+        let height = (self.height >> level).max(1) as i32;
+        unsafe {
+            gl.bind_texture(self.target, Some(self.id));
+            gl.pixel_store_i32(UNPACK_ALIGNMENT, 1);
+            if self.target == TEXTURE_CUBE_MAP {
+                gl.tex_sub_image_2d(
+                    TEXTURE_CUBE_MAP_POSITIVE_X + layer,
+                    level,
+                    0,
+                    0,
+                    width,
+                    height,
+                    format,
+                    ty,
+                    PixelUnpackData::Slice(data),
+                );
+            } else {
+                gl.tex_sub_image_3d(
+                    self.target,
+                    level,
+                    0,
+                    0,
+                    layer as _,
+                    width,
+                    height,
+                    1,
+                    format,
+                    ty,
+                    PixelUnpackData::Slice(data),
+                );
+            }
+            gl.pixel_store_i32(UNPACK_ALIGNMENT, 4);
+            gl.bind_texture(self.target, None);
+        }
+    }
+
+    fn upload_image(&self, gl: &Context, layer: u32, data: &[u8]) -> Result<(), TextureError> {
+        let image = image::load_from_memory(data)
+            .map(to_rgba)
+            .map_err(|e| TextureError::Decode(e.to_string()))?;
+        if (image.width(), image.height()) != (self.width, self.height) {
+            return Err(TextureError::SizeMismatch {
+                layer,
+                expected: (self.width, self.height),
+                found: (image.width(), image.height()),
+            });
+        }
+        // The colour space is given by the internal format of the storage
+        let color_space = if matches!(self.internal_format, SRGB8 | SRGB8_ALPHA8) {
+            ColorSpace::Srgb
+        } else {
+            ColorSpace::Linear
+        };
+        let pixels = Pixels::new(image, color_space)?;
+        self.upload_layer(gl, layer, 0, pixels.format, pixels.ty, &pixels.data);
+        Ok(())
+    }
+
+    fn generate_mipmap(&self, gl: &Context) {
+        unsafe {
+            gl.bind_texture(self.target, Some(self.id));
+            gl.generate_mipmap(self.target);
+            gl.bind_texture(self.target, None);
+        }
+    }
+}
+
+fn to_rgba(image: DynamicImage) -> DynamicImage {
+    match image {
+        DynamicImage::ImageRgba8(_)
+        | DynamicImage::ImageRgba16(_)
+        | DynamicImage::ImageRgba32F(_) => image,
+        DynamicImage::ImageLuma16(_)
+        | DynamicImage::ImageLumaA16(_)
+        | DynamicImage::ImageRgb16(_) => DynamicImage::ImageRgba16(image.to_rgba16()),
+        DynamicImage::ImageRgb32F(_) => DynamicImage::ImageRgba32F(image.to_rgba32f()),
+        _ => DynamicImage::ImageRgba8(image.to_rgba8()),
+    }
+}
+
+macro_rules! impl_layered_texture {
+    ($type:ident, $target:expr) => {
+        impl $type {
+            pub fn id(&self) -> NativeTexture {
+                self.storage.id
+            }
+
+            pub fn internal_format(&self) -> u32 {
+                self.storage.internal_format
+            }
+
+            pub fn width(&self) -> u32 {
+                self.storage.width
+            }
+
+            pub fn height(&self) -> u32 {
+                self.storage.height
+            }
+
+            pub fn levels(&self) -> i32 {
+                self.storage.levels
+            }
+
+            /// Binds the texture to texture unit `TEXTURE0 + unit`.
+            pub fn bind(&self, gl: &Context, unit: u32) {
+                unsafe {
+                    gl.active_texture(TEXTURE0 + unit);
+                    gl.bind_texture($target, Some(self.storage.id));
+                }
+            }
+
+            /// Binds all layers of mip `level` to image unit `unit` with the internal format
+            /// of the texture. `access` is `READ_ONLY`, `WRITE_ONLY` or `READ_WRITE`.
+            pub fn bind_image(&self, gl: &Context, unit: u32, level: i32, access: u32) {
+                unsafe {
+                    gl.bind_image_texture(
+                        unit,
+                        self.storage.id,
+                        level,
+                        true,
+                        0,
+                        access,
+                        self.storage.internal_format,
+                    )
+                };
+            }
+
+            /// Binds a single layer of mip `level` to image unit `unit`, for use as an
+            /// `image2D` in shaders.
+            pub fn bind_image_layer(
+                &self,
+                gl: &Context,
+                unit: u32,
+                level: i32,
+                layer: u32,
+                access: u32,
+            ) {
+                unsafe {
+                    gl.bind_image_texture(
+                        unit,
+                        self.storage.id,
+                        level,
+                        false,
+                        layer as _,
+                        access,
+                        self.storage.internal_format,
+                    )
+                };
+            }
+
+            pub fn set_filter(&self, gl: &Context, min_filter: u32, mag_filter: u32) {
+                unsafe {
+                    gl.bind_texture($target, Some(self.storage.id));
+                    gl.tex_parameter_i32($target, TEXTURE_MIN_FILTER, min_filter as _);
+                    gl.tex_parameter_i32($target, TEXTURE_MAG_FILTER, mag_filter as _);
+                    gl.bind_texture($target, None);
+                }
+            }
+
+            pub fn set_wrap(&self, gl: &Context, wrap: u32) {
+                unsafe {
+                    gl.bind_texture($target, Some(self.storage.id));
+                    for parameter in [TEXTURE_WRAP_S, TEXTURE_WRAP_T, TEXTURE_WRAP_R] {
+                        gl.tex_parameter_i32($target, parameter, wrap as _);
+                    }
+                    gl.bind_texture($target, None);
+                }
+            }
+
+            /// Regenerates mip levels 1 and up from level 0.
+            pub fn generate_mipmap(&self, gl: &Context) {
+                self.storage.generate_mipmap(gl);
+            }
+
+            pub fn delete(self, gl: &Context) {
+                delete_object(gl, self.storage.id);
+            }
+        }
+    };
+}
+
+/// Volume texture with immutable storage.
+#[derive(Debug)]
+pub struct Texture3D {
+    storage: Storage,
+}
+
+impl_layered_texture!(Texture3D, TEXTURE_3D);
+
+impl Texture3D {
+    /// Allocates uninitialised storage, with a full mip chain if `mipmapped`.
+    pub fn new(
+        gl: &Context,
+        internal_format: u32,
+        width: u32,
+        height: u32,
+        depth: u32,
+        mipmapped: bool,
+    ) -> Result<Self, TextureError> {
+        let levels = if mipmapped {
+            mip_level_count(width.max(height).max(depth))
+        } else {
+            1
+        };
+        let size = (width, height, depth);
+        Storage::new(gl, TEXTURE_3D, internal_format, size, levels).map(|storage| Self { storage })
+    }
+
+    /// Creates a volume from encoded images of equal size, one per slice.
+    pub fn from_images(
+        gl: &Context,
+        slices: &[&[u8]],
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError> {
+        Storage::from_images(gl, TEXTURE_3D, slices, options).map(|storage| Self { storage })
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.storage.layers
+    }
+
+    /// Uploads raw pixel data to slice `slice` of mip `level`.
+    pub fn upload_slice(
+        &self,
+        gl: &Context,
+        slice: u32,
+        level: i32,
+        format: u32,
+        ty: u32,
+        data: &[u8],
+    ) {
+        self.storage
+            .upload_layer(gl, slice, level, format, ty, data);
+    }
+}
+
+/// 2D texture array with immutable storage, e.g. a sprite atlas.
+#[derive(Debug)]
+pub struct TextureArray2D {
+    storage: Storage,
+}
+
+impl_layered_texture!(TextureArray2D, TEXTURE_2D_ARRAY);
+
+impl TextureArray2D {
+    /// Allocates uninitialised storage, with a full mip chain if `mipmapped`.
+    pub fn new(
+        gl: &Context,
+        internal_format: u32,
+        width: u32,
+        height: u32,
+        layers: u32,
+        mipmapped: bool,
+    ) -> Result<Self, TextureError> {
+        let levels = if mipmapped {
+            mip_level_count(width.max(height))
+        } else {
+            1
+        };
+        let size = (width, height, layers);
+        Storage::new(gl, TEXTURE_2D_ARRAY, internal_format, size, levels)
+            .map(|storage| Self { storage })
+    }
+
+    /// Creates an array from encoded images of equal size, one per layer.
+    pub fn from_images(
+        gl: &Context,
+        layers: &[&[u8]],
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError> {
+        Storage::from_images(gl, TEXTURE_2D_ARRAY, layers, options).map(|storage| Self { storage })
+    }
+
+    pub fn layers(&self) -> u32 {
+        self.storage.layers
+    }
+
+    /// Uploads raw pixel data to `layer` of mip `level`.
+    pub fn upload_layer(
+        &self,
+        gl: &Context,
+        layer: u32,
+        level: i32,
+        format: u32,
+        ty: u32,
+        data: &[u8],
+    ) {
+        self.storage
+            .upload_layer(gl, layer, level, format, ty, data);
+    }
+
+    /// Decodes an image and uploads it to mip level 0 of `layer`. Mip-maps have to be
+    /// regenerated afterwards.
+    pub fn upload_image(&self, gl: &Context, layer: u32, data: &[u8]) -> Result<(), TextureError> {
+        self.storage.upload_image(gl, layer, data)
+    }
+}
+
+/// Cubemap with immutable storage. Faces are in the order +X, -X, +Y, -Y, +Z, -Z.
+#[derive(Debug)]
+pub struct TextureCube {
+    storage: Storage,
+}
+
+impl_layered_texture!(TextureCube, TEXTURE_CUBE_MAP);
+
+impl TextureCube {
+    /// Allocates uninitialised storage for faces of `size` x `size`, with a full mip chain
+    /// if `mipmapped`.
+    pub fn new(
+        gl: &Context,
+        internal_format: u32,
+        size: u32,
+        mipmapped: bool,
+    ) -> Result<Self, TextureError> {
+        let levels = if mipmapped { mip_level_count(size) } else { 1 };
+        Storage::new(
+            gl,
+            TEXTURE_CUBE_MAP,
+            internal_format,
+            (size, size, 6),
+            levels,
+        )
+        .map(|storage| Self { storage })
+    }
+
+    pub fn from_images(
+        gl: &Context,
+        faces: [&[u8]; 6],
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError> {
+        Storage::from_images(gl, TEXTURE_CUBE_MAP, &faces, options).map(|storage| Self { storage })
+    }
+
+    /// Uploads raw pixel data to `face` of mip `level`.
+    pub fn upload_face(
+        &self,
+        gl: &Context,
+        face: u32,
+        level: i32,
+        format: u32,
+        ty: u32,
+        data: &[u8],
+    ) {
+        self.storage.upload_layer(gl, face, level, format, ty, data);
+    }
+
+    /// Decodes an image and uploads it to mip level 0 of `face`.
+    pub fn upload_image(&self, gl: &Context, face: u32, data: &[u8]) -> Result<(), TextureError> {
+        self.storage.upload_image(gl, face, data)
+    }
+}
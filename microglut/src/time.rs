@@ -1,24 +1,143 @@
-use std::sync::{atomic::Ordering, LazyLock};
+use std::sync::{atomic::Ordering, LazyLock, Mutex};
 use std::time::Instant;
 
 use atomic_float::AtomicF32;
 
 static START: LazyLock<Instant> = LazyLock::new(Instant::now);
-// Initialize with 1.0 because 0.0 is scary.
-static DELTA: LazyLock<AtomicF32> = LazyLock::new(|| AtomicF32::new(1.0));
+// No time has passed before the first frame has been rendered
+static DELTA: LazyLock<AtomicF32> = LazyLock::new(|| AtomicF32::new(0.0));
+static CLOCK: Mutex<Clock> = Mutex::new(Clock::new());
 
-pub(crate) fn initialize() {
+/// Where [delta_time] comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TimeSource {
+    /// The wall-clock duration of the previous frame.
+    #[default]
+    RealTime,
+    /// The same number of seconds every frame, however long the frame took. Makes
+    /// animation reproducible, e.g. for headless runs that are compared image by image.
+    Fixed(f32),
+    /// Only what has been passed to [advance_time] since the previous frame.
+    Manual,
+}
+
+struct Clock {
+    source: TimeSource,
+    paused: bool,
+    steps: u32,
+    manual_delta: f32,
+    /// Sum of all delta times so far, see [simulation_time].
+    time: f64,
+}
+
+impl Clock {
+    const fn new() -> Self {
+        Clock {
+            source: TimeSource::RealTime,
+            paused: false,
+            steps: 0,
+            manual_delta: 0.0,
+            time: 0.0,
+        }
+    }
+
+    fn next_delta(&mut self, frame_duration: f32) -> f32 {
+        let delta = match self.source {
+            TimeSource::RealTime => frame_duration,
+            TimeSource::Fixed(delta) => delta,
+            TimeSource::Manual => std::mem::take(&mut self.manual_delta),
+        };
+        if !self.paused {
+            delta
+        } else if self.steps > 0 {
+            self.steps -= 1;
+            delta
+        } else {
+            0.0
+        }
+    }
+}
+
+fn clock() -> std::sync::MutexGuard<'static, Clock> {
+    CLOCK.lock().unwrap()
+}
+
+pub(crate) fn initialize(source: TimeSource, paused: bool) {
     let _ = &*START;
+    // Start from a clean clock, so nothing carries over from a previous window
+    *clock() = Clock {
+        source,
+        paused,
+        ..Clock::new()
+    };
+    // The first frame is updated before any frame duration has been measured
+    match source {
+        TimeSource::Fixed(delta) if !paused => set_delta_time(delta),
+        _ => set_delta_time(0.0),
+    }
 }
 
+/// Wall-clock seconds since the program started. Not affected by the [TimeSource] or by
+/// pausing, so it is suitable for measuring how long things take.
 pub fn elapsed_time() -> f32 {
     Instant::now().duration_since(*START).as_secs_f32()
 }
 
-pub(crate) fn set_delta_time(f: f32) {
+fn set_delta_time(f: f32) {
     DELTA.store(f, Ordering::Release)
 }
 
+/// Called after every frame with its wall-clock duration.
+pub(crate) fn end_frame(frame_duration: f32) {
+    let mut clock = clock();
+    let delta = clock.next_delta(frame_duration);
+    clock.time += delta as f64;
+    set_delta_time(delta);
+}
+
+/// Seconds to advance animation by this frame. Zero while paused.
 pub fn delta_time() -> f32 {
     DELTA.load(Ordering::Acquire)
 }
+
+/// Seconds of animation time that have passed, i.e. the sum of all delta times.
+pub fn simulation_time() -> f32 {
+    clock().time as f32
+}
+
+/// Overrides the animation time, e.g. to jump to a point in a replay. The delta time of
+/// the following frames is not affected.
+pub fn set_simulation_time(time: f32) {
+    clock().time = time as f64;
+}
+
+pub fn time_source() -> TimeSource {
+    clock().source
+}
+
+pub fn set_time_source(source: TimeSource) {
+    clock().source = source;
+}
+
+/// Adds `seconds` to the delta time of the next frame when the source is
+/// [TimeSource::Manual].
+pub fn advance_time(seconds: f32) {
+    clock().manual_delta += seconds;
+}
+
+pub fn is_paused() -> bool {
+    clock().paused
+}
+
+/// Pauses or resumes time. While paused [delta_time] is zero, except for frames requested
+/// with [step].
+pub fn set_paused(paused: bool) {
+    let mut clock = clock();
+    clock.paused = paused;
+    clock.steps = 0;
+}
+
+/// Lets one frame advance while paused.
+pub fn step() {
+    clock().steps += 1;
+}
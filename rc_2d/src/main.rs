@@ -5,29 +5,19 @@ use std::collections::HashMap;
 
 use fbo::SceneFBO;
 use microglut::{
-    delta_time,
+    debug_group, delete_object, delta_time, drawable_size,
     fbo::{bind_output_fbo, bind_texture_fbo},
     glam::{Mat4, Vec2, Vec3, Vec4},
     glow::{
-        Context, HasContext, NativeBuffer, NativeProgram, NativeTexture, NativeVertexArray,
-        ARRAY_BUFFER, BLEND, CLAMP_TO_EDGE, COLOR_BUFFER_BIT, DEPTH_BUFFER_BIT, FLOAT, FRAMEBUFFER,
-        LINEAR, ONE_MINUS_SRC_ALPHA, RGBA, RGBA32F, SHADER_STORAGE_BUFFER, SRC_ALPHA, STATIC_DRAW,
-        TEXTURE0, TEXTURE1, TEXTURE2, TEXTURE_2D_ARRAY, TEXTURE_MAG_FILTER, TEXTURE_MIN_FILTER,
-        TEXTURE_WRAP_S, TEXTURE_WRAP_T, TRIANGLES, UNSIGNED_BYTE,
+        Context, HasContext, NativeBuffer, NativeProgram, NativeVertexArray, ARRAY_BUFFER, BLEND,
+        COLOR_BUFFER_BIT, DEPTH_BUFFER_BIT, FLOAT, FRAMEBUFFER, ONE_MINUS_SRC_ALPHA,
+        SHADER_STORAGE_BUFFER, SRC_ALPHA, STATIC_DRAW, TEXTURE0, TEXTURE1, TEXTURE2, TRIANGLES,
     },
-    load_shaders, MicroGLUT, Window, FBO,
+    label_object, load_shaders, pop_debug_group, push_debug_group, track_object, DebugOutput,
+    MicroGLUT, TextureArray2D, TextureOptions, Window, FBO,
 };
 use sprite::Sprite;
 
-fn debug_message_callback(_source: u32, _type: u32, _id: u32, severity: u32, message: String) {
-    let severity = match severity {
-        DEBUG_SEVERITY_MEDIUM => "M",
-        DEBUG_SEVERITY_HIGH => "H",
-        _ => return,
-    };
-    eprintln!("[{severity}] {message}");
-}
-
 mod fbo;
 mod sprite;
 
@@ -80,15 +70,24 @@ struct App {
     probe_spacing: f32,
     interval_length: f32,
 
-    texture_array: NativeTexture,
+    texture_array: TextureArray2D,
     sprites: Vec<Sprite>,
     sprite_ssbo: NativeBuffer, // Uses binding point 0
 }
 
 impl App {
+    /// Names the screen and cascade sized targets, which are recreated on resize.
+    fn label_render_targets(&self, gl: &Context) {
+        self.scene.label(gl, "Scene");
+        self.dist_field.label(gl, "Distance field");
+        self.prev_cascade.label(gl, "Previous cascade");
+        self.curr_cascade.label(gl, "Current cascade");
+    }
+
     fn draw_scene(&mut self, gl: &Context) {
+        let _group = debug_group(gl, "Scene");
         unsafe {
-            gl.bind_framebuffer(FRAMEBUFFER, Some(self.scene.fb));
+            gl.bind_framebuffer(FRAMEBUFFER, Some(self.scene.fb()));
             gl.use_program(Some(self.scene_program));
             gl.enable(BLEND);
             gl.blend_func(SRC_ALPHA, ONE_MINUS_SRC_ALPHA);
@@ -110,8 +109,7 @@ impl App {
                 gl.enable_vertex_attrib_array(texcoord_loc);
             }
 
-            gl.active_texture(TEXTURE0);
-            gl.bind_texture(TEXTURE_2D_ARRAY, Some(self.texture_array));
+            self.texture_array.bind(gl, 0);
             gl.uniform_1_i32(
                 gl.get_uniform_location(self.scene_program, "tex_array")
                     .as_ref(),
@@ -148,6 +146,7 @@ impl App {
     }
 
     fn draw_fbo(&self, gl: &Context, source: &FBO, destination: Option<&FBO>) {
+        let _group = debug_group(gl, "Copy");
         unsafe {
             gl.use_program(Some(self.fbo_program));
             bind_texture_fbo(gl, source, TEXTURE0);
@@ -159,9 +158,12 @@ impl App {
     }
 
     fn calculate_dist_field(&mut self, gl: &Context) {
+        let _group = debug_group(gl, "Distance field");
         unsafe {
             // Seed the jump flood algorithm
-            let mut tmp = FBO::init(gl, self.screen_width, self.screen_height, false);
+            let tmp = FBO::init(gl, self.screen_width, self.screen_height, false);
+            tmp.label(gl, "Jump flood ping-pong");
+            push_debug_group(gl, "Jump flood seed");
             gl.use_program(Some(self.jfa_seed_program));
             self.scene.bind_as_textures(gl, TEXTURE0);
             bind_output_fbo(
@@ -178,6 +180,7 @@ impl App {
                 0,
             );
             self.draw_screen_quad(gl, self.jfa_seed_program);
+            pop_debug_group(gl);
 
             // Jump flood algorithm
             gl.use_program(Some(self.jfa_program));
@@ -195,6 +198,7 @@ impl App {
                 .ceil() as u32;
             let mut ping_pong = false;
             for i in 0..passes {
+                let _group = debug_group(gl, &format!("Jump flood pass {i}"));
                 if ping_pong {
                     ping_pong = false;
                     bind_texture_fbo(gl, &tmp, TEXTURE0);
@@ -228,6 +232,7 @@ impl App {
             }
 
             // Finalise the distance field
+            let _group = debug_group(gl, "Signed distance field");
             gl.use_program(Some(self.sdf_program));
             bind_texture_fbo(gl, &tmp, TEXTURE0);
             bind_output_fbo(
@@ -256,8 +261,10 @@ impl App {
         )))
         .log(4.0)
         .ceil() as i32;
+        let _group = debug_group(gl, "Cascades");
         unsafe {
             for n in (0..num_cascades).rev() {
+                let _group = debug_group(gl, &format!("Cascade {n}"));
                 gl.use_program(Some(self.rc_program));
                 bind_texture_fbo(gl, &self.prev_cascade, TEXTURE0);
                 bind_texture_fbo(gl, &self.dist_field, TEXTURE1);
@@ -344,10 +351,9 @@ impl MicroGLUT for App {
 
         let texcoords = [Vec2::ZERO, Vec2::new(2.0, 0.0), Vec2::new(0.0, 2.0)];
 
-        let screen_width = window.size().0 as i32;
-        let screen_height = window.size().1 as i32;
-        let texture_width = 200;
-        let texture_height = 200;
+        let (width, height) = drawable_size(window);
+        let screen_width = width as i32;
+        let screen_height = height as i32;
 
         let textures = vec![
             load_bytes!("textures/RainTexture1.png"),
@@ -363,16 +369,6 @@ impl MicroGLUT for App {
             ("red_circle", 3.0),
             ("white_circle", 4.0),
         ]); // Used for convenience when giving sprites textures
-        let mut img = vec![];
-        use stb_image::image::{load_from_memory, LoadResult};
-        for i in 0..textures.len() {
-            let image = match load_from_memory(textures[i]) {
-                LoadResult::Error(e) => panic!("{}", e),
-                LoadResult::ImageU8(image) => image,
-                LoadResult::ImageF32(_image) => todo!(),
-            };
-            img.extend_from_slice(&image.data);
-        }
 
         let sprites = vec![
             Sprite::new(
@@ -413,14 +409,14 @@ impl MicroGLUT for App {
         let cascade_height = (screen_height as f32) / probe_spacing_adjusted;
 
         unsafe {
-            let quad_vao = gl.create_vertex_array().unwrap();
+            let quad_vao = track_object(gl.create_vertex_array().unwrap());
             gl.bind_vertex_array(Some(quad_vao));
 
-            let quad_vbo = gl.create_buffer().unwrap();
+            let quad_vbo = track_object(gl.create_buffer().unwrap());
             gl.bind_buffer(ARRAY_BUFFER, Some(quad_vbo));
             gl.buffer_data_u8_slice(ARRAY_BUFFER, bytemuck::cast_slice(&vertices), STATIC_DRAW);
 
-            let quad_tex_vbo = gl.create_buffer().unwrap();
+            let quad_tex_vbo = track_object(gl.create_buffer().unwrap());
             gl.bind_buffer(ARRAY_BUFFER, Some(quad_tex_vbo));
             gl.buffer_data_u8_slice(ARRAY_BUFFER, bytemuck::cast_slice(&texcoords), STATIC_DRAW);
 
@@ -429,32 +425,51 @@ impl MicroGLUT for App {
                 gl,
                 include_str!("scene_vertex.glsl"),
                 include_str!("scene_fragment.glsl"),
-            );
+            )
+            .unwrap();
             let rc_program = load_shaders(
                 gl,
                 include_str!("vertex.glsl"),
                 include_str!("rc_bilinear.glsl"),
-            );
+            )
+            .unwrap();
             let jfa_seed_program = load_shaders(
                 gl,
                 include_str!("vertex.glsl"),
                 include_str!("dist_field/seed_jump_flood.glsl"),
-            );
+            )
+            .unwrap();
             let jfa_program = load_shaders(
                 gl,
                 include_str!("vertex.glsl"),
                 include_str!("dist_field/jump_flood.glsl"),
-            );
+            )
+            .unwrap();
             let sdf_program = load_shaders(
                 gl,
                 include_str!("vertex.glsl"),
                 include_str!("dist_field/dist_field.glsl"),
-            );
+            )
+            .unwrap();
             let fbo_program = load_shaders(
                 gl,
                 include_str!("vertex.glsl"),
                 include_str!("fbo_fragment.glsl"),
-            );
+            )
+            .unwrap();
+            for (program, label) in [
+                (scene_program, "Scene"),
+                (rc_program, "Radiance cascades"),
+                (jfa_seed_program, "Jump flood seed"),
+                (jfa_program, "Jump flood"),
+                (sdf_program, "Signed distance field"),
+                (fbo_program, "Copy"),
+            ] {
+                label_object(gl, program, label);
+            }
+            label_object(gl, quad_vao, "Screen quad");
+            label_object(gl, quad_vbo, "Screen quad positions");
+            label_object(gl, quad_tex_vbo, "Screen quad texcoords");
 
             let dist_field = FBO::init(gl, screen_width, screen_height, false);
             let scene = SceneFBO::init(gl, screen_width, screen_height, 2);
@@ -462,34 +477,11 @@ impl MicroGLUT for App {
             let curr_cascade = FBO::init(gl, cascade_width as _, cascade_height as _, false);
 
             // Load sprite textures into a texture array
-            let texture_array = gl.create_texture().unwrap();
-            gl.bind_texture(TEXTURE_2D_ARRAY, Some(texture_array));
-            gl.tex_storage_3d(
-                TEXTURE_2D_ARRAY,
-                1,
-                RGBA,
-                texture_width,
-                texture_height,
-                textures.len() as _,
-            );
-            gl.tex_parameter_i32(TEXTURE_2D_ARRAY, TEXTURE_MIN_FILTER, LINEAR as _);
-            gl.tex_parameter_i32(TEXTURE_2D_ARRAY, TEXTURE_MAG_FILTER, LINEAR as _);
-            gl.tex_parameter_i32(TEXTURE_2D_ARRAY, TEXTURE_WRAP_S, CLAMP_TO_EDGE as _);
-            gl.tex_parameter_i32(TEXTURE_2D_ARRAY, TEXTURE_WRAP_T, CLAMP_TO_EDGE as _);
-            gl.tex_image_3d(
-                TEXTURE_2D_ARRAY,
-                0,
-                RGBA32F as _,
-                texture_width,
-                texture_height,
-                textures.len() as _,
-                0,
-                RGBA,
-                UNSIGNED_BYTE,
-                Some(&img),
-            );
+            let texture_array =
+                TextureArray2D::from_images(gl, &textures, &TextureOptions::new()).unwrap();
+            label_object(gl, texture_array.id(), "Sprite textures");
 
-            let sprite_ssbo = gl.create_buffer().unwrap();
+            let sprite_ssbo = track_object(gl.create_buffer().unwrap());
             gl.bind_buffer(SHADER_STORAGE_BUFFER, Some(sprite_ssbo));
             gl.buffer_data_u8_slice(
                 SHADER_STORAGE_BUFFER,
@@ -497,10 +489,11 @@ impl MicroGLUT for App {
                 STATIC_DRAW,
             );
             gl.bind_buffer_base(SHADER_STORAGE_BUFFER, 0, Some(sprite_ssbo));
+            label_object(gl, sprite_ssbo, "Sprites");
 
             gl.viewport(0, 0, screen_width, screen_height);
 
-            App {
+            let app = App {
                 quad_vao,
                 quad_vertex_buffer: quad_vbo,
                 quad_texcoord_buffer: quad_tex_vbo,
@@ -523,7 +516,9 @@ impl MicroGLUT for App {
                 texture_array,
                 sprites,
                 sprite_ssbo,
-            }
+            };
+            app.label_render_targets(gl);
+            app
         }
     }
 
@@ -542,12 +537,66 @@ impl MicroGLUT for App {
         self.calculate_cascades(gl);
         self.draw_fbo(gl, &self.curr_cascade, None);
     }
+
+    fn resize(&mut self, gl: &Context, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            // Minimised
+            return;
+        }
+        self.screen_width = width as _;
+        self.screen_height = height as _;
+        self.cascade_width = width as f32 / self.probe_spacing;
+        self.cascade_height = height as f32 / self.probe_spacing;
+
+        let scene = SceneFBO::init(gl, self.screen_width, self.screen_height, 2);
+        std::mem::replace(&mut self.scene, scene).delete(gl);
+        for (fbo, width, height) in [
+            (&mut self.dist_field, self.screen_width, self.screen_height),
+            (
+                &mut self.prev_cascade,
+                self.cascade_width as _,
+                self.cascade_height as _,
+            ),
+            (
+                &mut self.curr_cascade,
+                self.cascade_width as _,
+                self.cascade_height as _,
+            ),
+        ] {
+            std::mem::replace(fbo, FBO::init(gl, width, height, false)).delete(gl);
+        }
+
+        self.label_render_targets(gl);
+        unsafe { gl.viewport(0, 0, self.screen_width, self.screen_height) };
+    }
+
+    fn exit(self, gl: &Context) {
+        delete_object(gl, self.quad_vao);
+        delete_object(gl, self.quad_vertex_buffer);
+        delete_object(gl, self.quad_texcoord_buffer);
+        for program in [
+            self.scene_program,
+            self.rc_program,
+            self.jfa_seed_program,
+            self.jfa_program,
+            self.sdf_program,
+            self.fbo_program,
+        ] {
+            delete_object(gl, program);
+        }
+        self.scene.delete(gl);
+        self.dist_field.delete(gl);
+        self.prev_cascade.delete(gl);
+        self.curr_cascade.delete(gl);
+        self.texture_array.delete(gl);
+        delete_object(gl, self.sprite_ssbo);
+    }
 }
 
 fn main() {
     App::sdl2_window("Radiance cascades 2D prototype")
         .gl_version(4, 5)
-        .debug_message_callback(debug_message_callback)
+        .debug_output(DebugOutput::new())
         .window_size(1024, 1024)
         .start();
 }
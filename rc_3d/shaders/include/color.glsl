@@ -0,0 +1,9 @@
+#include "rc_constants.glsl"
+
+vec3 linear_to_srgb(vec3 c) {
+    return pow(c.rgb, vec3(1.0 / gamma));
+}
+
+vec3 srgb_to_linear(vec3 c) {
+    return pow(c.rgb, vec3(gamma));
+}
@@ -0,0 +1,10 @@
+layout(std430) readonly buffer HiZConstants {
+    vec2 hi_z_resolution;
+    vec2 inv_hi_z_resolution;
+    float hi_z_start_mip_level;
+    float hi_z_max_mip_level;
+    float max_steps;
+    float max_ray_distance;
+    float z_far;
+    float z_near;
+};
@@ -0,0 +1,12 @@
+vec3 octahedral_decode(vec2 v) {
+    // Based on https://knarkowicz.wordpress.com/2014/04/16/octahedron-normal-vector-encoding/
+    //vec2 v_adjusted = 2.0 * v - 1.0;
+    vec2 v_adjusted = v;
+    vec3 n = vec3(v_adjusted.xy, 1.0 - abs(v_adjusted.x) - abs(v_adjusted.y));
+    float t = max((-n.z), 0.0);
+    return normalize(vec3(
+        n.x + ((n.x >= 0.0) ? (-t) : t),
+        n.y + ((n.y >= 0.0) ? (-t) : t),
+        n.z
+    ));
+}
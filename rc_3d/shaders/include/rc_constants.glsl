@@ -0,0 +1,11 @@
+layout(std430) readonly buffer RCConstants {
+    vec2 c0_resolution;
+    float num_cascades;
+    float c0_probe_spacing;
+    float c0_interval_length;
+    float normal_offset;    // Offset probe position along surface normals
+    float gamma;
+    float ambient_occlusion_factor;
+    float diffuse_intensity;
+    float ambient_occlusion;
+};
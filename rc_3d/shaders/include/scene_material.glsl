@@ -0,0 +1,102 @@
+// Material of the fragment being shaded. Comes from the uniforms set through
+// MaterialBindings, or with BATCHED defined from the material table of a BatchedModel.
+
+#ifdef BATCHED
+
+#include "materials.glsl"
+
+flat in uint material_index;
+
+bool has_opacity_texture() {
+    return material_has_texture(materials[material_index], MATERIAL_DISSOLVE_TEXTURE);
+}
+
+float sample_opacity(vec2 tex_coord) {
+    return material_texture(materials[material_index], MATERIAL_DISSOLVE_TEXTURE, tex_coord).r;
+}
+
+bool has_diffuse_texture() {
+    return material_has_texture(materials[material_index], MATERIAL_DIFFUSE_TEXTURE);
+}
+
+vec4 sample_diffuse(vec2 tex_coord) {
+    return material_texture(materials[material_index], MATERIAL_DIFFUSE_TEXTURE, tex_coord);
+}
+
+bool has_diffuse_color() {
+    return material_has(materials[material_index], MATERIAL_HAS_DIFFUSE);
+}
+
+vec3 diffuse_color() {
+    return materials[material_index].diffuse.rgb;
+}
+
+bool has_normal_texture() {
+    return material_has_texture(materials[material_index], MATERIAL_NORMAL_TEXTURE);
+}
+
+vec3 sample_normal(vec2 tex_coord) {
+    return material_texture(materials[material_index], MATERIAL_NORMAL_TEXTURE, tex_coord).xyz;
+}
+
+vec3 emissive_color() {
+    return materials[material_index].emissive.rgb;
+}
+
+#else
+
+uniform vec3 emissive;
+uniform int has_emissive;
+uniform vec3 diffuse;
+uniform int has_diffuse;
+uniform vec3 specular;
+uniform int has_specular;
+uniform float opacity;
+uniform int has_opacity;
+
+uniform sampler2D diffuse_tex;
+uniform int has_diffuse_tex;
+uniform sampler2D specular_tex;
+uniform int has_specular_tex;
+uniform sampler2D opacity_tex;
+uniform int has_opacity_tex;
+uniform sampler2D normal_map;
+uniform int has_normal_map;
+
+bool has_opacity_texture() {
+    return has_opacity_tex == 1;
+}
+
+float sample_opacity(vec2 tex_coord) {
+    return texture(opacity_tex, tex_coord).r;
+}
+
+bool has_diffuse_texture() {
+    return has_diffuse_tex == 1;
+}
+
+vec4 sample_diffuse(vec2 tex_coord) {
+    return texture(diffuse_tex, tex_coord);
+}
+
+bool has_diffuse_color() {
+    return has_diffuse == 1;
+}
+
+vec3 diffuse_color() {
+    return diffuse;
+}
+
+bool has_normal_texture() {
+    return has_normal_map == 1;
+}
+
+vec3 sample_normal(vec2 tex_coord) {
+    return texture(normal_map, tex_coord).xyz;
+}
+
+vec3 emissive_color() {
+    return emissive;
+}
+
+#endif
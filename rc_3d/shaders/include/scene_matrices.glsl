@@ -0,0 +1,8 @@
+layout(std430) readonly buffer SceneMatrices {
+    mat4 world_to_view;
+    mat4 world_to_view_inv;
+    mat4 perspective;
+    mat4 perspective_inv;
+    vec2 screen_res;
+    vec2 screen_res_inv;
+};
@@ -0,0 +1,40 @@
+#include "scene_matrices.glsl"
+#include "hi_z_constants.glsl"
+
+const bool REMAP_DEPTH = false;
+
+float screen_depth_to_view_depth(float depth) {
+    // NOTE: These calculations depend on the projection matrix
+    if (REMAP_DEPTH) {
+        float remapped_depth = depth * 2.0 - 1.0;
+        return - z_near * z_far / (z_far + remapped_depth * (z_near - z_far));
+    }
+    return - z_near * z_far / (z_far + depth * (z_near - z_far));
+}
+
+// pixel_coord.xy is the fragment coordinate (gl_FragCoord.xy),
+// pixel_coord.z is the depth saved in the depth buffer for the pixel
+vec4 screen_pos_to_view_pos(vec3 pixel_coord) {
+    // Adapted from https://www.khronos.org/opengl/wiki/Compute_eye_space_from_window_space
+    vec3 ndc = vec3(
+        2.0 * pixel_coord.x * screen_res_inv.x - 1.0,
+        2.0 * pixel_coord.y * screen_res_inv.y - 1.0,
+        2.0 * pixel_coord.z - 1.0
+    );
+
+    float clip_w = perspective[3].z / (ndc.z - perspective[2].z / perspective[2].w);
+    vec4 clip_pos = vec4(ndc.xyz * clip_w, clip_w);
+    return perspective_inv * clip_pos;
+}
+
+vec4 view_pos_to_screen_pos(vec3 view_pos) {
+    vec4 clip_pos = perspective * vec4(view_pos, 1.0);
+    vec4 ndc = vec4(clip_pos.xyz / clip_pos.w, clip_pos.w);
+    vec4 screen_pos = vec4(
+        (ndc.x + 1.0) * 0.5 * screen_res.x,
+        (ndc.y + 1.0) * 0.5 * screen_res.y,
+        (ndc.z + 1.0) * 0.5,
+        ndc.w
+    );
+    return screen_pos;
+}
@@ -6,78 +6,8 @@ uniform sampler2D scene_normal;
 uniform sampler2D hi_z_tex;
 out vec4 color;
 
-const bool REMAP_DEPTH = false;
-
-layout(std430) buffer SceneMatrices {
-    mat4 world_to_view;
-    mat4 world_to_view_inv;
-    mat4 perspective;
-    mat4 perspective_inv;
-    vec2 screen_res;
-    vec2 screen_res_inv;
-};
-
-layout(std430) buffer HiZConstants {
-    vec2 hi_z_resolution;
-    vec2 inv_hi_z_resolution;
-
-    float hi_z_start_mip_level;
-    float hi_z_max_mip_level;
-    float max_steps;
-    float max_ray_distance;
-
-    float z_far;
-    float z_near;
-};
-
-vec3 octahedral_decode(vec2 v) {
-    // Based on https://knarkowicz.wordpress.com/2014/04/16/octahedron-normal-vector-encoding/
-    //vec2 v_adjusted = 2.0 * v - 1.0;
-    vec2 v_adjusted = v;
-    vec3 n = vec3(v_adjusted.xy, 1.0 - abs(v_adjusted.x) - abs(v_adjusted.y));
-    float t = max((-n.z), 0.0);
-    return normalize(vec3(
-        n.x + ((n.x >= 0.0) ? (-t) : t),
-        n.y + ((n.y >= 0.0) ? (-t) : t),
-        n.z
-    ));
-}
-
-float screen_depth_to_view_depth(float depth) {
-    // NOTE: These calculations depend on the projection matrix
-    if (REMAP_DEPTH) {
-        float remapped_depth = depth * 2.0 - 1.0;
-        return - z_near * z_far / (z_far + remapped_depth * (z_near - z_far));
-    }
-    return - z_near * z_far / (z_far + depth * (z_near - z_far));
-}
-
-// pixel_coord.xy is the fragment coordinate (gl_FragCoord.xy),
-// pixel_coord.z is the depth saved in the depth buffer for the pixel
-vec4 screen_pos_to_view_pos(vec3 pixel_coord) {
-    // Adapted from https://www.khronos.org/opengl/wiki/Compute_eye_space_from_window_space
-    vec3 ndc = vec3(
-        2.0 * pixel_coord.x * screen_res_inv.x - 1.0,
-        2.0 * pixel_coord.y * screen_res_inv.y - 1.0,
-        2.0 * pixel_coord.z - 1.0
-    );
-
-    float clip_w = perspective[3].z / (ndc.z - perspective[2].z / perspective[2].w);
-    vec4 clip_pos = vec4(ndc.xyz * clip_w, clip_w);
-    return perspective_inv * clip_pos;
-}
-
-vec4 view_pos_to_screen_pos(vec3 view_pos) {
-    vec4 clip_pos = perspective * vec4(view_pos, 1.0);
-    vec4 ndc = vec4(clip_pos.xyz / clip_pos.w, clip_pos.w);
-    vec4 screen_pos = vec4(
-        (ndc.x + 1.0) * 0.5 * screen_res.x,
-        (ndc.y + 1.0) * 0.5 * screen_res.y,
-        (ndc.z + 1.0) * 0.5,
-        ndc.w
-    );
-    return screen_pos;
-}
+#include "screen_space.glsl"
+#include "octahedral.glsl"
 
 bool trace(vec3 ray_start_vs, vec3 direction_vs, out vec3 hit, out float iters) {
     hit = vec3(-1.0);
@@ -1,43 +1,29 @@
-#[macro_use]
-extern crate load_file;
-
-use std::{collections::VecDeque, f32::consts::PI, ffi::CStr};
+use std::{collections::VecDeque, f32::consts::PI, path::Path};
 
 use bytemuck::{Pod, Zeroable};
 use camera::Camera;
 use microglut::{
-    delta_time, elapsed_time,
+    debug_group, delete_object, dpi_scale, drawable_size, elapsed_time,
     glam::{Mat4, Quat, Vec2, Vec3, Vec4},
     glow::{
-        Context, HasContext, NativeBuffer, NativeProgram, PixelPackData, BLEND, COLOR_ATTACHMENT0,
-        COLOR_ATTACHMENT3, COLOR_BUFFER_BIT, CULL_FACE, DEBUG_OUTPUT, DEPTH_BUFFER_BIT, DEPTH_TEST,
-        DRAW_FRAMEBUFFER, FRAMEBUFFER, LINEAR, MULTISAMPLE, ONE_MINUS_SRC_ALPHA, READ_FRAMEBUFFER,
-        RGBA, SHADER_STORAGE_BUFFER, SRC_ALPHA, STATIC_DRAW, TEXTURE0, TEXTURE1, TEXTURE2,
-        TEXTURE_2D, TEXTURE_MAX_LEVEL, UNSIGNED_BYTE,
-    },
-    imgui, load_shaders, load_tangent_buf,
-    sdl2::{
-        keyboard::{Keycode, Mod, Scancode},
-        mouse::MouseButton,
+        Context, HasContext, NativeBuffer, BACK, BLEND, COLOR_ATTACHMENT0, COLOR_ATTACHMENT3,
+        COLOR_BUFFER_BIT, CULL_FACE, DEPTH_BUFFER_BIT, DEPTH_TEST, DRAW_FRAMEBUFFER, FRAMEBUFFER,
+        LINEAR, MULTISAMPLE, ONE_MINUS_SRC_ALPHA, READ_FRAMEBUFFER, SHADER_STORAGE_BUFFER,
+        SRC_ALPHA, STATIC_DRAW, TEXTURE0, TEXTURE1, TEXTURE2, TEXTURE_2D, TEXTURE_MAX_LEVEL,
     },
-    MaterialBindings, MicroGLUT, Model, Texture, Window,
+    imgui, is_key_down, label_object, mip_level_count,
+    sdl2::{keyboard::Scancode, mouse::MouseButton},
+    track_object, BatchedModel, DebugOutput, GpuProfiler, LoadShaders, MaterialBinding,
+    MaterialBindings, MicroGLUT, Model, Program, ReadbackImage, ShaderStage, Texture, TextureCache,
+    TextureUnit, Window,
 };
-use object::Object;
+use object::{scene_aabb, Object};
 use quad_renderer::QuadRenderer;
 use radiance_cascades::RadianceCascades;
 use scene_fbo::SceneFBO;
 use strum::{Display, VariantArray};
 use voxelizer::Voxelizer;
 
-fn debug_message_callback(_source: u32, _type: u32, _id: u32, severity: u32, message: String) {
-    let severity = match severity {
-        DEBUG_SEVERITY_MEDIUM => "M",
-        DEBUG_SEVERITY_HIGH => "H",
-        _ => return,
-    };
-    eprintln!("[{severity}] {message}");
-}
-
 mod camera;
 mod object;
 mod quad_renderer;
@@ -45,6 +31,58 @@ mod radiance_cascades;
 mod scene_fbo;
 mod voxelizer;
 
+/// Directory of the shared GLSL files in the source tree, for shaders that are read from disk
+/// to be hot reloaded.
+const SHADER_INCLUDE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/include");
+
+/// Shared GLSL files available to `#include` in shaders that are embedded in the binary.
+const SHADER_INCLUDES: &[(&str, &str)] = &[
+    (
+        "scene_matrices.glsl",
+        include_str!("../shaders/include/scene_matrices.glsl"),
+    ),
+    (
+        "hi_z_constants.glsl",
+        include_str!("../shaders/include/hi_z_constants.glsl"),
+    ),
+    (
+        "rc_constants.glsl",
+        include_str!("../shaders/include/rc_constants.glsl"),
+    ),
+    (
+        "screen_space.glsl",
+        include_str!("../shaders/include/screen_space.glsl"),
+    ),
+    (
+        "octahedral.glsl",
+        include_str!("../shaders/include/octahedral.glsl"),
+    ),
+    ("color.glsl", include_str!("../shaders/include/color.glsl")),
+    (
+        "scene_material.glsl",
+        include_str!("../shaders/include/scene_material.glsl"),
+    ),
+    ("materials.glsl", BatchedModel::MATERIALS_GLSL),
+];
+
+/// Storage buffer binding of the material table of batched models.
+const MATERIAL_TABLE_BINDING: u32 = 3;
+
+/// Uniforms of `scene_material.glsl` that materials are bound to when not drawing batched.
+fn scene_material_bindings() -> MaterialBindings {
+    MaterialBindings {
+        emissive: Some(MaterialBinding::new("emissive")),
+        diffuse: Some(MaterialBinding::new("diffuse")),
+        specular: Some(MaterialBinding::new("specular")),
+        dissolve: Some(MaterialBinding::new("opacity")),
+        diffuse_texture: Some((MaterialBinding::new("diffuse_tex"), 0)),
+        specular_texture: Some((MaterialBinding::new("specular_tex"), 1)),
+        normal_texture: Some((MaterialBinding::new("normal_map"), 2)),
+        dissolve_texture: Some((MaterialBinding::new("opacity_tex"), 3)),
+        ..Default::default()
+    }
+}
+
 #[repr(C)]
 #[derive(Default, Clone, Copy, Pod, Zeroable)]
 struct SceneMatrices {
@@ -83,12 +121,17 @@ enum DebugMode {
 }
 
 struct App {
-    scene_program: NativeProgram,
-    depth_program: NativeProgram,
-    ssrt_program: NativeProgram,
+    scene_program: Program,
+    batched_scene_program: Program,
+    depth_program: Program,
+    ssrt_program: Program,
 
     screen_resolution: Vec2,
+    /// Drawable pixels per screen coordinate, see [dpi_scale].
+    dpi_scale: f32,
     objects: Vec<Object>,
+    texture_cache: TextureCache,
+    material_bindings: MaterialBindings,
     camera: Camera,
 
     scene: SceneFBO,
@@ -106,9 +149,12 @@ struct App {
 
     debug: bool,
     debug_mode: DebugMode,
+    /// Draw every object with one multi-draw, reading materials from a storage buffer.
+    batched_drawing: bool,
 
     mouse_is_down: bool,
     frame_times: VecDeque<f32>,
+    profiler: GpuProfiler,
 }
 
 impl App {
@@ -136,8 +182,14 @@ impl App {
             );
             gl.bind_buffer(SHADER_STORAGE_BUFFER, None);
 
-            gl.bind_framebuffer(FRAMEBUFFER, Some(self.scene.fb));
-            gl.use_program(Some(self.scene_program));
+            let program = if self.batched_drawing {
+                &self.batched_scene_program
+            } else {
+                &self.scene_program
+            };
+
+            gl.bind_framebuffer(FRAMEBUFFER, Some(self.scene.fb()));
+            program.use_program(gl);
             gl.enable(BLEND);
             gl.enable(DEPTH_TEST);
             //gl.enable(CULL_FACE);
@@ -152,63 +204,22 @@ impl App {
             gl.clear_color(0.0, 0.0, 0.0, 0.0);
             gl.clear(COLOR_BUFFER_BIT | DEPTH_BUFFER_BIT);
 
-            gl.uniform_matrix_4_f32_slice(
-                gl.get_uniform_location(self.scene_program, "world_to_view")
-                    .as_ref(),
-                false,
-                w_t_v.as_ref(),
-            );
-            gl.uniform_matrix_4_f32_slice(
-                gl.get_uniform_location(self.scene_program, "projection")
-                    .as_ref(),
-                false,
-                perspective_mat.as_ref(),
-            );
-
-            let material_bindings = MaterialBindings {
-                ambient: None,
-                emissive: Some(String::from("emissive")),
-                diffuse: Some(String::from("diffuse")),
-                specular: Some(String::from("specular")),
-                shininess: None,
-                dissolve: Some(String::from("opacity")),
-                optical_density: None,
-                ambient_texture: None,
-                diffuse_texture: Some((String::from("diffuse_tex"), 0)),
-                specular_texture: Some((String::from("specular_tex"), 1)),
-                normal_texture: Some((String::from("normal_map"), 2)),
-                shininess_texture: None,
-                dissolve_texture: Some((String::from("opacity_tex"), 3)),
-                illumination_model: None,
-            };
+            program.set_uniform(gl, "world_to_view", w_t_v);
+            program.set_uniform(gl, "projection", perspective_mat);
 
             for object in &self.objects {
-                gl.uniform_matrix_4_f32_slice(
-                    gl.get_uniform_location(self.scene_program, "model_to_world")
-                        .as_ref(),
-                    false,
-                    object.get_transformation().as_ref(),
-                );
-                gl.uniform_4_f32_slice(
-                    gl.get_uniform_location(self.scene_program, "v_albedo")
-                        .as_ref(),
-                    object.albedo.as_ref(),
-                );
-                gl.uniform_4_f32_slice(
-                    gl.get_uniform_location(self.scene_program, "v_emissive")
-                        .as_ref(),
-                    object.emissive.as_ref(),
-                );
-                object.model.draw(
-                    gl,
-                    self.scene_program,
-                    "position",
-                    Some("v_normal"),
-                    Some("v_tex_coord"),
-                    Some("v_tangent"),
-                    Some("v_bitangent"),
-                    Some(&material_bindings),
-                );
+                program.set_uniform(gl, "model_to_world", object.get_transformation());
+                program.set_uniform(gl, "v_albedo", object.albedo);
+                program.set_uniform(gl, "v_emissive", object.emissive);
+                match &object.batch {
+                    Some(batch) if self.batched_drawing => {
+                        batch.bind_materials(gl, program, MATERIAL_TABLE_BINDING, 0);
+                        batch.draw(gl);
+                    }
+                    _ => object
+                        .model
+                        .draw(gl, program, Some(&self.material_bindings)),
+                }
             }
 
             gl.bind_framebuffer(FRAMEBUFFER, None);
@@ -225,34 +236,23 @@ impl App {
             // to properly populate the first level of min & max depth
             // Note: It is possible to write the level 0 data in the shader for the scene,
             // but this fills level 0 with the scene clear colour for any holes in the scene
-            gl.use_program(Some(self.depth_program));
-            gl.bind_framebuffer(FRAMEBUFFER, Some(self.scene.fb));
+            let program = &self.depth_program;
+            program.use_program(gl);
+            gl.bind_framebuffer(FRAMEBUFFER, Some(self.scene.fb()));
             gl.active_texture(TEXTURE0);
             gl.bind_texture(TEXTURE_2D, Some(self.scene.depth_texture));
-            gl.uniform_1_i32(
-                gl.get_uniform_location(self.depth_program, "depth_tex")
-                    .as_ref(),
-                0,
-            );
+            program.set_uniform(gl, "depth_tex", TextureUnit(0));
             gl.framebuffer_texture(
                 FRAMEBUFFER,
                 COLOR_ATTACHMENT3,
                 Some(self.scene.hi_z_texture),
                 0,
             );
-            gl.uniform_2_f32_slice(
-                gl.get_uniform_location(self.depth_program, "dimensions")
-                    .as_ref(),
-                start_dims.as_ref(),
-            );
-            gl.uniform_1_i32(
-                gl.get_uniform_location(self.depth_program, "level")
-                    .as_ref(),
-                0,
-            );
+            program.set_uniform(gl, "dimensions", start_dims);
+            program.set_uniform(gl, "level", 0);
 
             gl.viewport(0, 0, start_dims.x as _, start_dims.y as _);
-            self.quad_renderer.draw_screen_quad(gl, self.depth_program);
+            self.quad_renderer.draw_screen_quad(gl, program.id());
 
             // Calculate each mip-level using the previous one as the input
             gl.bind_texture(TEXTURE_2D, Some(self.scene.hi_z_texture));
@@ -266,32 +266,16 @@ impl App {
                     Some(self.scene.hi_z_texture),
                     level,
                 );
-                gl.uniform_2_f32_slice(
-                    gl.get_uniform_location(self.depth_program, "dimensions")
-                        .as_ref(),
-                    mip_dims.as_ref(),
-                );
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(self.depth_program, "level")
-                        .as_ref(),
-                    level,
-                );
-                gl.uniform_1_i32(
-                    gl.get_uniform_location(self.depth_program, "prev_mip_level")
-                        .as_ref(),
-                    level - 1,
-                );
-                gl.uniform_2_f32_slice(
-                    gl.get_uniform_location(self.depth_program, "prev_level_dimensions")
-                        .as_ref(),
-                    prev_dims.as_ref(),
-                );
+                program.set_uniform(gl, "dimensions", mip_dims);
+                program.set_uniform(gl, "level", level);
+                program.set_uniform(gl, "prev_mip_level", level - 1);
+                program.set_uniform(gl, "prev_level_dimensions", prev_dims);
 
                 // Prevent reading the current mip-level as that would be undefined behaviour
                 gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MAX_LEVEL, level - 1);
 
                 gl.viewport(0, 0, mip_dims.x as _, mip_dims.y as _);
-                self.quad_renderer.draw_screen_quad(gl, self.depth_program);
+                self.quad_renderer.draw_screen_quad(gl, program.id());
             }
 
             // Restore to original value
@@ -315,30 +299,19 @@ impl App {
 
     fn draw_ssrt(&self, gl: &Context) {
         unsafe {
-            gl.use_program(Some(self.ssrt_program));
+            let program = &self.ssrt_program;
+            program.use_program(gl);
             gl.bind_framebuffer(FRAMEBUFFER, None);
 
             gl.active_texture(TEXTURE0);
             gl.bind_texture(TEXTURE_2D, Some(self.scene.hi_z_texture));
-            gl.uniform_1_i32(
-                gl.get_uniform_location(self.ssrt_program, "hi_z_tex")
-                    .as_ref(),
-                0,
-            );
+            program.set_uniform(gl, "hi_z_tex", TextureUnit(0));
             gl.active_texture(TEXTURE1);
             gl.bind_texture(TEXTURE_2D, Some(self.scene.albedo));
-            gl.uniform_1_i32(
-                gl.get_uniform_location(self.ssrt_program, "scene_albedo")
-                    .as_ref(),
-                1,
-            );
+            program.set_uniform(gl, "scene_albedo", TextureUnit(1));
             gl.active_texture(TEXTURE2);
             gl.bind_texture(TEXTURE_2D, Some(self.scene.normal));
-            gl.uniform_1_i32(
-                gl.get_uniform_location(self.ssrt_program, "scene_normal")
-                    .as_ref(),
-                2,
-            );
+            program.set_uniform(gl, "scene_normal", TextureUnit(2));
 
             gl.viewport(
                 0,
@@ -347,51 +320,53 @@ impl App {
                 self.screen_resolution.y as _,
             );
             gl.clear(COLOR_BUFFER_BIT | DEPTH_BUFFER_BIT);
-            self.quad_renderer.draw_screen_quad(gl, self.ssrt_program);
+            self.quad_renderer.draw_screen_quad(gl, program.id());
         }
     }
 
-    fn save_screen_to(&self, gl: &Context) {
-        unsafe {
-            let mut image: Vec<u8> = vec![];
-            image.resize(4 * 1280 * 720, 0);
-            let p = PixelPackData::Slice(&mut image);
-            //gl.read_buffer(COLOR_ATTACHMENT0);
-            //gl.pixel_store_i32(parameter, value);
-            gl.read_pixels(
-                0,
-                0,
-                self.screen_resolution.x as _,
-                self.screen_resolution.y as _,
-                RGBA,
-                UNSIGNED_BYTE,
-                p,
-            );
+    fn save_screen_to(&self, gl: &Context, path: &str) {
+        let result = ReadbackImage::from_framebuffer(
+            gl,
+            None,
+            BACK,
+            self.screen_resolution.x as _,
+            self.screen_resolution.y as _,
+        )
+        .and_then(|image| image.save(path));
+        if let Err(error) = result {
+            eprintln!("{error}");
+        }
+    }
 
-            let rev_img: Vec<u8> = image
-                .chunks(4 * 1280)
-                .into_iter()
-                .rev()
-                .flatten()
-                .copied()
-                .collect();
-
-            image::save_buffer(
-                "test.png",
-                &rev_img,
-                self.screen_resolution.x as _,
-                self.screen_resolution.y as _,
-                image::ExtendedColorType::Rgba8,
-            )
-            .unwrap();
+    /// Saves the G-buffer and every Hi-Z level as float images in the working directory
+    fn save_g_buffer(&self, gl: &Context) {
+        let width = self.screen_resolution.x as u32;
+        let height = self.screen_resolution.y as u32;
+        let mut textures = vec![
+            ("scene_albedo.exr".to_string(), self.scene.albedo, 0),
+            ("scene_emissive.exr".to_string(), self.scene.emissive, 0),
+            ("scene_normal.exr".to_string(), self.scene.normal, 0),
+            ("scene_depth.pfm".to_string(), self.scene.depth_texture, 0),
+        ];
+        for level in 0..=self.hi_z_constants.hi_z_max_mip_level as i32 {
+            textures.push((format!("hi_z_{level}.exr"), self.scene.hi_z_texture, level));
+        }
+
+        for (path, texture, level) in textures {
+            let result = ReadbackImage::from_texture(gl, texture, level, width, height)
+                .and_then(|image| image.save(&path));
+            if let Err(error) = result {
+                eprintln!("{error}");
+            }
         }
     }
 }
 
 impl MicroGLUT for App {
     fn init(gl: &Context, window: &Window) -> Self {
-        let screen_width = window.size().0 as i32;
-        let screen_height = window.size().1 as i32;
+        let (width, height) = drawable_size(window);
+        let screen_width = width as i32;
+        let screen_height = height as i32;
         let screen_resolution = Vec2::new(screen_width as _, screen_height as _);
         let screen_resolution_inv =
             Vec2::new(1.0 / screen_width as f32, 1.0 / screen_height as f32);
@@ -400,18 +375,6 @@ impl MicroGLUT for App {
 
         let scene = SceneFBO::init(gl, screen_width, screen_height);
 
-        let voxel_res = 256.0;
-        let voxel_origin = Vec3::new(0.0, 7.5, 0.0);
-        let voxel_volume_side_lengths = Vec3::new(30.0, 15.0, 16.0);
-        // Note: cracks in the voxelization may appear if all sides are not of the same length
-        let voxelizer = Voxelizer::new(
-            gl,
-            Vec3::new(voxel_res, voxel_res, voxel_res),
-            voxel_origin,
-            voxel_volume_side_lengths,
-        );
-        voxelizer.clear_voxels(gl, &quad_renderer, Vec4::new(0., 0., 0., 0.0));
-
         let camera = Camera::new(
             Vec3::new(0., 1., -1.),
             Vec3::Z,
@@ -447,7 +410,7 @@ impl MicroGLUT for App {
             hi_z_resolution: screen_resolution,
             inv_hi_z_resolution: screen_resolution_inv,
             hi_z_start_mip_level: 0.0,
-            hi_z_max_mip_level: 10.0,
+            hi_z_max_mip_level: (mip_level_count(width.max(height)) - 1) as f32,
             max_steps: 400.0,
             max_ray_distance: 30.0,
             z_near: 0.3,
@@ -459,7 +422,7 @@ impl MicroGLUT for App {
             gl.enable(MULTISAMPLE);
 
             // Create and bind shader storage buffer objects
-            let scene_matrices_ssbo = gl.create_buffer().unwrap();
+            let scene_matrices_ssbo = track_object(gl.create_buffer().unwrap());
             gl.bind_buffer(SHADER_STORAGE_BUFFER, Some(scene_matrices_ssbo));
             gl.buffer_data_u8_slice(
                 SHADER_STORAGE_BUFFER,
@@ -472,7 +435,7 @@ impl MicroGLUT for App {
                 Some(scene_matrices_ssbo),
             );
 
-            let hi_z_constants_ssbo = gl.create_buffer().unwrap();
+            let hi_z_constants_ssbo = track_object(gl.create_buffer().unwrap());
             gl.bind_buffer(SHADER_STORAGE_BUFFER, Some(hi_z_constants_ssbo));
             gl.buffer_data_u8_slice(
                 SHADER_STORAGE_BUFFER,
@@ -487,36 +450,39 @@ impl MicroGLUT for App {
             gl.bind_buffer(SHADER_STORAGE_BUFFER, None);
 
             // Load all shaders
-            let scene_program =
-                load_shaders(gl, include_str!("scene.vert"), include_str!("scene.frag"));
-            let depth_program = load_shaders(
+            let scene_shaders =
+                LoadShaders::new(include_str!("scene.vert"), include_str!("scene.frag"))
+                    .includes(SHADER_INCLUDES);
+            let scene_program = Program::compile(gl, &scene_shaders).unwrap();
+            let batched_scene_program =
+                Program::compile(gl, &scene_shaders.define("BATCHED", 1)).unwrap();
+            let depth_program = Program::compile(
                 gl,
-                include_str!("vertex.glsl"),
-                include_str!("../shaders/min_max.frag"),
-            );
-            let ssrt_program = load_shaders(
+                &LoadShaders::new(
+                    include_str!("vertex.glsl"),
+                    include_str!("../shaders/min_max.frag"),
+                ),
+            )
+            .unwrap();
+            let ssrt_program = Program::compile(
                 gl,
-                include_str!("vertex.glsl"),
-                include_str!("../shaders/naive_ray_marcher.frag"),
-            );
-
-            let scene_matrices_ssbo_loc = gl
-                .get_shader_storage_block_index(ssrt_program, "SceneMatrices")
-                .unwrap();
+                &LoadShaders::new(
+                    include_str!("vertex.glsl"),
+                    include_str!("../shaders/naive_ray_marcher.frag"),
+                )
+                .source_name(ShaderStage::Fragment, "naive_ray_marcher.frag")
+                .includes(SHADER_INCLUDES),
+            )
+            .unwrap();
+            scene_program.label(gl, "Scene");
+            batched_scene_program.label(gl, "Scene (batched)");
+            depth_program.label(gl, "Hi-Z min/max");
+            ssrt_program.label(gl, "Naive ray marcher");
+            label_object(gl, scene_matrices_ssbo, "SceneMatrices");
+            label_object(gl, hi_z_constants_ssbo, "HiZConstants");
 
-            gl.shader_storage_block_binding(
-                ssrt_program,
-                scene_matrices_ssbo_loc,
-                scene_matrices_binding,
-            );
-            let constants_ssbo_loc = gl
-                .get_shader_storage_block_index(ssrt_program, "HiZConstants")
-                .unwrap();
-            gl.shader_storage_block_binding(
-                ssrt_program,
-                constants_ssbo_loc,
-                hi_z_constants_binding,
-            );
+            ssrt_program.bind_storage_block(gl, "SceneMatrices", scene_matrices_binding);
+            ssrt_program.bind_storage_block(gl, "HiZConstants", hi_z_constants_binding);
 
             gl.viewport(0, 0, screen_width, screen_height);
 
@@ -563,33 +529,23 @@ impl MicroGLUT for App {
             );
             let sphere = Object::new(sphere_model);
 
-            let sponza_model = Model::load_obj_data(
+            let mut texture_cache = TextureCache::new();
+            let sponza_model = Model::load_obj_data_with_cache(
                 gl,
                 include_bytes!("../models/sponza.obj"),
                 Some(&|_| tobj::load_mtl_buf(&mut &include_bytes!("../models/sponza.mtl")[..])),
                 Some(&|name| {
-                    load_bytes!(&format!("../textures/sponza_textures/{}", name)).to_vec()
-                }),
-                Some(&|name| {
-                    load_bytes!(&format!(
-                        "../models/sponza_tangents/g {}_tangents.txt",
-                        name
+                    Path::new(concat!(
+                        env!("CARGO_MANIFEST_DIR"),
+                        "/textures/sponza_textures"
                     ))
-                    .to_vec()
+                    .join(name)
                 }),
-                false,
-            );
-
-            let (vase_tangents, vase_bitangents) = load_tangent_buf(load_bytes!(
-                "../models/sponza_tangents/vase_round_tangents.txt"
-            ))
-            .unwrap();
-
-            sponza_model.meshes.iter().for_each(|mesh| {
-                if mesh.material == Some(1) {
-                    mesh.load_tangents(gl, &vase_tangents, &vase_bitangents);
-                }
-            });
+                None,
+                true,
+                &mut texture_cache,
+            )
+            .unwrap_or_else(|error| panic!("failed to load Sponza: {error}"));
             let sponza = Object::new(sponza_model);
 
             //let objects = vec![
@@ -631,14 +587,30 @@ impl MicroGLUT for App {
                 // .with_scale(Vec3::new(15.0, 15.0, 8.0)),
                 sponza.with_uniform_scale(0.01),
             ];
+            // Only used by the scenes that are commented out above
+            rock.delete(gl);
+            for object in [cube, suzanne, sphere] {
+                object.delete(gl);
+            }
+
+            let voxel_res = 256.0;
+            // Note: cracks in the voxelization may appear if all sides are not of the same length
+            let voxelizer = Voxelizer::new(
+                gl,
+                Vec3::new(voxel_res, voxel_res, voxel_res),
+                scene_aabb(&objects),
+            );
+            voxelizer.clear_voxels(gl, &quad_renderer, Vec4::new(0., 0., 0., 0.0));
 
             App {
                 scene_program,
+                batched_scene_program,
                 depth_program,
                 ssrt_program,
                 objects,
                 scene,
                 screen_resolution,
+                dpi_scale: dpi_scale(window),
                 scene_matrices,
                 scene_matrices_ssbo,
                 scene_matrices_binding,
@@ -647,24 +619,49 @@ impl MicroGLUT for App {
                 hi_z_constants_binding,
                 debug: false,
                 debug_mode: DebugMode::RadianceCascades,
+                batched_drawing: false,
                 mouse_is_down: false,
                 voxelizer,
                 quad_renderer,
                 camera,
                 radiance_cascades,
                 frame_times: VecDeque::new(),
+                profiler: GpuProfiler::new(),
+                texture_cache,
+                material_bindings: scene_material_bindings(),
             }
         }
     }
 
     fn display(&mut self, gl: &Context, window: &Window) {
         let t_start = elapsed_time();
+        // Changes when the window moves to a display with a different scale
+        self.dpi_scale = dpi_scale(window);
+        self.profiler.begin_frame(gl);
+
+        self.profiler.begin(gl, "Scene");
         self.draw_scene(gl);
+        self.profiler.end(gl);
+
+        self.profiler.begin(gl, "Hi-Z");
         self.generate_hi_z_buffer(gl);
+        self.profiler.end(gl);
+
+        self.profiler.begin(gl, "Voxel clear");
         self.voxelizer
             .clear_voxels(gl, &self.quad_renderer, Vec4::new(0.0, 0.0, 0.0, 0.0));
-        self.voxelizer.voxelize(gl, &self.objects);
+        self.profiler.end(gl);
+
+        self.profiler.begin(gl, "Voxelize");
+        self.voxelizer.voxelize(
+            gl,
+            &self.objects,
+            &self.material_bindings,
+            self.batched_drawing,
+        );
+        self.profiler.end(gl);
         if self.debug {
+            let _group = debug_group(gl, &format!("Debug view: {}", self.debug_mode));
             match self.debug_mode {
                 DebugMode::RayMarching => {
                     self.draw_ssrt(gl);
@@ -675,10 +672,11 @@ impl MicroGLUT for App {
                         self.screen_resolution,
                         &self.scene,
                         &self.voxelizer,
+                        &self.profiler,
                     );
                 }
                 DebugMode::DepthBuffer => unsafe {
-                    gl.bind_framebuffer(READ_FRAMEBUFFER, Some(self.scene.fb));
+                    gl.bind_framebuffer(READ_FRAMEBUFFER, Some(self.scene.fb()));
                     gl.read_buffer(COLOR_ATTACHMENT3);
                     gl.bind_framebuffer(DRAW_FRAMEBUFFER, None);
                     gl.viewport(
@@ -701,7 +699,7 @@ impl MicroGLUT for App {
                     );
                 },
                 DebugMode::Scene => unsafe {
-                    gl.bind_framebuffer(READ_FRAMEBUFFER, Some(self.scene.fb));
+                    gl.bind_framebuffer(READ_FRAMEBUFFER, Some(self.scene.fb()));
                     gl.read_buffer(COLOR_ATTACHMENT0);
                     gl.bind_framebuffer(DRAW_FRAMEBUFFER, None);
                     gl.viewport(
@@ -733,8 +731,13 @@ impl MicroGLUT for App {
                 }
             }
         } else {
-            self.radiance_cascades
-                .render(gl, self.screen_resolution, &self.scene, &self.voxelizer);
+            self.radiance_cascades.render(
+                gl,
+                self.screen_resolution,
+                &self.scene,
+                &self.voxelizer,
+                &self.profiler,
+            );
         }
         let t_end = elapsed_time();
         self.frame_times.push_back(t_end - t_start);
@@ -745,32 +748,27 @@ impl MicroGLUT for App {
         //println!("Time to render: {:?}", t_end - t_start);
     }
 
-    fn key_down(
-        &mut self,
-        keycode: Option<Keycode>,
-        scancode: Option<Scancode>,
-        keymod: Mod,
-        repeat: bool,
-    ) {
-        if let Some(kc) = keycode {
-            let cam_right = self.camera.right();
-            let direction = match kc {
-                Keycode::W => self.camera.look_direction,
-                Keycode::S => -self.camera.look_direction,
-                Keycode::A => -cam_right,
-                Keycode::D => cam_right,
-                Keycode::SPACE => {
-                    if keymod == Mod::LSHIFTMOD {
-                        -Vec3::Y
-                    } else {
-                        Vec3::Y
-                    }
-                }
-                _ => Vec3::ZERO,
-            };
-            self.camera
-                .move_by(direction * delta_time() * self.camera.walk_speed);
+    fn update(&mut self, dt: f32) {
+        let cam_right = self.camera.right();
+        let mut direction = Vec3::ZERO;
+        for (scancode, step) in [
+            (Scancode::W, self.camera.look_direction),
+            (Scancode::S, -self.camera.look_direction),
+            (Scancode::A, -cam_right),
+            (Scancode::D, cam_right),
+        ] {
+            if is_key_down(scancode) {
+                direction += step;
+            }
+        }
+        if is_key_down(Scancode::Space) {
+            if is_key_down(Scancode::LShift) {
+                direction -= Vec3::Y;
+            } else {
+                direction += Vec3::Y;
+            }
         }
+        self.camera.move_by(direction * dt * self.camera.walk_speed);
     }
 
     fn mouse_down(&mut self, button: MouseButton, x: i32, y: i32) {
@@ -793,20 +791,96 @@ impl MicroGLUT for App {
 
     fn mouse_moved_rel(&mut self, xrel: i32, yrel: i32) {
         if self.mouse_is_down {
+            // The motion is in screen coordinates while the resolution is in pixels
+            let window_size = self.screen_resolution / self.dpi_scale;
             let speed = self.camera.rotational_speed;
-            let rotation = (Quat::from_rotation_y(speed * -xrel as f32 / self.screen_resolution.x)
-                * Quat::from_rotation_x(speed * yrel as f32 / self.screen_resolution.y))
+            let rotation = (Quat::from_rotation_y(speed * -xrel as f32 / window_size.x)
+                * Quat::from_rotation_x(speed * yrel as f32 / window_size.y))
             .normalize();
             self.camera.rotate(rotation);
         }
     }
 
+    fn resize(&mut self, gl: &Context, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            // Minimised
+            return;
+        }
+        let screen_resolution = Vec2::new(width as _, height as _);
+        if screen_resolution == self.screen_resolution {
+            return;
+        }
+        self.screen_resolution = screen_resolution;
+        let screen_resolution_inv = 1.0 / screen_resolution;
+
+        let scene = SceneFBO::init(gl, width as _, height as _);
+        std::mem::replace(&mut self.scene, scene).delete(gl);
+        self.radiance_cascades.resize(gl, screen_resolution);
+
+        self.camera.aspect_ratio = width as f32 / height as f32;
+        self.scene_matrices.screen_resolution = screen_resolution;
+        self.scene_matrices.screen_resolution_inv = screen_resolution_inv;
+        self.hi_z_constants.hi_z_resolution = screen_resolution;
+        self.hi_z_constants.inv_hi_z_resolution = screen_resolution_inv;
+        // The Hi-Z texture is reallocated with a full mip chain for the new size
+        self.hi_z_constants.hi_z_max_mip_level = (mip_level_count(width.max(height)) - 1) as f32;
+
+        unsafe {
+            // The matrices are uploaded every frame, so only the resolution is written here
+            gl.bind_buffer(SHADER_STORAGE_BUFFER, Some(self.scene_matrices_ssbo));
+            gl.buffer_sub_data_u8_slice(
+                SHADER_STORAGE_BUFFER,
+                0x100,
+                bytemuck::bytes_of(&[screen_resolution, screen_resolution_inv]),
+            );
+            gl.bind_buffer(SHADER_STORAGE_BUFFER, Some(self.hi_z_constants_ssbo));
+            gl.buffer_data_u8_slice(
+                SHADER_STORAGE_BUFFER,
+                bytemuck::bytes_of(&self.hi_z_constants),
+                STATIC_DRAW,
+            );
+            gl.bind_buffer(SHADER_STORAGE_BUFFER, None);
+            gl.viewport(0, 0, width as _, height as _);
+        }
+    }
+
+    fn exit(self, gl: &Context) {
+        self.scene_program.delete(gl);
+        self.batched_scene_program.delete(gl);
+        self.depth_program.delete(gl);
+        self.ssrt_program.delete(gl);
+        for object in self.objects {
+            object.delete(gl);
+        }
+        // After the objects, so that the cache holds the last reference to the textures
+        let mut texture_cache = self.texture_cache;
+        texture_cache.clear(gl);
+        self.scene.delete(gl);
+        delete_object(gl, self.scene_matrices_ssbo);
+        delete_object(gl, self.hi_z_constants_ssbo);
+        self.quad_renderer.delete(gl);
+        self.radiance_cascades.delete(gl);
+        self.voxelizer.delete(gl);
+        self.profiler.delete(gl);
+    }
+
     fn ui(&mut self, gl: &Context, ui: &mut imgui::Ui) {
         let mut constants_changed = false;
         ui.checkbox("Enable debug mode", &mut self.debug);
+        if ui.checkbox("Batched drawing", &mut self.batched_drawing) && self.batched_drawing {
+            // Batches copy the meshes and textures, so they are only made when needed
+            for object in &mut self.objects {
+                object.build_batch(gl);
+            }
+        }
 
         if ui.button("Save screenshot") {
-            self.save_screen_to(gl);
+            self.save_screen_to(gl, "screenshot.png");
+        }
+        ui.same_line();
+        if ui.button("Save G-buffer") {
+            self.save_g_buffer(gl);
+            self.radiance_cascades.save_cascades(gl);
         }
 
         if let Some(cb) = ui.begin_combo("Debug mode", self.debug_mode.to_string()) {
@@ -830,6 +904,9 @@ impl MicroGLUT for App {
         self.camera.ui(ui);
         self.radiance_cascades.ui(gl, ui);
         self.voxelizer.ui(ui);
+        if ui.button("Fit voxel volume to scene") {
+            self.voxelizer.fit_to(scene_aabb(&self.objects));
+        }
 
         if ui.tree_node("Ray marching").is_some() {
             constants_changed = constants_changed
@@ -860,13 +937,26 @@ impl MicroGLUT for App {
         ui.plot_lines("Frame times", self.frame_times.make_contiguous())
             .overlay_text(format!("FPS {}", fps))
             .build();
+
+        if ui.collapsing_header("GPU profiler", imgui::TreeNodeFlags::empty()) {
+            self.profiler.ui(ui);
+        }
+
+        let textures = self.texture_cache.stats();
+        ui.text(format!(
+            "Textures: {} ({:.1} MiB), cache hits {}, misses {}",
+            textures.textures,
+            textures.memory_size as f32 / (1024.0 * 1024.0),
+            textures.hits,
+            textures.misses,
+        ));
     }
 }
 
 fn main() {
     App::sdl2_window("Radiance cascades 3D prototype")
         .gl_version(4, 5)
-        .debug_message_callback(debug_message_callback)
+        .debug_output(DebugOutput::new())
         .window_size(1280, 720)
         .start();
 }
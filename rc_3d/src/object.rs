@@ -1,11 +1,13 @@
 use microglut::{
     glam::{Mat4, Quat, Vec3, Vec4},
-    Model,
+    glow::Context,
+    Aabb, BatchedModel, BoundingSphere, Model,
 };
 
-#[derive(Clone)]
 pub struct Object {
     pub model: Model,
+    /// The model packed for multi-draw, see [Object::build_batch].
+    pub batch: Option<BatchedModel>,
     rotation: Quat,
     translation: Vec3,
     scale: Vec3,
@@ -17,6 +19,7 @@ impl Object {
     pub fn new(model: Model) -> Self {
         Self {
             model,
+            batch: None,
             rotation: Quat::IDENTITY,
             translation: Vec3::ZERO,
             scale: Vec3::ONE,
@@ -44,10 +47,33 @@ impl Object {
         self
     }
 
+    pub fn build_batch(&mut self, gl: &Context) {
+        if self.batch.is_none() {
+            self.batch = Some(self.model.batch(gl).unwrap());
+        }
+    }
+
+    pub fn delete(self, gl: &Context) {
+        self.model.delete(gl);
+        if let Some(batch) = self.batch {
+            batch.delete(gl);
+        }
+    }
+
     pub fn get_transformation(&self) -> Mat4 {
         Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
     }
 
+    pub fn world_aabb(&self) -> Aabb {
+        self.model.aabb().transformed(self.get_transformation())
+    }
+
+    pub fn world_bounding_sphere(&self) -> BoundingSphere {
+        self.model
+            .bounding_sphere()
+            .transformed(self.get_transformation())
+    }
+
     pub fn with_albedo(mut self, albedo: Vec4) -> Self {
         self.albedo = albedo;
         self
@@ -58,3 +84,8 @@ impl Object {
         self
     }
 }
+
+/// Bounds of all objects in world space.
+pub fn scene_aabb(objects: &[Object]) -> Aabb {
+    objects.iter().map(Object::world_aabb).collect()
+}
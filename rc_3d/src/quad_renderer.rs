@@ -1,9 +1,11 @@
 use microglut::{
+    delete_object,
     glam::{Vec2, Vec3},
     glow::{
         Context, HasContext, NativeBuffer, NativeProgram, NativeVertexArray, ARRAY_BUFFER, FLOAT,
         STATIC_DRAW, TRIANGLES,
     },
+    label_object, track_object,
 };
 
 pub struct QuadRenderer {
@@ -22,17 +24,21 @@ impl QuadRenderer {
 
         let texcoords = [Vec2::ZERO, Vec2::new(2.0, 0.0), Vec2::new(0.0, 2.0)];
         unsafe {
-            let quad_vao = gl.create_vertex_array().unwrap();
+            let quad_vao = track_object(gl.create_vertex_array().unwrap());
             gl.bind_vertex_array(Some(quad_vao));
 
-            let quad_vertex_buffer = gl.create_buffer().unwrap();
+            let quad_vertex_buffer = track_object(gl.create_buffer().unwrap());
             gl.bind_buffer(ARRAY_BUFFER, Some(quad_vertex_buffer));
             gl.buffer_data_u8_slice(ARRAY_BUFFER, bytemuck::cast_slice(&vertices), STATIC_DRAW);
 
-            let quad_texcoord_buffer = gl.create_buffer().unwrap();
+            let quad_texcoord_buffer = track_object(gl.create_buffer().unwrap());
             gl.bind_buffer(ARRAY_BUFFER, Some(quad_texcoord_buffer));
             gl.buffer_data_u8_slice(ARRAY_BUFFER, bytemuck::cast_slice(&texcoords), STATIC_DRAW);
 
+            label_object(gl, quad_vao, "Screen quad");
+            label_object(gl, quad_vertex_buffer, "Screen quad positions");
+            label_object(gl, quad_texcoord_buffer, "Screen quad texcoords");
+
             Self {
                 quad_vao,
                 quad_vertex_buffer,
@@ -40,6 +46,13 @@ impl QuadRenderer {
             }
         }
     }
+
+    pub fn delete(self, gl: &Context) {
+        delete_object(gl, self.quad_vao);
+        delete_object(gl, self.quad_vertex_buffer);
+        delete_object(gl, self.quad_texcoord_buffer);
+    }
+
     pub fn draw_screen_quad(&self, gl: &Context, program: NativeProgram) {
         unsafe {
             gl.bind_vertex_array(Some(self.quad_vao));
@@ -1,70 +1,55 @@
 use microglut::{
     glam::Vec2,
     glow::{
-        Context, HasContext, NativeFramebuffer, NativeTexture, CLAMP_TO_EDGE, COLOR_ATTACHMENT0,
-        FRAMEBUFFER, LINEAR, RGBA, RGBA32F, TEXTURE_2D, TEXTURE_MAG_FILTER, TEXTURE_MIN_FILTER,
-        TEXTURE_WRAP_S, TEXTURE_WRAP_T, UNSIGNED_BYTE,
+        Context, HasContext, NativeFramebuffer, NativeTexture, FRAMEBUFFER, RGBA32F, TEXTURE_2D,
     },
+    Framebuffer, FramebufferBuilder,
 };
 
+/// One framebuffer per cascade, each with the cascade texture as its only colour attachment.
 pub struct CascadeFBO {
-    pub fb: NativeFramebuffer,
-    pub cascades: Vec<NativeTexture>,
+    cascades: Vec<Framebuffer>,
 }
 
 impl CascadeFBO {
     pub fn new(gl: &Context, c0_res: Vec2, num_cascades: i32) -> Self {
-        unsafe {
-            let fb = gl.create_framebuffer().unwrap();
-            gl.bind_framebuffer(FRAMEBUFFER, Some(fb));
-
-            let cascades = (0..num_cascades)
-                .into_iter()
-                .map(|i| {
-                    let tex = gl.create_texture().unwrap();
-                    gl.bind_texture(TEXTURE_2D, Some(tex));
-                    gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_S, CLAMP_TO_EDGE as _);
-                    gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_T, CLAMP_TO_EDGE as _);
-                    gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MAG_FILTER, LINEAR as _);
-                    gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MIN_FILTER, LINEAR as _);
-                    gl.tex_image_2d(
-                        TEXTURE_2D,
-                        0,
-                        RGBA32F as _,
-                        c0_res.x as _,
-                        c0_res.y as _,
-                        0,
-                        RGBA,
-                        UNSIGNED_BYTE,
-                        None,
-                    );
-                    tex
-                })
-                .collect();
+        let cascades = (0..num_cascades)
+            .map(|i| {
+                let framebuffer = FramebufferBuilder::new(c0_res.x as _, c0_res.y as _)
+                    .color(RGBA32F)
+                    .build(gl)
+                    .unwrap();
+                framebuffer.label(gl, &format!("Cascade {i}"));
+                framebuffer
+            })
+            .collect();
+        CascadeFBO { cascades }
+    }
 
-            gl.draw_buffers(&[COLOR_ATTACHMENT0]);
-            gl.bind_framebuffer(FRAMEBUFFER, None);
-            gl.bind_texture(TEXTURE_2D, None);
-            CascadeFBO { fb, cascades }
+    pub fn delete(self, gl: &Context) {
+        for cascade in self.cascades {
+            cascade.delete(gl);
         }
     }
 
+    pub fn framebuffer(&self, cascade: usize) -> NativeFramebuffer {
+        self.cascades[cascade].fb()
+    }
+
+    pub fn texture(&self, cascade: usize) -> NativeTexture {
+        self.cascades[cascade].color(0)
+    }
+
     pub fn bind_cascade_as_texture(&self, gl: &Context, cascade: usize, texture_unit: u32) {
         unsafe {
             gl.active_texture(texture_unit);
-            gl.bind_texture(TEXTURE_2D, Some(self.cascades[cascade]));
+            gl.bind_texture(TEXTURE_2D, Some(self.texture(cascade)));
         }
     }
 
     pub fn bind_cascade_as_output(&self, gl: &Context, cascade: usize) {
         unsafe {
-            gl.bind_framebuffer(FRAMEBUFFER, Some(self.fb));
-            gl.framebuffer_texture(
-                FRAMEBUFFER,
-                COLOR_ATTACHMENT0,
-                Some(self.cascades[cascade]),
-                0,
-            );
+            gl.bind_framebuffer(FRAMEBUFFER, Some(self.framebuffer(cascade)));
         };
     }
 }
@@ -11,50 +11,13 @@ uniform float cascade_index;
 
 uniform float ambient;
 
-layout(std430) readonly buffer RCConstants {
-    vec2 c0_resolution;
-    float num_cascades;
-    float c0_probe_spacing;
-    float c0_interval_length;
-    float normal_offset;
-    float gamma;
-    float ambient_occlusion_factor;
-    float diffuse_intensity;
-    float ambient_occlusion;
-};
-
-layout(std430) readonly buffer SceneMatrices {
-    mat4 world_to_view;
-    mat4 world_to_view_inv;
-    mat4 perspective;
-    mat4 perspective_inv;
-    vec2 screen_res;
-    vec2 screen_res_inv;
-};
+#include "rc_constants.glsl"
+#include "scene_matrices.glsl"
+#include "octahedral.glsl"
+#include "color.glsl"
 
 const float altitudes[4] = {acos(-0.75), acos(-0.25), acos(0.25), acos(0.75)};
 
-vec3 linear_to_srgb(vec3 c) {
-    return pow(c.rgb, vec3(1.0 / gamma));
-}
-
-vec3 srgb_to_linear(vec3 c) {
-    return pow(c.rgb, vec3(gamma));
-}
-
-vec3 octahedral_decode(vec2 v) {
-    // Based on https://knarkowicz.wordpress.com/2014/04/16/octahedron-normal-vector-encoding/
-    //vec2 v_adjusted = 2.0 * v - 1.0;
-    vec2 v_adjusted = v;
-    vec3 n = vec3(v_adjusted.xy, 1.0 - abs(v_adjusted.x) - abs(v_adjusted.y));
-    float t = max((-n.z), 0.0);
-    return normalize(vec3(
-        n.x + ((n.x >= 0.0) ? (-t) : t),
-        n.y + ((n.y >= 0.0) ? (-t) : t),
-        n.z
-    ));
-}
-
 void main() {
     vec3 normal = octahedral_decode(texture(scene_normal, tex_coord).xy);
 
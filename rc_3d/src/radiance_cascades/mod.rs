@@ -1,17 +1,24 @@
+use std::path::Path;
+
 use bytemuck::{Pod, Zeroable};
 use cascade_fbo::CascadeFBO;
 use microglut::{
+    delete_object,
     glam::Vec2,
     glow::{
-        Context, HasContext, NativeBuffer, NativeProgram, COLOR_ATTACHMENT0, COLOR_BUFFER_BIT,
-        DRAW_FRAMEBUFFER, FRAMEBUFFER, LINEAR, READ_FRAMEBUFFER, READ_ONLY, RGBA16F,
-        SHADER_STORAGE_BUFFER, STATIC_DRAW, TEXTURE0, TEXTURE1, TEXTURE2, TEXTURE3, TEXTURE4,
-        TEXTURE_2D,
+        Context, HasContext, NativeBuffer, COLOR_ATTACHMENT0, COLOR_BUFFER_BIT, DRAW_FRAMEBUFFER,
+        FRAMEBUFFER, LINEAR, READ_FRAMEBUFFER, READ_ONLY, SHADER_STORAGE_BUFFER, STATIC_DRAW,
+        TEXTURE0, TEXTURE1, TEXTURE2, TEXTURE3, TEXTURE4, TEXTURE_2D,
     },
-    imgui, LoadShaders,
+    imgui, label_object, track_object, GpuProfiler, LoadShaders, ReadbackImage, ReloadableProgram,
+    ShaderStage, TextureUnit,
 };
+use strum::{Display, VariantArray};
 
-use crate::{quad_renderer::QuadRenderer, scene_fbo::SceneFBO, voxelizer::Voxelizer};
+use crate::{
+    quad_renderer::QuadRenderer, scene_fbo::SceneFBO, voxelizer::Voxelizer, SHADER_INCLUDES,
+    SHADER_INCLUDE_DIR,
+};
 
 mod cascade_fbo;
 
@@ -39,6 +46,11 @@ fn ceil_to_multiple_of_n(number: f32, n: f32) -> f32 {
     (number / n).ceil() * n
 }
 
+/// Resolution of the cascade 0 texture for a given screen resolution.
+fn c0_resolution(screen_resolution: Vec2, probe_spacing: f32) -> Vec2 {
+    4.0 * screen_resolution / probe_spacing
+}
+
 #[repr(C)]
 #[derive(Default, Clone, Copy, Pod, Zeroable)]
 struct RadianceCascadesConstants {
@@ -54,26 +66,81 @@ struct RadianceCascadesConstants {
     _padding: [f32; 2],
 }
 
+#[derive(Display, VariantArray, PartialEq, Copy, Clone)]
+enum TraceMethod {
+    #[strum(to_string = "Naive screen space")]
+    NaiveScreenSpace,
+    #[strum(to_string = "Hi-Z")]
+    HiZ,
+    Voxel,
+}
+
+impl TraceMethod {
+    /// Value of `TRACE_METHOD` in rc.frag
+    fn define(self) -> &'static str {
+        match self {
+            TraceMethod::NaiveScreenSpace => "NAIVE_SS",
+            TraceMethod::HiZ => "HI_Z",
+            TraceMethod::Voxel => "VOXEL",
+        }
+    }
+}
+
+/// This directory in the source tree.
+const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/radiance_cascades");
+
+/// Shaders in this directory. When run from the source tree they are read at runtime so
+/// they can be hot reloaded, otherwise the sources embedded in the binary are used.
+macro_rules! shaders {
+    ($vertex:literal, $fragment:literal) => {
+        if Path::new(SHADER_DIR).is_dir() && Path::new(SHADER_INCLUDE_DIR).is_dir() {
+            LoadShaders::new_from_path(
+                Path::new(SHADER_DIR).join($vertex),
+                Path::new(SHADER_DIR).join($fragment),
+            )
+            .include_dir(SHADER_INCLUDE_DIR)
+        } else {
+            LoadShaders::new(include_str!($vertex), include_str!($fragment))
+                .source_name(ShaderStage::Vertex, $vertex)
+                .source_name(ShaderStage::Fragment, $fragment)
+                .includes(SHADER_INCLUDES)
+        }
+    };
+}
+
+fn cascade_shaders(trace_method: TraceMethod, debug_intervals: bool) -> LoadShaders {
+    let shaders = shaders!("rc.vert", "rc.frag").define("TRACE_METHOD", trace_method.define());
+    if debug_intervals {
+        shaders.define("DEBUG_INTERVALS", 1)
+    } else {
+        shaders
+    }
+}
+
 pub struct RadianceCascades {
-    cascade_program: NativeProgram,
-    integration_program: NativeProgram,
+    cascade_program: ReloadableProgram,
+    integration_program: ReloadableProgram,
     cascades: CascadeFBO,
     quad_renderer: QuadRenderer,
 
     constants: RadianceCascadesConstants,
     constants_ssbo: NativeBuffer,
     constants_ssbo_binding: u32,
+    scene_matrices_binding: u32,
+    hi_z_constants_binding: u32,
     ambient_level: f32,
+    trace_method: TraceMethod,
 
     // Debug info
     merge_cascades: bool,
     debug_cascade_index: usize,
+    debug_intervals: bool,
 }
 
 impl RadianceCascadesConstants {
     pub fn create_shader_storage_buffer(&self, gl: &Context, binding_point: u32) -> NativeBuffer {
         unsafe {
-            let ssbo = gl.create_buffer().unwrap();
+            let ssbo = track_object(gl.create_buffer().unwrap());
             gl.bind_buffer(SHADER_STORAGE_BUFFER, Some(ssbo));
             gl.bind_buffer_base(SHADER_STORAGE_BUFFER, binding_point, Some(ssbo));
             self.upload_to_buffer(gl, ssbo);
@@ -103,17 +170,19 @@ impl RadianceCascades {
         let interval_length = Vec2::ZERO.distance(Vec2::new(probe_spacing, probe_spacing)) * 0.5;
         let probe_spacing_adjusted = ceil_to_power_of_n(probe_spacing, 2.0);
         let interval_length_adjusted = ceil_to_multiple_of_n(interval_length, 2.0);
-        let cascade_width = 4.0 * screen_resolution.x / probe_spacing_adjusted;
-        let cascade_height = 4.0 * screen_resolution.y / probe_spacing_adjusted;
-        let c0_resolution = Vec2::new(cascade_width, cascade_height);
+        let c0_resolution = c0_resolution(screen_resolution, probe_spacing_adjusted);
         //let num_cascades = Vec2::ZERO.distance(screen_dims).log(4.0).ceil();
 
         let cascades = CascadeFBO::new(gl, c0_resolution, cascade_count as _);
 
-        let cascade_program =
-            LoadShaders::new(include_str!("rc.vert"), include_str!("rc.frag")).compile(gl);
-        let integration_program =
-            LoadShaders::new(include_str!("rc.vert"), include_str!("integrate.frag")).compile(gl);
+        let trace_method = TraceMethod::Voxel;
+        let debug_intervals = false;
+        let mut cascade_program =
+            ReloadableProgram::new(gl, cascade_shaders(trace_method, debug_intervals)).unwrap();
+        let mut integration_program =
+            ReloadableProgram::new(gl, shaders!("rc.vert", "integrate.frag")).unwrap();
+        cascade_program.label(gl, "Radiance cascades");
+        integration_program.label(gl, "Integrate radiance");
 
         let quad_renderer = QuadRenderer::new(gl);
 
@@ -131,55 +200,10 @@ impl RadianceCascades {
         };
         let constants_ssbo_binding = binding_point;
         let constants_ssbo = constants.create_shader_storage_buffer(gl, constants_ssbo_binding);
+        label_object(gl, constants_ssbo, "RCConstants");
         constants.upload_to_buffer(gl, constants_ssbo);
 
-        unsafe {
-            let hi_z_constants_ssbo_loc = gl
-                .get_shader_storage_block_index(cascade_program, "HiZConstants")
-                .unwrap();
-            gl.shader_storage_block_binding(
-                cascade_program,
-                hi_z_constants_ssbo_loc,
-                hi_z_constants_binding,
-            );
-
-            let mut scene_matrices_ssbo_loc = gl
-                .get_shader_storage_block_index(cascade_program, "SceneMatrices")
-                .unwrap();
-            gl.shader_storage_block_binding(
-                cascade_program,
-                scene_matrices_ssbo_loc,
-                scene_matrices_binding,
-            );
-
-            let mut constants_ssbo_loc = gl
-                .get_shader_storage_block_index(cascade_program, "RCConstants")
-                .unwrap();
-            gl.shader_storage_block_binding(
-                cascade_program,
-                constants_ssbo_loc,
-                constants_ssbo_binding,
-            );
-
-            constants_ssbo_loc = gl
-                .get_shader_storage_block_index(integration_program, "RCConstants")
-                .unwrap();
-            gl.shader_storage_block_binding(
-                integration_program,
-                constants_ssbo_loc,
-                constants_ssbo_binding,
-            );
-            scene_matrices_ssbo_loc = gl
-                .get_shader_storage_block_index(integration_program, "SceneMatrices")
-                .unwrap();
-            gl.shader_storage_block_binding(
-                integration_program,
-                scene_matrices_ssbo_loc,
-                scene_matrices_binding,
-            );
-        }
-
-        Self {
+        let radiance_cascades = Self {
             cascade_program,
             integration_program,
             cascades,
@@ -187,9 +211,52 @@ impl RadianceCascades {
             constants,
             constants_ssbo,
             constants_ssbo_binding,
+            scene_matrices_binding,
+            hi_z_constants_binding,
             merge_cascades: true,
             debug_cascade_index: 0,
+            debug_intervals,
             ambient_level: 0.1,
+            trace_method,
+        };
+        radiance_cascades.bind_storage_blocks(gl);
+        radiance_cascades
+    }
+
+    pub fn delete(self, gl: &Context) {
+        self.cascade_program.delete(gl);
+        self.integration_program.delete(gl);
+        self.cascades.delete(gl);
+        self.quad_renderer.delete(gl);
+        delete_object(gl, self.constants_ssbo);
+    }
+
+    fn bind_storage_blocks(&self, gl: &Context) {
+        let cascade_program = self.cascade_program.program();
+        cascade_program.bind_storage_block(gl, "HiZConstants", self.hi_z_constants_binding);
+        cascade_program.bind_storage_block(gl, "SceneMatrices", self.scene_matrices_binding);
+        cascade_program.bind_storage_block(gl, "RCConstants", self.constants_ssbo_binding);
+
+        let integration_program = self.integration_program.program();
+        integration_program.bind_storage_block(gl, "RCConstants", self.constants_ssbo_binding);
+        integration_program.bind_storage_block(gl, "SceneMatrices", self.scene_matrices_binding);
+    }
+
+    /// Rebuilds the programs if their shader files have been modified
+    fn reload_programs(&mut self, gl: &Context) {
+        let cascade_reloaded = self.cascade_program.poll(gl);
+        let integration_reloaded = self.integration_program.poll(gl);
+        if cascade_reloaded || integration_reloaded {
+            self.bind_storage_blocks(gl);
+        }
+    }
+
+    /// Switches to the cascade program permutation for the current trace method and debug
+    /// settings. Keeps the previous program if the new one fails to compile.
+    fn update_cascade_program(&mut self, gl: &Context) {
+        let shaders = cascade_shaders(self.trace_method, self.debug_intervals);
+        if self.cascade_program.set_shaders(gl, shaders) {
+            self.bind_storage_blocks(gl);
         }
     }
 
@@ -199,10 +266,11 @@ impl RadianceCascades {
         screen_resolution: Vec2,
         scene: &SceneFBO,
         voxelizer: &Voxelizer,
+        profiler: &GpuProfiler,
     ) {
+        let cascade_program = self.cascade_program.program();
         unsafe {
-            gl.use_program(Some(self.cascade_program));
-            gl.bind_framebuffer(FRAMEBUFFER, Some(self.cascades.fb));
+            cascade_program.use_program(gl);
             gl.active_texture(TEXTURE1);
             gl.bind_texture(TEXTURE_2D, Some(scene.albedo));
             gl.active_texture(TEXTURE2);
@@ -212,70 +280,22 @@ impl RadianceCascades {
             gl.active_texture(TEXTURE4);
             gl.bind_texture(TEXTURE_2D, Some(scene.hi_z_texture));
 
-            gl.uniform_1_i32(
-                gl.get_uniform_location(self.cascade_program, "prev_cascade")
-                    .as_ref(),
-                0,
-            );
-            gl.uniform_1_i32(
-                gl.get_uniform_location(self.cascade_program, "scene_albedo")
-                    .as_ref(),
-                1,
-            );
-            gl.uniform_1_i32(
-                gl.get_uniform_location(self.cascade_program, "scene_emissive")
-                    .as_ref(),
-                2,
-            );
-            gl.uniform_1_i32(
-                gl.get_uniform_location(self.cascade_program, "scene_normal")
-                    .as_ref(),
-                3,
-            );
-            gl.uniform_1_i32(
-                gl.get_uniform_location(self.cascade_program, "hi_z_tex")
-                    .as_ref(),
-                4,
-            );
+            cascade_program.set_uniform(gl, "prev_cascade", TextureUnit(0));
+            cascade_program.set_uniform(gl, "scene_albedo", TextureUnit(1));
+            cascade_program.set_uniform(gl, "scene_emissive", TextureUnit(2));
+            cascade_program.set_uniform(gl, "scene_normal", TextureUnit(3));
+            cascade_program.set_uniform(gl, "hi_z_tex", TextureUnit(4));
 
-            gl.uniform_1_i32(
-                gl.get_uniform_location(self.cascade_program, "merge_cascades")
-                    .as_ref(),
-                self.merge_cascades.into(),
-            );
+            cascade_program.set_uniform(gl, "merge_cascades", self.merge_cascades);
 
-            gl.bind_image_texture(
-                0,
-                voxelizer.voxel_texture(),
-                0,
-                false,
-                0,
-                READ_ONLY,
-                RGBA16F,
-            );
-            gl.uniform_1_f32(
-                gl.get_uniform_location(self.cascade_program, "step_count")
-                    .as_ref(),
-                voxelizer.step_count(),
-            );
-            gl.uniform_matrix_4_f32_slice(
-                gl.get_uniform_location(self.cascade_program, "world_to_voxel")
-                    .as_ref(),
-                false,
-                voxelizer.world_to_voxel().as_ref(),
-            );
-            gl.uniform_3_f32_slice(
-                gl.get_uniform_location(self.cascade_program, "voxel_resolution")
-                    .as_ref(),
-                voxelizer.resolution().as_ref(),
-            );
+            voxelizer.voxel_texture().bind_image(gl, 0, 0, READ_ONLY);
+            cascade_program.set_uniform(gl, "step_count", voxelizer.step_count());
+            cascade_program.set_uniform(gl, "world_to_voxel", voxelizer.world_to_voxel());
+            cascade_program.set_uniform(gl, "voxel_resolution", voxelizer.resolution());
 
             for n in (0..self.constants.cascade_count as i32).rev() {
-                gl.uniform_1_f32(
-                    gl.get_uniform_location(self.cascade_program, "cascade_index")
-                        .as_ref(),
-                    n as _,
-                );
+                let _scope = profiler.scope(gl, format!("Cascade {n}"));
+                cascade_program.set_uniform(gl, "cascade_index", n as f32);
 
                 gl.active_texture(TEXTURE0);
                 self.cascades.bind_cascade_as_texture(
@@ -294,7 +314,7 @@ impl RadianceCascades {
                 gl.clear_color(0.0, 0.0, 0.0, 0.0);
                 gl.clear(COLOR_BUFFER_BIT);
                 self.quad_renderer
-                    .draw_screen_quad(gl, self.cascade_program);
+                    .draw_screen_quad(gl, cascade_program.id());
             }
 
             gl.viewport(0, 0, screen_resolution.x as _, screen_resolution.y as _);
@@ -309,38 +329,15 @@ impl RadianceCascades {
         screen_resolution: Vec2,
         scene: &SceneFBO,
     ) {
+        let integration_program = self.integration_program.program();
         unsafe {
-            gl.use_program(Some(self.integration_program));
-            gl.uniform_1_i32(
-                gl.get_uniform_location(self.integration_program, "cascade")
-                    .as_ref(),
-                0,
-            );
-            gl.uniform_1_i32(
-                gl.get_uniform_location(self.integration_program, "scene_normal")
-                    .as_ref(),
-                1,
-            );
-            gl.uniform_1_i32(
-                gl.get_uniform_location(self.integration_program, "scene_albedo")
-                    .as_ref(),
-                2,
-            );
-            gl.uniform_1_i32(
-                gl.get_uniform_location(self.integration_program, "scene_emissive")
-                    .as_ref(),
-                3,
-            );
-            gl.uniform_1_f32(
-                gl.get_uniform_location(self.integration_program, "cascade_index")
-                    .as_ref(),
-                cascade_index as _,
-            );
-            gl.uniform_1_f32(
-                gl.get_uniform_location(self.integration_program, "ambient")
-                    .as_ref(),
-                self.ambient_level,
-            );
+            integration_program.use_program(gl);
+            integration_program.set_uniform(gl, "cascade", TextureUnit(0));
+            integration_program.set_uniform(gl, "scene_normal", TextureUnit(1));
+            integration_program.set_uniform(gl, "scene_albedo", TextureUnit(2));
+            integration_program.set_uniform(gl, "scene_emissive", TextureUnit(3));
+            integration_program.set_uniform(gl, "cascade_index", cascade_index as f32);
+            integration_program.set_uniform(gl, "ambient", self.ambient_level);
 
             self.cascades
                 .bind_cascade_as_texture(gl, cascade_index, TEXTURE0);
@@ -354,7 +351,7 @@ impl RadianceCascades {
             gl.viewport(0, 0, screen_resolution.x as _, screen_resolution.y as _);
             gl.clear(COLOR_BUFFER_BIT);
             self.quad_renderer
-                .draw_screen_quad(gl, self.integration_program);
+                .draw_screen_quad(gl, integration_program.id());
         }
     }
 
@@ -364,22 +361,24 @@ impl RadianceCascades {
         screen_resolution: Vec2,
         scene: &SceneFBO,
         voxelizer: &Voxelizer,
+        profiler: &GpuProfiler,
     ) {
-        self.calculate_cascades(gl, screen_resolution, scene, voxelizer);
+        self.reload_programs(gl);
+        {
+            let _scope = profiler.scope(gl, "Cascades");
+            self.calculate_cascades(gl, screen_resolution, scene, voxelizer, profiler);
+        }
 
         let cascade_width = self.constants.c0_resolution.x as i32;
         let cascade_height = self.constants.c0_resolution.y as i32;
         let screen_width = screen_resolution.x as i32;
         let screen_height = screen_resolution.y as i32;
         unsafe {
-            gl.bind_framebuffer(READ_FRAMEBUFFER, Some(self.cascades.fb));
-            gl.read_buffer(COLOR_ATTACHMENT0);
-            gl.framebuffer_texture(
+            gl.bind_framebuffer(
                 READ_FRAMEBUFFER,
-                COLOR_ATTACHMENT0,
-                Some(self.cascades.cascades[self.debug_cascade_index]),
-                0,
+                Some(self.cascades.framebuffer(self.debug_cascade_index)),
             );
+            gl.read_buffer(COLOR_ATTACHMENT0);
             gl.viewport(0, 0, screen_width, screen_height);
             gl.bind_framebuffer(DRAW_FRAMEBUFFER, None);
             gl.blit_framebuffer(
@@ -403,12 +402,51 @@ impl RadianceCascades {
         screen_resolution: Vec2,
         scene: &SceneFBO,
         voxelizer: &Voxelizer,
+        profiler: &GpuProfiler,
     ) {
-        self.calculate_cascades(gl, screen_resolution, scene, voxelizer);
+        self.reload_programs(gl);
+        {
+            let _scope = profiler.scope(gl, "Cascades");
+            self.calculate_cascades(gl, screen_resolution, scene, voxelizer, profiler);
+        }
+        let _scope = profiler.scope(gl, "Integration");
         self.integrate_radiance(gl, self.debug_cascade_index, screen_resolution, scene);
     }
 
+    /// Saves every cascade texture as a float image in the working directory
+    pub fn save_cascades(&self, gl: &Context) {
+        for cascade in 0..self.constants.cascade_count as usize {
+            let result = ReadbackImage::from_framebuffer(
+                gl,
+                Some(self.cascades.framebuffer(cascade)),
+                COLOR_ATTACHMENT0,
+                self.constants.c0_resolution.x as _,
+                self.constants.c0_resolution.y as _,
+            )
+            .and_then(|image| image.save(format!("cascade_{cascade}.exr")));
+            if let Err(error) = result {
+                eprintln!("{error}");
+            }
+        }
+    }
+
+    /// Reallocates the cascades to match a new screen resolution.
+    pub fn resize(&mut self, gl: &Context, screen_resolution: Vec2) {
+        self.constants.c0_resolution =
+            c0_resolution(screen_resolution, self.constants.c0_probe_spacing);
+        let cascades = CascadeFBO::new(
+            gl,
+            self.constants.c0_resolution,
+            self.constants.cascade_count as _,
+        );
+        std::mem::replace(&mut self.cascades, cascades).delete(gl);
+        self.constants.upload_to_buffer(gl, self.constants_ssbo);
+    }
+
     pub fn ui(&mut self, gl: &Context, ui: &imgui::Ui) {
+        self.cascade_program.error_window(ui, "rc.frag");
+        self.integration_program.error_window(ui, "integrate.frag");
+
         let mut constants_changed = false;
         let mut ao = self.constants.ambient_occlusion != 0.0;
         if ui.tree_node("Radiance cascades").is_some() {
@@ -444,6 +482,29 @@ impl RadianceCascades {
                 || ui.slider("Normal offset", 0.0, 1.0, &mut self.constants.normal_offset);
 
             ui.slider("Ambient level", 0.0, 1.0, &mut self.ambient_level);
+
+            let mut permutation_changed = false;
+            if let Some(cb) = ui.begin_combo("Trace method", self.trace_method.to_string()) {
+                for cur in TraceMethod::VARIANTS {
+                    if &self.trace_method == cur {
+                        ui.set_item_default_focus();
+                    }
+                    let clicked = ui
+                        .selectable_config(cur.to_string())
+                        .selected(&self.trace_method == cur)
+                        .build();
+                    if clicked && &self.trace_method != cur {
+                        self.trace_method = *cur;
+                        permutation_changed = true;
+                    }
+                }
+                cb.end();
+            }
+            permutation_changed =
+                ui.checkbox("Debug intervals", &mut self.debug_intervals) || permutation_changed;
+            if permutation_changed {
+                self.update_cascade_program(gl);
+            }
         }
 
         self.constants.ambient_occlusion = ao as i32 as f32;
@@ -1,10 +1,14 @@
 #version 450
 
-in vec4 position;
-in vec2 v_tex_coord;
-in vec3 v_normal;
-in vec3 v_tangent;
-in vec3 v_bitangent;
+layout(location = 0) in vec4 position;
+layout(location = 1) in vec3 v_normal;
+layout(location = 2) in vec2 v_tex_coord;
+layout(location = 3) in vec3 v_tangent;
+layout(location = 4) in vec3 v_bitangent;
+#ifdef BATCHED
+layout(location = 5) in uint v_material_index;
+flat out uint material_index;
+#endif
 out vec2 tex_coord;
 out vec4 albedo;
 //out vec4 emissive;
@@ -25,4 +29,7 @@ void main() {
     normal = mat3(model_to_world) * v_normal;
     tangent = mat3(model_to_world) * v_tangent;
     bitangent = mat3(model_to_world) * v_bitangent;
+#ifdef BATCHED
+    material_index = v_material_index;
+#endif
 }
\ No newline at end of file
@@ -1,19 +1,21 @@
 use microglut::{
+    delete_object,
     glam::{Mat4, Vec2, Vec3, Vec4},
     glow::{
-        Context, HasContext, NativeBuffer, NativeFramebuffer, NativeProgram, NativeTexture,
-        NativeVertexArray, ARRAY_BUFFER, BLEND, CLAMP_TO_EDGE, COLOR_ATTACHMENT0, COLOR_BUFFER_BIT,
+        Context, HasContext, NativeBuffer, NativeFramebuffer, NativeProgram, NativeRenderbuffer,
+        NativeTexture, NativeVertexArray, ARRAY_BUFFER, BLEND, COLOR_ATTACHMENT0, COLOR_BUFFER_BIT,
         CULL_FACE, DEPTH_ATTACHMENT, DEPTH_BUFFER_BIT, DEPTH_COMPONENT16, DEPTH_TEST,
-        ELEMENT_ARRAY_BUFFER, FLOAT, FRAMEBUFFER, LINEAR, NEAREST, READ_ONLY, RENDERBUFFER, RGBA,
-        RGBA16, RGBA16F, RGBA8, STATIC_DRAW, TEXTURE_2D_MULTISAMPLE, TEXTURE_3D,
-        TEXTURE_MAG_FILTER, TEXTURE_MIN_FILTER, TEXTURE_WRAP_R, TEXTURE_WRAP_S, TEXTURE_WRAP_T,
-        TRIANGLES, UNSIGNED_BYTE, UNSIGNED_INT, WRITE_ONLY,
+        ELEMENT_ARRAY_BUFFER, FLOAT, FRAMEBUFFER, LINEAR, NEAREST, READ_ONLY, RENDERBUFFER,
+        RGBA16F, RGBA8, STATIC_DRAW, TEXTURE_2D_MULTISAMPLE, TRIANGLES, UNSIGNED_INT, WRITE_ONLY,
     },
-    imgui, LoadShaders, MaterialBindings,
+    imgui, label_object, track_object, Aabb, LoadShaders, MaterialBindings, Program, Texture3D,
 };
 use strum::{Display, VariantArray};
 
-use crate::{camera::Camera, object::Object, quad_renderer::QuadRenderer};
+use crate::{
+    camera::Camera, object::Object, quad_renderer::QuadRenderer, MATERIAL_TABLE_BINDING,
+    SHADER_INCLUDES,
+};
 
 #[derive(Display, VariantArray, PartialEq, Copy, Clone)]
 enum VisualizationMode {
@@ -25,13 +27,14 @@ pub struct Voxelizer {
     origin: Vec3,
     volume_side_lengths: Vec3,
 
-    voxel_texture: NativeTexture,
-    voxelizer_program: NativeProgram,
-    instanced_visualizing_program: NativeProgram,
-    clear_program: NativeProgram,
+    voxel_texture: Texture3D,
+    voxelizer_program: Program,
+    batched_voxelizer_program: Program,
+    instanced_visualizing_program: Program,
+    clear_program: Program,
     cube_renderer: CubeRenderer,
 
-    tracer_program: NativeProgram,
+    tracer_program: Program,
     tracer_step_length: f32,
     tracer_step_count: f32,
 
@@ -41,56 +44,67 @@ pub struct Voxelizer {
 
     // An MSAA render target is needed for an approximation of conservative rasterization
     msaa_fbo: NativeFramebuffer,
+    msaa_texture: NativeTexture,
+    msaa_renderbuffer: NativeRenderbuffer,
 }
 
 impl Voxelizer {
-    pub fn new(gl: &Context, resolution: Vec3, origin: Vec3, volume_side_lengths: Vec3) -> Self {
+    /// Creates a voxelizer whose volume is fitted to `volume`, see [Voxelizer::fit_to].
+    pub fn new(gl: &Context, resolution: Vec3, volume: Aabb) -> Self {
         unsafe {
-            let voxelizer_program =
+            let voxelizer_shaders =
                 LoadShaders::new(include_str!("voxelize.vert"), include_str!("voxelize.frag"))
                     .geometry(include_str!("voxelize.geom"))
-                    .compile(gl);
+                    .includes(SHADER_INCLUDES);
+            let voxelizer_program = Program::compile(gl, &voxelizer_shaders).unwrap();
+            let batched_voxelizer_program =
+                Program::compile(gl, &voxelizer_shaders.define("BATCHED", 1)).unwrap();
 
-            let visualizing_program = LoadShaders::new(
-                include_str!("../vertex.glsl"),
-                include_str!("trace_voxels.frag"),
+            let visualizing_program = Program::compile(
+                gl,
+                &LoadShaders::new(
+                    include_str!("../vertex.glsl"),
+                    include_str!("trace_voxels.frag"),
+                ),
             )
-            .compile(gl);
-            let instanced_visualizing_program = LoadShaders::new(
-                include_str!("voxel_instanced.vert"),
-                include_str!("voxel_instanced.frag"),
+            .unwrap();
+            let instanced_visualizing_program = Program::compile(
+                gl,
+                &LoadShaders::new(
+                    include_str!("voxel_instanced.vert"),
+                    include_str!("voxel_instanced.frag"),
+                ),
             )
-            .compile(gl);
-
-            let clear_program =
-                LoadShaders::new(include_str!("clear.vert"), include_str!("clear.frag"))
-                    .compile(gl);
-
-            let voxel_texture = gl.create_texture().unwrap();
-            gl.bind_texture(TEXTURE_3D, Some(voxel_texture));
-            gl.tex_parameter_i32(TEXTURE_3D, TEXTURE_WRAP_R, CLAMP_TO_EDGE as _);
-            gl.tex_parameter_i32(TEXTURE_3D, TEXTURE_WRAP_S, CLAMP_TO_EDGE as _);
-            gl.tex_parameter_i32(TEXTURE_3D, TEXTURE_WRAP_T, CLAMP_TO_EDGE as _);
-            gl.tex_parameter_i32(TEXTURE_3D, TEXTURE_MAG_FILTER, NEAREST as _);
-            gl.tex_parameter_i32(TEXTURE_3D, TEXTURE_MIN_FILTER, LINEAR as _);
-            gl.tex_image_3d(
-                TEXTURE_3D,
-                0,
-                RGBA16F as _,
+            .unwrap();
+
+            let clear_program = Program::compile(
+                gl,
+                &LoadShaders::new(include_str!("clear.vert"), include_str!("clear.frag")),
+            )
+            .unwrap();
+
+            let voxel_texture = Texture3D::new(
+                gl,
+                RGBA16F,
                 resolution.x as _,
                 resolution.y as _,
                 resolution.z as _,
-                0,
-                RGBA,
-                UNSIGNED_BYTE,
-                None,
-            );
-            gl.bind_texture(TEXTURE_3D, None);
+                false,
+            )
+            .unwrap();
+            voxel_texture.set_filter(gl, LINEAR, NEAREST);
+
+            voxelizer_program.label(gl, "Voxelize");
+            batched_voxelizer_program.label(gl, "Voxelize (batched)");
+            visualizing_program.label(gl, "Trace voxels");
+            instanced_visualizing_program.label(gl, "Instanced voxels");
+            clear_program.label(gl, "Clear voxels");
+            label_object(gl, voxel_texture.id(), "Voxels");
 
-            let msaa_fbo = gl.create_framebuffer().unwrap();
+            let msaa_fbo = track_object(gl.create_framebuffer().unwrap());
             gl.bind_framebuffer(FRAMEBUFFER, Some(msaa_fbo));
 
-            let msaa_tex = gl.create_texture().unwrap();
+            let msaa_tex = track_object(gl.create_texture().unwrap());
             gl.bind_texture(TEXTURE_2D_MULTISAMPLE, Some(msaa_tex));
             gl.tex_storage_2d_multisample(
                 TEXTURE_2D_MULTISAMPLE,
@@ -103,7 +117,7 @@ impl Voxelizer {
             gl.framebuffer_texture(FRAMEBUFFER, COLOR_ATTACHMENT0, Some(msaa_tex), 0);
             gl.bind_texture(TEXTURE_2D_MULTISAMPLE, None);
 
-            let msaa_rb = gl.create_renderbuffer().unwrap();
+            let msaa_rb = track_object(gl.create_renderbuffer().unwrap());
             gl.bind_renderbuffer(RENDERBUFFER, Some(msaa_rb));
             gl.renderbuffer_storage_multisample(
                 RENDERBUFFER,
@@ -116,19 +130,26 @@ impl Voxelizer {
             gl.bind_renderbuffer(RENDERBUFFER, None);
 
             gl.bind_framebuffer(FRAMEBUFFER, None);
+            label_object(gl, msaa_fbo, "Voxelization MSAA");
+            label_object(gl, msaa_tex, "Voxelization MSAA color");
+            label_object(gl, msaa_rb, "Voxelization MSAA depth");
 
             let cube_renderer = CubeRenderer::new(gl);
 
+            let (origin, volume_side_lengths) = fitted_volume(volume);
             Self {
                 resolution,
                 origin,
                 volume_side_lengths,
                 voxel_texture,
                 voxelizer_program,
+                batched_voxelizer_program,
                 tracer_program: visualizing_program,
                 instanced_visualizing_program,
                 clear_program,
                 msaa_fbo,
+                msaa_texture: msaa_tex,
+                msaa_renderbuffer: msaa_rb,
                 cube_renderer,
                 visualisation_mode: VisualizationMode::Instanced,
                 use_msaa: true,
@@ -138,6 +159,19 @@ impl Voxelizer {
         }
     }
 
+    pub fn delete(self, gl: &Context) {
+        self.voxel_texture.delete(gl);
+        self.voxelizer_program.delete(gl);
+        self.batched_voxelizer_program.delete(gl);
+        self.instanced_visualizing_program.delete(gl);
+        self.clear_program.delete(gl);
+        self.tracer_program.delete(gl);
+        self.cube_renderer.delete(gl);
+        delete_object(gl, self.msaa_fbo);
+        delete_object(gl, self.msaa_texture);
+        delete_object(gl, self.msaa_renderbuffer);
+    }
+
     pub fn resolution(&self) -> Vec3 {
         self.resolution
     }
@@ -164,8 +198,8 @@ impl Voxelizer {
         world_to_voxel
     }
 
-    pub fn voxel_texture(&self) -> NativeTexture {
-        self.voxel_texture
+    pub fn voxel_texture(&self) -> &Texture3D {
+        &self.voxel_texture
     }
 
     pub fn step_length(&self) -> f32 {
@@ -178,36 +212,42 @@ impl Voxelizer {
 
     pub fn clear_voxels(&self, gl: &Context, quad_renderer: &QuadRenderer, clear_color: Vec4) {
         unsafe {
-            gl.use_program(Some(self.clear_program));
+            self.clear_program.use_program(gl);
             gl.viewport(0, 0, self.resolution.x as _, self.resolution.y as _);
 
             gl.bind_framebuffer(FRAMEBUFFER, None);
             gl.color_mask(false, false, false, false);
-            gl.uniform_4_f32_slice(
-                gl.get_uniform_location(self.clear_program, "clear_color")
-                    .as_ref(),
-                clear_color.as_ref(),
-            );
-            gl.uniform_3_i32_slice(
-                gl.get_uniform_location(self.clear_program, "voxel_resolution")
-                    .as_ref(),
-                self.resolution.as_ivec3().as_ref(),
-            );
+            self.clear_program
+                .set_uniform(gl, "clear_color", clear_color);
+            self.clear_program
+                .set_uniform(gl, "voxel_resolution", self.resolution.as_ivec3());
 
-            gl.bind_image_texture(0, self.voxel_texture, 0, false, 0, WRITE_ONLY, RGBA16F);
+            self.voxel_texture.bind_image(gl, 0, 0, WRITE_ONLY);
             gl.disable(CULL_FACE);
             gl.disable(DEPTH_TEST);
             gl.disable(BLEND);
             gl.clear(COLOR_BUFFER_BIT | DEPTH_BUFFER_BIT);
-            quad_renderer.draw_screen_quad(gl, self.clear_program);
+            quad_renderer.draw_screen_quad(gl, self.clear_program.id());
             gl.enable(CULL_FACE);
             gl.color_mask(true, true, true, true);
         }
     }
 
-    pub fn voxelize(&self, gl: &Context, objects: &Vec<Object>) {
+    /// Voxelizes `objects`, drawing their batched models if `batched` is set and they have one.
+    pub fn voxelize(
+        &self,
+        gl: &Context,
+        objects: &Vec<Object>,
+        material_bindings: &MaterialBindings,
+        batched: bool,
+    ) {
         unsafe {
-            gl.use_program(Some(self.voxelizer_program));
+            let program = if batched {
+                &self.batched_voxelizer_program
+            } else {
+                &self.voxelizer_program
+            };
+            program.use_program(gl);
             if self.use_msaa {
                 gl.bind_framebuffer(FRAMEBUFFER, Some(self.msaa_fbo));
             }
@@ -242,31 +282,12 @@ impl Voxelizer {
             let projection_y = p_y * Mat4::look_to_rh(self.origin, Vec3::Y, Vec3::Z);
             let projection_z = projection * Mat4::look_to_rh(self.origin, Vec3::NEG_Z, Vec3::Y);
 
-            gl.uniform_matrix_4_f32_slice(
-                gl.get_uniform_location(self.voxelizer_program, "projection_x")
-                    .as_ref(),
-                false,
-                projection_x.as_ref(),
-            );
-            gl.uniform_matrix_4_f32_slice(
-                gl.get_uniform_location(self.voxelizer_program, "projection_y")
-                    .as_ref(),
-                false,
-                projection_y.as_ref(),
-            );
-            gl.uniform_matrix_4_f32_slice(
-                gl.get_uniform_location(self.voxelizer_program, "projection_z")
-                    .as_ref(),
-                false,
-                projection_z.as_ref(),
-            );
-            gl.uniform_3_i32_slice(
-                gl.get_uniform_location(self.voxelizer_program, "voxel_resolution")
-                    .as_ref(),
-                self.resolution.as_ivec3().as_ref(),
-            );
+            program.set_uniform(gl, "projection_x", projection_x);
+            program.set_uniform(gl, "projection_y", projection_y);
+            program.set_uniform(gl, "projection_z", projection_z);
+            program.set_uniform(gl, "voxel_resolution", self.resolution.as_ivec3());
 
-            gl.bind_image_texture(0, self.voxel_texture, 0, false, 0, WRITE_ONLY, RGBA16F);
+            self.voxel_texture.bind_image(gl, 0, 0, WRITE_ONLY);
 
             gl.disable(CULL_FACE);
             gl.disable(DEPTH_TEST);
@@ -275,49 +296,17 @@ impl Voxelizer {
             gl.color_mask(false, false, false, false);
             gl.clear(COLOR_BUFFER_BIT | DEPTH_BUFFER_BIT);
 
-            let material_bindings = MaterialBindings {
-                ambient: None,
-                emissive: Some(String::from("emissive")),
-                diffuse: Some(String::from("diffuse")),
-                specular: Some(String::from("specular")),
-                shininess: None,
-                dissolve: Some(String::from("opacity")),
-                optical_density: None,
-                ambient_texture: None,
-                diffuse_texture: Some((String::from("diffuse_tex"), 0)),
-                specular_texture: Some((String::from("specular_tex"), 1)),
-                normal_texture: Some((String::from("normal_tex"), 2)),
-                shininess_texture: None,
-                dissolve_texture: Some((String::from("opacity_tex"), 3)),
-                illumination_model: None,
-            };
             for obj in objects {
-                gl.uniform_matrix_4_f32_slice(
-                    gl.get_uniform_location(self.voxelizer_program, "model_to_world")
-                        .as_ref(),
-                    false,
-                    obj.get_transformation().as_ref(),
-                );
-                gl.uniform_4_f32_slice(
-                    gl.get_uniform_location(self.voxelizer_program, "albedo")
-                        .as_ref(),
-                    obj.albedo.as_ref(),
-                );
-                gl.uniform_4_f32_slice(
-                    gl.get_uniform_location(self.voxelizer_program, "emissive")
-                        .as_ref(),
-                    obj.emissive.as_ref(),
-                );
-                obj.model.draw(
-                    gl,
-                    self.voxelizer_program,
-                    "position",
-                    Some("normal"),
-                    Some("tex_coord"),
-                    None,
-                    None,
-                    Some(&material_bindings),
-                );
+                program.set_uniform(gl, "model_to_world", obj.get_transformation());
+                program.set_uniform(gl, "albedo", obj.albedo);
+                program.set_uniform(gl, "emissive", obj.emissive);
+                match &obj.batch {
+                    Some(batch) if batched => {
+                        batch.bind_materials(gl, program, MATERIAL_TABLE_BINDING, 0);
+                        batch.draw(gl);
+                    }
+                    _ => obj.model.draw(gl, program, Some(material_bindings)),
+                }
             }
 
             gl.bind_framebuffer(FRAMEBUFFER, None);
@@ -369,84 +358,41 @@ impl Voxelizer {
             * projection_z;
 
         unsafe {
-            gl.use_program(Some(self.tracer_program));
+            let program = &self.tracer_program;
+            program.use_program(gl);
             gl.bind_framebuffer(FRAMEBUFFER, None);
             gl.viewport(0, 0, screen_resolution.x as _, screen_resolution.y as _);
             gl.clear(COLOR_BUFFER_BIT);
 
-            gl.uniform_3_f32_slice(
-                gl.get_uniform_location(self.tracer_program, "cam_pos")
-                    .as_ref(),
-                camera.position.as_ref(),
-            );
-            gl.uniform_3_f32_slice(
-                gl.get_uniform_location(self.tracer_program, "pixel_down_left")
-                    .as_ref(),
-                pixel_down_left.as_ref(),
-            );
-            gl.uniform_3_f32_slice(
-                gl.get_uniform_location(self.tracer_program, "pixel_delta_u")
-                    .as_ref(),
-                pixel_delta_u.as_ref(),
-            );
-            gl.uniform_3_f32_slice(
-                gl.get_uniform_location(self.tracer_program, "pixel_delta_v")
-                    .as_ref(),
-                pixel_delta_v.as_ref(),
-            );
-            gl.uniform_matrix_4_f32_slice(
-                gl.get_uniform_location(self.tracer_program, "world_to_voxel")
-                    .as_ref(),
-                false,
-                world_to_voxel.as_ref(),
-            );
-            gl.uniform_1_f32(
-                gl.get_uniform_location(self.tracer_program, "step_length")
-                    .as_ref(),
-                self.tracer_step_length,
-            );
-            gl.uniform_1_f32(
-                gl.get_uniform_location(self.tracer_program, "step_count")
-                    .as_ref(),
-                self.tracer_step_count,
-            );
-            gl.bind_image_texture(0, self.voxel_texture, 0, false, 0, READ_ONLY, RGBA16);
+            program.set_uniform(gl, "cam_pos", camera.position);
+            program.set_uniform(gl, "pixel_down_left", pixel_down_left);
+            program.set_uniform(gl, "pixel_delta_u", pixel_delta_u);
+            program.set_uniform(gl, "pixel_delta_v", pixel_delta_v);
+            program.set_uniform(gl, "world_to_voxel", world_to_voxel);
+            program.set_uniform(gl, "step_length", self.tracer_step_length);
+            program.set_uniform(gl, "step_count", self.tracer_step_count);
+            self.voxel_texture.bind_image(gl, 0, 0, READ_ONLY);
 
             gl.enable(BLEND);
-            renderer.draw_screen_quad(gl, self.tracer_program);
+            renderer.draw_screen_quad(gl, program.id());
             gl.disable(BLEND);
         }
     }
 
     pub fn visualize_instanced(&self, gl: &Context, camera: &Camera, screen_resolution: Vec2) {
         unsafe {
-            gl.use_program(Some(self.instanced_visualizing_program));
+            let program = &self.instanced_visualizing_program;
+            program.use_program(gl);
             gl.bind_framebuffer(FRAMEBUFFER, None);
             gl.viewport(0, 0, screen_resolution.x as _, screen_resolution.y as _);
 
             let w_t_v =
                 camera.view_transform() * Mat4::look_to_rh(-self.origin, Vec3::NEG_Z, Vec3::Y);
-            gl.uniform_matrix_4_f32_slice(
-                gl.get_uniform_location(self.instanced_visualizing_program, "world_to_view")
-                    .as_ref(),
-                false,
-                w_t_v.as_ref(),
-            );
-            gl.uniform_matrix_4_f32_slice(
-                gl.get_uniform_location(self.instanced_visualizing_program, "projection")
-                    .as_ref(),
-                false,
-                camera.perspective_transform().as_ref(),
-            );
-            gl.uniform_3_i32(
-                gl.get_uniform_location(self.instanced_visualizing_program, "voxel_resolution")
-                    .as_ref(),
-                self.resolution.x as _,
-                self.resolution.y as _,
-                self.resolution.z as _,
-            );
+            program.set_uniform(gl, "world_to_view", w_t_v);
+            program.set_uniform(gl, "projection", camera.perspective_transform());
+            program.set_uniform(gl, "voxel_resolution", self.resolution.as_ivec3());
 
-            gl.bind_image_texture(0, self.voxel_texture, 0, false, 0, READ_ONLY, RGBA16F);
+            self.voxel_texture.bind_image(gl, 0, 0, READ_ONLY);
 
             gl.clear(COLOR_BUFFER_BIT | DEPTH_BUFFER_BIT);
             gl.enable(CULL_FACE);
@@ -454,7 +400,7 @@ impl Voxelizer {
             gl.enable(BLEND);
             self.cube_renderer.draw_instanced(
                 gl,
-                self.instanced_visualizing_program,
+                program.id(),
                 (self.resolution.x * self.resolution.y * self.resolution.z) as _,
             );
             gl.disable(DEPTH_TEST);
@@ -477,6 +423,11 @@ impl Voxelizer {
         }
     }
 
+    /// Centres the voxel volume on `aabb` and makes it large enough to contain it.
+    pub fn fit_to(&mut self, aabb: Aabb) {
+        (self.origin, self.volume_side_lengths) = fitted_volume(aabb);
+    }
+
     pub fn ui(&mut self, ui: &mut imgui::Ui) {
         if ui.tree_node("Voxelisation").is_some() {
             ui.separator_with_text("Voxelisation parameters");
@@ -514,7 +465,6 @@ impl Voxelizer {
 struct CubeRenderer {
     vao: NativeVertexArray,
     vbo: NativeBuffer,
-    #[expect(unused)]
     ebo: NativeBuffer,
 }
 
@@ -537,14 +487,14 @@ impl CubeRenderer {
         ];
 
         unsafe {
-            let vao = gl.create_vertex_array().unwrap();
+            let vao = track_object(gl.create_vertex_array().unwrap());
             gl.bind_vertex_array(Some(vao));
 
-            let vbo = gl.create_buffer().unwrap();
+            let vbo = track_object(gl.create_buffer().unwrap());
             gl.bind_buffer(ARRAY_BUFFER, Some(vbo));
             gl.buffer_data_u8_slice(ARRAY_BUFFER, bytemuck::cast_slice(&vertices), STATIC_DRAW);
 
-            let ebo = gl.create_buffer().unwrap();
+            let ebo = track_object(gl.create_buffer().unwrap());
             gl.bind_buffer(ELEMENT_ARRAY_BUFFER, Some(ebo));
             gl.buffer_data_u8_slice(
                 ELEMENT_ARRAY_BUFFER,
@@ -555,6 +505,12 @@ impl CubeRenderer {
         }
     }
 
+    fn delete(self, gl: &Context) {
+        delete_object(gl, self.vao);
+        delete_object(gl, self.vbo);
+        delete_object(gl, self.ebo);
+    }
+
     fn draw_instanced(&self, gl: &Context, program: NativeProgram, count: i32) {
         unsafe {
             gl.bind_vertex_array(Some(self.vao));
@@ -568,3 +524,12 @@ impl CubeRenderer {
         }
     }
 }
+
+/// Origin and side lengths of a voxel volume around `aabb`, with a margin so that geometry
+/// on the boundary is not clipped.
+fn fitted_volume(aabb: Aabb) -> (Vec3, Vec3) {
+    if aabb.is_empty() {
+        return (Vec3::ZERO, Vec3::ONE);
+    }
+    (aabb.center(), (aabb.size() * 1.02).max(Vec3::splat(0.01)))
+}
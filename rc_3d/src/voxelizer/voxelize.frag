@@ -11,41 +11,25 @@ layout(binding = 0, rgba16f) uniform writeonly image3D voxel_tex;
 
 uniform ivec3 voxel_resolution;
 
-uniform vec3 emissive;
-uniform int has_emissive;
-uniform vec3 diffuse;
-uniform int has_diffuse;
-uniform vec3 specular;
-uniform int has_specular;
-uniform float opacity;
-uniform int has_opacity;
-
-uniform sampler2D diffuse_tex;
-uniform int has_diffuse_tex;
-uniform sampler2D specular_tex;
-uniform int has_specular_tex;
-uniform sampler2D normal_tex;
-uniform int has_normal_tex;
-uniform sampler2D opacity_tex;
-uniform int has_opacity_tex;
+#include "scene_material.glsl"
 
 out vec4 color;
 
 void main() {
     vec2 adjusted_tex_coord = vec2(frag_tex_coord.x, 1.0 - frag_tex_coord.y);
 
-    vec4 diffuse_color = vec4(0.0);
-    if (has_opacity_tex == 1 && texture(opacity_tex, adjusted_tex_coord).r < 0.1) {
+    vec4 albedo = vec4(0.0);
+    if (has_opacity_texture() && sample_opacity(adjusted_tex_coord) < 0.1) {
         discard;
     }
-    if (has_diffuse_tex == 1) {
-        diffuse_color = texture(diffuse_tex, adjusted_tex_coord);
+    if (has_diffuse_texture()) {
+        albedo = sample_diffuse(adjusted_tex_coord);
     }
-    else if (has_diffuse == 1) {
-        diffuse_color = vec4(diffuse, 1.0);
+    else if (has_diffuse_color()) {
+        albedo = vec4(diffuse_color(), 1.0);
     }
     else {
-        diffuse_color = frag_albedo;
+        albedo = frag_albedo;
     }
 
     ivec3 voxel_pos = ivec3(gl_FragCoord.xy, (gl_FragCoord.z * 2.0 - 1.0) * voxel_resolution.z);
@@ -69,6 +53,6 @@ void main() {
 
     // TODO: Store normals in a separate 3D texture
     // TODO: Store through atomic averaging as several fragments may belong to the same voxel
-    //imageStore(voxel_tex, voxel_pos, diffuse_color);
-    imageStore(voxel_tex, voxel_pos, vec4(emissive, 1.0));
+    //imageStore(voxel_tex, voxel_pos, albedo);
+    imageStore(voxel_tex, voxel_pos, vec4(emissive_color(), 1.0));
 }
\ No newline at end of file
@@ -1,8 +1,12 @@
 #version 450
 
-in vec3 position;
-in vec3 normal;
-in vec2 tex_coord;
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec2 tex_coord;
+#ifdef BATCHED
+layout(location = 5) in uint material_index;
+flat out uint geom_material_index;
+#endif
 
 uniform mat4 model_to_world;
 
@@ -21,5 +25,8 @@ void main() {
     geom_albedo = albedo;
     geom_emissive = emissive;
     geom_tex_coord = tex_coord;
+#ifdef BATCHED
+    geom_material_index = material_index;
+#endif
     gl_Position = vec4(geom_world_pos, 1.0);
 }